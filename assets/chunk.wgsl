@@ -2,6 +2,7 @@
 
 struct VertexInput {
     @location(0) position: u32,
+    @location(1) tile: u32,
     // @location(1) tex_coords: u32,
     // @location(2) ao: u32,
 }
@@ -11,6 +12,10 @@ struct VertexOutput {
     @location(0) tex_coords: vec2<f32>,
     @location(1) ao: f32,
     @location(2) distance: f32,
+    // for tiled quads tex_coords is in blocks, and this is the corner of the texture
+    @location(3) @interpolate(flat) tile_origin: vec2<f32>,
+    @location(4) @interpolate(flat) tiled: u32,
+    @location(5) light: f32,
 }
 
 @group(0) @binding(0)
@@ -22,7 +27,7 @@ var<uniform> view: mat4x4<f32>;
 @group(2) @binding(0)
 var<uniform> model: mat4x4<f32>;
 
-fn unpack_vertex(in_vertex: u32) -> VertexOutput {
+fn unpack_vertex(in_vertex: u32, in_tile: u32) -> VertexOutput {
     
     var output: VertexOutput;
     
@@ -38,7 +43,21 @@ fn unpack_vertex(in_vertex: u32) -> VertexOutput {
         f32((in_vertex & 0x00000078u) >> u32(3)) / 16.0
     );
     
-    output.ao = f32((in_vertex & 0x00000007u) >> u32(0)) / 2.0;
+    output.ao = f32((in_vertex & 0x00000007u) >> u32(0)) / 6.0;
+
+    // every level of light darker than full light dims the block a bit more
+    let block_light = f32((in_tile & 0x0000F000u) >> u32(12));
+    let sky_light = f32((in_tile & 0x000F0000u) >> u32(16));
+    output.light = max(pow(0.8, 15.0 - max(sky_light, block_light)), 0.05);
+
+    output.tiled = (in_tile & 0x80000000u) >> u32(31);
+    output.tile_origin = output.tex_coords;
+    if output.tiled != 0u {
+        output.tex_coords = vec2<f32>(
+            f32((in_tile & 0x00000FC0u) >> u32(6)),
+            f32((in_tile & 0x0000003Fu) >> u32(0))
+        );
+    }
     
     output.distance = 1.0;
     
@@ -49,7 +68,7 @@ fn unpack_vertex(in_vertex: u32) -> VertexOutput {
 fn vs_main(
     input: VertexInput,
 ) -> VertexOutput {
-    var out: VertexOutput = unpack_vertex(input.position);
+    var out: VertexOutput = unpack_vertex(input.position, input.tile);
     
     out.clip_position = projection * view * model * out.clip_position;
     out.distance = length(out.clip_position);
@@ -64,8 +83,8 @@ var t_diffuse: texture_2d<f32>;
 @group(3)@binding(1)
 var s_diffuse: sampler;
 
-@fragment
-fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+// the texel of the block's texture, shaded and faded into the fog
+fn shade(in: VertexOutput) -> vec4<f32> {
     // we manually set the fog color and fog start/end at this point, might be better to pass in as 
     var distance = in.distance;
     if distance < 128.0 {
@@ -73,6 +92,31 @@ fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
     } else {
         distance = distance - 128.0;
     }
-    return mix(in.ao * textureSample(t_diffuse, s_diffuse, in.tex_coords), vec4<f32>(0.1, 0.2, 0.3, 1.0), min(distance / 32.0, 1.0));
+    // repeat the texture once per block across tiled quads
+    let tex_coords = select(in.tex_coords, in.tile_origin + fract(in.tex_coords) / 16.0, in.tiled != 0u);
+    let texel = textureSample(t_diffuse, s_diffuse, tex_coords);
+    let color = mix(in.ao * in.light * texel.rgb, vec3<f32>(0.1, 0.2, 0.3), min(distance / 32.0, 1.0));
+    return vec4<f32>(color, texel.a);
     // return in.ao * textureSample(t_diffuse, s_diffuse, in.tex_coords);
 }
+
+@fragment
+fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    return vec4<f32>(shade(in).rgb, 1.0);
+}
+
+// blocks with holes in their texture, the see through texels are thrown away
+@fragment
+fn fs_cutout(in: VertexOutput) -> @location(0) vec4<f32> {
+    let color = shade(in);
+    if color.a < 0.5 {
+        discard;
+    }
+    return vec4<f32>(color.rgb, 1.0);
+}
+
+// blocks blended with what is behind them by their texture's alpha
+@fragment
+fn fs_translucent(in: VertexOutput) -> @location(0) vec4<f32> {
+    return shade(in);
+}
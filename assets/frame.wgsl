@@ -38,7 +38,29 @@ var t_diffuse: texture_2d<f32>;
 @group(0)@binding(1)
 var s_diffuse: sampler;
 
+// Upscale filters, picked by the fragment entry point
+
+// the closest texel, every texel is drawn as a block of pixels
 @fragment
 fn fs_main(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<i32>(textureDimensions(t_diffuse));
+    let texel = clamp(vec2<i32>(in.tex_coords * vec2<f32>(size)), vec2<i32>(0), size - 1);
+    return textureLoad(t_diffuse, texel, 0);
+}
+
+// the 4 closest texels blended together
+@fragment
+fn fs_bilinear(in: VertexOutput) -> @location(0) vec4<f32> {
     return textureSample(t_diffuse, s_diffuse, in.tex_coords);
 }
+
+// the closest texel, blended with its neighbours only on the pixels where
+// two texels meet, so fractional scales don't give texels different widths
+@fragment
+fn fs_sharp(in: VertexOutput) -> @location(0) vec4<f32> {
+    let size = vec2<f32>(textureDimensions(t_diffuse));
+    let texel = in.tex_coords * size;
+    let seam = floor(texel + 0.5);
+    let offset = clamp((texel - seam) / fwidth(texel), vec2<f32>(-0.5), vec2<f32>(0.5));
+    return textureSample(t_diffuse, s_diffuse, (seam + offset) / size);
+}
@@ -0,0 +1,17 @@
+use clap::Parser;
+use mcrs::{cli::WorldGenCli, worldgen::generate_region};
+
+fn main() {
+    env_logger::init();
+
+    let report = WorldGenCli::parse()
+        .options()
+        .and_then(|options| generate_region(&options));
+    match report {
+        Ok(report) => println!("{}", report),
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            std::process::exit(1);
+        }
+    }
+}
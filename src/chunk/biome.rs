@@ -0,0 +1,261 @@
+//! Biomes, picked by the temperature and humidity of each column of the world.
+//!
+//! Every biome sits at a point in temperature and humidity, and a column
+//! belongs to the biome nearest to its climate. Biomes almost as near are
+//! blended into the shape of the terrain, so there are no cliffs at borders.
+
+use libnoise::prelude::*;
+use serde::Deserialize;
+
+use super::{ChunkConfig, Position};
+
+/// How fast temperature and humidity change across the world, lower makes
+/// larger biomes.
+const CLIMATE_SCALE: f64 = 0.001;
+/// How much nearer to a column's climate the nearest biome has to be than
+/// another biome for the other biome not to be blended in.
+const BLEND_DISTANCE: f64 = 0.2;
+
+#[derive(Clone, Copy, Debug, Deserialize, PartialEq, Eq, Hash)]
+pub enum Biome {
+    Plains,
+    Desert,
+    Mountains,
+    Tundra,
+    Ocean,
+}
+
+/// How a biome shapes the terrain and which blocks cover it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct BiomeParams {
+    /// Height the ground is around, in blocks.
+    pub height: f64,
+    /// Blocks of height the terrain noise is spread over, higher is hillier.
+    pub squish: f64,
+    /// Ident of the block at the top of the ground.
+    pub surface: &'static str,
+    /// Ident of the block between the surface and the stone below.
+    pub subsurface: &'static str,
+}
+
+impl Biome {
+    pub const ALL: [Biome; 5] = [
+        Biome::Plains,
+        Biome::Desert,
+        Biome::Mountains,
+        Biome::Tundra,
+        Biome::Ocean,
+    ];
+
+    pub fn params(&self) -> BiomeParams {
+        let (height, squish, surface, subsurface) = match self {
+            Biome::Plains => (8.0, 32.0, "grass", "dirt"),
+            Biome::Desert => (10.0, 40.0, "sand", "sand"),
+            Biome::Mountains => (32.0, 96.0, "stone", "stone"),
+            Biome::Tundra => (12.0, 32.0, "snow", "dirt"),
+            Biome::Ocean => (-24.0, 24.0, "sand", "sand"),
+        };
+        BiomeParams {
+            height,
+            squish,
+            surface,
+            subsurface,
+        }
+    }
+
+    /// The temperature and humidity at the heart of the biome.
+    fn climate(&self) -> [f64; 2] {
+        match self {
+            Biome::Plains => [0.0, 0.0],
+            Biome::Desert => [0.5, -0.4],
+            Biome::Mountains => [-0.1, -0.5],
+            Biome::Tundra => [-0.5, 0.1],
+            Biome::Ocean => [0.1, 0.5],
+        }
+    }
+}
+
+/// Noise for the temperature and humidity of the world.
+#[derive(Clone)]
+pub struct Climate {
+    temperature: Simplex<2>,
+    humidity: Simplex<2>,
+}
+
+impl Climate {
+    /// Create the climate of a world, the seed is the world's seed.
+    pub fn new(seed: u64) -> Self {
+        Self {
+            temperature: Source::simplex(seed.wrapping_add(1)),
+            humidity: Source::simplex(seed.wrapping_add(2)),
+        }
+    }
+
+    /// Get the temperature and humidity of the column at world space x and z.
+    pub fn sample(&self, x: i32, z: i32) -> [f64; 2] {
+        let position = [x as f64 * CLIMATE_SCALE, z as f64 * CLIMATE_SCALE];
+        [
+            self.temperature.sample(position),
+            self.humidity.sample(position),
+        ]
+    }
+}
+
+fn climate_distance(a: [f64; 2], b: [f64; 2]) -> f64 {
+    ((a[0] - b[0]).powi(2) + (a[1] - b[1]).powi(2)).sqrt()
+}
+
+/// Get how much each biome of `Biome::ALL` shapes a column with the climate.
+/// The nearest biome weighs the most, the weights add up to 1.
+pub fn biome_weights(climate: [f64; 2]) -> [f64; 5] {
+    let distances = Biome::ALL.map(|biome| climate_distance(biome.climate(), climate));
+    let nearest = distances.iter().copied().fold(f64::INFINITY, f64::min);
+
+    let weights = distances.map(|distance| (BLEND_DISTANCE - (distance - nearest)).max(0.0));
+    let total: f64 = weights.iter().sum();
+    weights.map(|weight| weight / total)
+}
+
+/// Get the biome nearest to a climate.
+pub fn biome_for_climate(climate: [f64; 2]) -> Biome {
+    let weights = biome_weights(climate);
+    let mut biome = 0;
+    for (i, weight) in weights.iter().enumerate() {
+        if *weight > weights[biome] {
+            biome = i;
+        }
+    }
+    Biome::ALL[biome]
+}
+
+/// The biome of a column of the world and the shape of its terrain, blended
+/// with the biomes around it.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct TerrainColumn {
+    pub biome: Biome,
+    /// Height the ground is around, in blocks.
+    pub height: f64,
+    /// Blocks of height the terrain noise is spread over.
+    pub squish: f64,
+}
+
+/// Get the terrain column at world space x and z.
+pub fn terrain_column(config: &ChunkConfig, x: i32, z: i32) -> TerrainColumn {
+    let climate = config.climate.sample(x, z);
+    let weights = biome_weights(climate);
+
+    let mut height = 0.0;
+    let mut squish = 0.0;
+    for (biome, weight) in Biome::ALL.iter().zip(weights) {
+        let params = biome.params();
+        height += params.height * weight;
+        squish += params.squish * weight;
+    }
+
+    let settings = &config.settings;
+    TerrainColumn {
+        biome: biome_for_climate(climate),
+        height: height + settings.ground_level as f64,
+        squish: squish * settings.height_amplitude,
+    }
+}
+
+/// Get the biome at a world space position, only x and z matter.
+pub fn biome_at(config: &ChunkConfig, position: &Position) -> Biome {
+    biome_for_climate(config.climate.sample(position.0, position.2))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::chunk::block::find_block;
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 8, 3)
+    }
+
+    /// Find a column of every biome, searching outwards from the origin.
+    fn find_biomes(config: &ChunkConfig) -> HashMap<Biome, Position> {
+        let mut found = HashMap::new();
+        for x in -100..100 {
+            for z in -100..100 {
+                let position = (x * 64, 0, z * 64);
+                found.entry(biome_at(config, &position)).or_insert(position);
+            }
+        }
+        found
+    }
+
+    #[test]
+    fn biome_climate_test() {
+        for biome in Biome::ALL {
+            assert_eq!(biome_for_climate(biome.climate()), biome);
+
+            // the heart of a biome isn't blended with any other biome
+            let weights = biome_weights(biome.climate());
+            assert_eq!(weights[biome as usize], 1.0);
+        }
+
+        // halfway between two biomes both weigh the same
+        let weights = biome_weights([0.05, 0.25]);
+        assert!((weights[Biome::Plains as usize] - weights[Biome::Ocean as usize]).abs() < 1e-9);
+        assert!((weights.iter().sum::<f64>() - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn biome_at_test() {
+        let config = create_mock_config();
+        let found = find_biomes(&config);
+        for biome in Biome::ALL {
+            assert!(found.contains_key(&biome), "no {:?} biome", biome);
+
+            let params = biome.params();
+            assert!(find_block(&config.dict, params.surface).is_some());
+            assert!(find_block(&config.dict, params.subsurface).is_some());
+        }
+
+        // the same for the same seed, and only x and z matter
+        let other = create_mock_config();
+        for (biome, position) in found.iter() {
+            assert_eq!(biome_at(&other, position), *biome);
+            assert_eq!(biome_at(&config, &(position.0, 100, position.2)), *biome);
+            assert_eq!(
+                terrain_column(&config, position.0, position.2).biome,
+                *biome
+            );
+        }
+    }
+
+    #[test]
+    fn blend_test() {
+        let config = create_mock_config();
+
+        // walk through a border between biomes, the ground never jumps
+        let found = find_biomes(&config);
+        let (from, to) = (found[&Biome::Ocean], found[&Biome::Mountains]);
+        let steps = (from.0 - to.0).abs().max((from.2 - to.2).abs());
+        let mut last = terrain_column(&config, from.0, from.2);
+        let mut biomes = vec![last.biome];
+        for step in 1..=steps {
+            let x = from.0 + (to.0 - from.0) * step / steps;
+            let z = from.2 + (to.2 - from.2) * step / steps;
+            let column = terrain_column(&config, x, z);
+            assert!(
+                (column.height - last.height).abs() < 2.0,
+                "{:?} to {:?} at {}, {}",
+                last,
+                column,
+                x,
+                z
+            );
+            if column.biome != last.biome {
+                biomes.push(column.biome);
+            }
+            last = column;
+        }
+        assert!(biomes.len() > 1);
+        assert_eq!(last.biome, Biome::Mountains);
+    }
+}
@@ -1,34 +1,111 @@
 use std::collections::HashMap;
 
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+
+use crate::chunk::cube_model::cube_model;
+use crate::chunk::culling::Side;
+use crate::chunk::lighting::LightData;
 use crate::chunk::meshing::Vertex;
 use crate::chunk::ChunkData;
+use crate::engine::resources::load_string;
 
 use super::{ChunkConfig, Position};
 
-pub type BlockModel = fn(&ChunkData, &ChunkConfig, &Position, &mut Vec<Vertex>, &mut Vec<u16>);
+pub type BlockModel =
+    fn(&ChunkData, &LightData, &ChunkConfig, &Position, &mut Vec<Vertex>, &mut Vec<u32>);
 
+#[derive(Clone)]
 pub struct Block {
     pub model: BlockModel,
     pub transparent: bool,
+    /// How the block's faces are drawn.
+    pub layer: RenderLayer,
+    /// If the player collides with the block.
+    pub collision: bool,
+    /// Light level the block gives off, from 0 to 15.
+    pub light_emission: u8,
     pub ident: String,
-    pub uv: [f32; 2],
+    /// Atlas uv of each face, indexed by `Side`.
+    pub uvs: [[f32; 2]; 6],
+    /// The level of the fluid if the block is one.
+    pub fluid: Option<Fluid>,
+}
+
+/// A level of a fluid, every level of a fluid is a block of its own with the
+/// levels following the source's id, from the highest to the lowest.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Fluid {
+    /// Id of the fluid's source block.
+    pub source: u32,
+    /// Level of the block, from 1 to `levels`, sources are at `levels`.
+    pub level: u8,
+    /// Number of levels of the fluid.
+    pub levels: u8,
+}
+
+impl Fluid {
+    pub fn is_source(&self) -> bool {
+        self.level == self.levels
+    }
+
+    /// Get the id of the block at a level of the fluid.
+    pub fn id(&self, level: u8) -> u32 {
+        self.source + (self.levels - level) as u32
+    }
+}
+
+/// How the faces of a block are drawn, each layer is meshed and drawn
+/// separately, in the order of `RenderLayer::ALL`.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq, Hash)]
+pub enum RenderLayer {
+    /// Fully covers what is behind it.
+    #[default]
+    Opaque,
+    /// Pixels are either fully covering or see through, like leaves.
+    Cutout,
+    /// Blended with what is behind it, like water.
+    Translucent,
+}
+
+impl RenderLayer {
+    pub const ALL: [RenderLayer; 3] = [
+        RenderLayer::Opaque,
+        RenderLayer::Cutout,
+        RenderLayer::Translucent,
+    ];
+}
+
+impl Block {
+    /// Get the atlas uv of a face of the block.
+    pub fn uv(&self, side: Side) -> [f32; 2] {
+        self.uvs[side as usize]
+    }
 }
 
 /// We provide two different implementations of the default block so we can
 /// have a default for the Block and &Block types
 
 const CONST_DEFAULT_BLOCK: Block = Block {
-    model: |_, _, _, _, _| {},
+    model: |_, _, _, _, _, _| {},
     transparent: true,
+    layer: RenderLayer::Opaque,
+    collision: false,
+    light_emission: 0,
     ident: String::new(),
-    uv: [0.0, 0.0],
+    uvs: [[0.0, 0.0]; 6],
+    fluid: None,
 };
 
 static STATIC_DEFAULT_BLOCK: Block = Block {
-    model: |_, _, _, _, _| {},
+    model: |_, _, _, _, _, _| {},
     transparent: true,
+    layer: RenderLayer::Opaque,
+    collision: false,
+    light_emission: 0,
     ident: String::new(),
-    uv: [0.0, 0.0],
+    uvs: [[0.0, 0.0]; 6],
+    fluid: None,
 };
 
 impl Default for Block {
@@ -44,3 +121,485 @@ impl Default for &Block {
 }
 
 pub type BlockDictionary = HashMap<u32, Block>;
+
+/// Get the id of the block with the ident.
+pub fn find_block(dict: &BlockDictionary, ident: &str) -> Option<u32> {
+    dict.iter()
+        .find(|(_, block)| block.ident == ident)
+        .map(|(id, _)| *id)
+}
+
+/// The file the block definitions are loaded from, in the assets folder.
+pub const BLOCKS_FILE: &str = "blocks.ron";
+/// The file naming the tiles of the texture atlas, in the assets folder.
+pub const ATLAS_FILE: &str = "atlas.ron";
+
+/// Names of the tiles in the texture atlas, so blocks can refer to their
+/// textures by name.
+pub struct AtlasManifest {
+    /// Size of a tile in texture coordinates.
+    pub uv_size: f32,
+    /// Position of each tile, in tiles from the top left of the atlas.
+    pub tiles: HashMap<String, [u32; 2]>,
+}
+
+impl AtlasManifest {
+    /// Parse the contents of an atlas file, checking every tile is inside the atlas.
+    pub fn parse(source: &str, uv_size: f32) -> anyhow::Result<Self> {
+        let tiles: HashMap<String, [u32; 2]> = ron::from_str(source)?;
+
+        let size = (1.0 / uv_size).round() as u32;
+        for (name, tile) in tiles.iter() {
+            if tile[0] >= size || tile[1] >= size {
+                bail!(
+                    "Texture {} at tile {:?} is outside of the {}x{} atlas",
+                    name,
+                    tile,
+                    size,
+                    size
+                );
+            }
+        }
+
+        Ok(Self { uv_size, tiles })
+    }
+
+    /// Get the texture coordinates of the top left of a tile.
+    pub fn uv(&self, name: &str) -> Option<[f32; 2]> {
+        self.tiles
+            .get(name)
+            .map(|tile| [tile[0] as f32 * self.uv_size, tile[1] as f32 * self.uv_size])
+    }
+}
+
+/// Names of the atlas textures on a block's faces.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum BlockTextures {
+    /// The same texture on every face.
+    All(String),
+    /// A texture for the top, one for the bottom and one for the other faces.
+    Column {
+        top: String,
+        bottom: String,
+        side: String,
+    },
+    /// A texture for every face.
+    Faces {
+        front: String,
+        back: String,
+        top: String,
+        bottom: String,
+        left: String,
+        right: String,
+    },
+}
+
+impl BlockTextures {
+    /// Get the texture of every face, indexed by `Side`.
+    pub fn names(&self) -> [&str; 6] {
+        match self {
+            BlockTextures::All(name) => [name; 6],
+            BlockTextures::Column { top, bottom, side } => [side, side, top, bottom, side, side],
+            BlockTextures::Faces {
+                front,
+                back,
+                top,
+                bottom,
+                left,
+                right,
+            } => [front, back, top, bottom, left, right],
+        }
+        .map(|name| name.as_str())
+    }
+}
+
+fn default_model() -> String {
+    "cube".to_owned()
+}
+
+fn default_collision() -> bool {
+    true
+}
+
+/// A block as it is written in the blocks file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct BlockDefinition {
+    pub id: u32,
+    pub ident: String,
+    /// Name of the block's model, see `block_model`.
+    #[serde(default = "default_model")]
+    pub model: String,
+    /// Only blocks with the "none" model can leave out their textures.
+    #[serde(default)]
+    pub textures: Option<BlockTextures>,
+    #[serde(default)]
+    pub transparent: bool,
+    #[serde(default)]
+    pub layer: RenderLayer,
+    #[serde(default = "default_collision")]
+    pub collision: bool,
+    #[serde(default)]
+    pub light_emission: u8,
+    /// Fluids take up one id per level, see `Fluid`.
+    #[serde(default)]
+    pub fluid_levels: u8,
+}
+
+/// Get a block model from its name in the blocks file.
+pub fn block_model(name: &str) -> Option<BlockModel> {
+    match name {
+        "none" => Some(|_, _, _, _, _, _| {}),
+        "cube" => Some(cube_model),
+        _ => None,
+    }
+}
+
+/// Check the definitions and turn them into a block dictionary, with the
+/// textures looked up in the atlas.
+pub fn build_block_dictionary(
+    definitions: Vec<BlockDefinition>,
+    atlas: &AtlasManifest,
+) -> anyhow::Result<BlockDictionary> {
+    let mut dict = BlockDictionary::new();
+    // the block each ident is taken by, fluids also take the idents of their
+    // lower levels
+    let mut idents = HashMap::new();
+    for definition in definitions {
+        let ident = &definition.ident;
+        let ids = definition.id..definition.id + definition.fluid_levels.max(1) as u32;
+        if let Some(id) = ids.clone().find(|id| dict.contains_key(id)) {
+            bail!("Block {} uses the id {} of another block", ident, id);
+        }
+        // the source keeps the ident, the lower levels are named after it
+        let level_idents: Vec<String> = (1..definition.fluid_levels)
+            .map(|level| format!("{}_{}", ident, level))
+            .collect();
+        for taken in std::iter::once(ident).chain(level_idents.iter()) {
+            match idents.insert(taken.clone(), ident.clone()) {
+                Some(other) if other == *taken && taken == ident => {
+                    bail!("Block {} is defined more than once", ident)
+                }
+                Some(other) if other == *taken => bail!(
+                    "Block {} names a level of its fluid {}, which is already a block",
+                    ident,
+                    taken
+                ),
+                Some(other) => bail!(
+                    "Block {} takes the ident {} of a level of the fluid {}",
+                    ident,
+                    taken,
+                    other
+                ),
+                None => {}
+            }
+        }
+
+        let model = block_model(&definition.model)
+            .ok_or_else(|| anyhow!("Block {} has unknown model {}", ident, definition.model))?;
+
+        let mut uvs = [[0.0, 0.0]; 6];
+        match &definition.textures {
+            Some(textures) => {
+                for (uv, name) in uvs.iter_mut().zip(textures.names()) {
+                    *uv = atlas
+                        .uv(name)
+                        .ok_or_else(|| anyhow!("Block {} has unknown texture {}", ident, name))?;
+                }
+            }
+            None if definition.model != "none" => {
+                bail!("Block {} has no textures", ident);
+            }
+            None => {}
+        }
+
+        // faces behind the block have to be drawn to be seen through it
+        if definition.layer != RenderLayer::Opaque && !definition.transparent {
+            bail!(
+                "Block {} is in the {:?} layer, so it has to be transparent",
+                ident,
+                definition.layer
+            );
+        }
+
+        if definition.light_emission > 15 {
+            bail!(
+                "Block {} has light emission {}, the max is 15",
+                ident,
+                definition.light_emission
+            );
+        }
+
+        if definition.fluid_levels > 0 && definition.collision {
+            bail!("Block {} is a fluid, so it can't have collision", ident);
+        }
+
+        let block = Block {
+            model,
+            transparent: definition.transparent,
+            layer: definition.layer,
+            collision: definition.collision,
+            light_emission: definition.light_emission,
+            ident: definition.ident.clone(),
+            uvs,
+            fluid: None,
+        };
+
+        if definition.fluid_levels == 0 {
+            dict.insert(definition.id, block);
+            continue;
+        }
+
+        let levels = definition.fluid_levels;
+        for level in 1..=levels {
+            let fluid = Fluid {
+                source: definition.id,
+                level,
+                levels,
+            };
+            let ident = if fluid.is_source() {
+                definition.ident.clone()
+            } else {
+                level_idents[level as usize - 1].clone()
+            };
+            dict.insert(
+                fluid.id(level),
+                Block {
+                    ident,
+                    fluid: Some(fluid),
+                    ..block.clone()
+                },
+            );
+        }
+    }
+
+    // empty space in chunks is block 0
+    if !dict.contains_key(&0) {
+        bail!("No block has the id 0, it has to be defined as air");
+    }
+
+    Ok(dict)
+}
+
+/// Parse the contents of a blocks file into a block dictionary.
+pub fn parse_block_dictionary(
+    source: &str,
+    atlas: &AtlasManifest,
+) -> anyhow::Result<BlockDictionary> {
+    // so optional fields can be written without `Some`
+    let definitions: Vec<BlockDefinition> = ron::Options::default()
+        .with_default_extension(ron::extensions::Extensions::IMPLICIT_SOME)
+        .from_str(source)?;
+    build_block_dictionary(definitions, atlas)
+}
+
+/// Load a block dictionary from the blocks file and atlas file in the assets
+/// folder, `uv_size` is the size of a tile in the texture atlas.
+pub async fn load_block_dictionary(uv_size: f32) -> anyhow::Result<BlockDictionary> {
+    let source = load_string(ATLAS_FILE, true).await?;
+    let atlas = AtlasManifest::parse(&source, uv_size)
+        .with_context(|| format!("Failed to load the atlas from {}", ATLAS_FILE))?;
+
+    let source = load_string(BLOCKS_FILE, true).await?;
+    parse_block_dictionary(&source, &atlas)
+        .with_context(|| format!("Failed to load blocks from {}", BLOCKS_FILE))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const UV_SIZE: f32 = 0.0625;
+
+    fn create_mock_atlas() -> AtlasManifest {
+        AtlasManifest::parse(
+            r#"{"a": (1, 2), "b": (3, 4), "c": (5, 6), "d": (15, 15)}"#,
+            UV_SIZE,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn blocks_file_test() -> anyhow::Result<()> {
+        let atlas = AtlasManifest::parse(include_str!("../../assets/atlas.ron"), UV_SIZE)?;
+        let dict = parse_block_dictionary(include_str!("../../assets/blocks.ron"), &atlas)?;
+
+        let air = &dict[&0];
+        assert!(air.transparent);
+        assert!(!air.collision);
+
+        let stone = dict.values().find(|b| b.ident == "stone").unwrap();
+        assert!(!stone.transparent);
+        assert!(stone.collision);
+        assert_eq!(stone.uv(Side::TOP), [0.0625, 0.0]);
+
+        let grass = dict.values().find(|b| b.ident == "grass").unwrap();
+        let dirt = dict.values().find(|b| b.ident == "dirt").unwrap();
+        assert_eq!(grass.uv(Side::BOTTOM), dirt.uv(Side::TOP));
+        assert_ne!(grass.uv(Side::TOP), grass.uv(Side::FRONT));
+        assert_eq!(grass.layer, RenderLayer::Opaque);
+
+        let glass = dict.values().find(|b| b.ident == "glass").unwrap();
+        assert_eq!(glass.layer, RenderLayer::Cutout);
+        let water = dict.values().find(|b| b.ident == "water").unwrap();
+        assert_eq!(water.layer, RenderLayer::Translucent);
+        assert!(water.transparent);
+        assert!(!water.collision);
+        Ok(())
+    }
+
+    #[test]
+    fn parse_blocks_test() -> anyhow::Result<()> {
+        let dict = parse_block_dictionary(
+            r#"[
+                (id: 0, ident: "air", model: "none", transparent: true, collision: false),
+                (
+                    id: 7,
+                    ident: "lamp",
+                    textures: Column(top: "a", bottom: "b", side: "c"),
+                    light_emission: 14,
+                ),
+                (
+                    id: 8,
+                    ident: "crate",
+                    textures: Faces(
+                        front: "a",
+                        back: "b",
+                        top: "c",
+                        bottom: "d",
+                        left: "a",
+                        right: "b",
+                    ),
+                ),
+            ]"#,
+            &create_mock_atlas(),
+        )?;
+
+        let lamp = &dict[&7];
+        assert_eq!(lamp.ident, "lamp");
+        assert_eq!(lamp.light_emission, 14);
+        assert!(lamp.collision);
+        assert!(!lamp.transparent);
+        assert_eq!(lamp.uv(Side::TOP), [0.0625, 0.125]);
+        assert_eq!(lamp.uv(Side::BOTTOM), [0.1875, 0.25]);
+        assert_eq!(lamp.uv(Side::LEFT), [0.3125, 0.375]);
+        assert_eq!(lamp.uv(Side::FRONT), [0.3125, 0.375]);
+
+        let crate_block = &dict[&8];
+        assert_eq!(crate_block.uv(Side::BACK), [0.1875, 0.25]);
+        assert_eq!(crate_block.uv(Side::BOTTOM), [0.9375, 0.9375]);
+        assert_eq!(crate_block.uv(Side::RIGHT), [0.1875, 0.25]);
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_blocks_test() {
+        let atlas = create_mock_atlas();
+        let error = |source: &str| {
+            parse_block_dictionary(source, &atlas)
+                .err()
+                .unwrap()
+                .to_string()
+        };
+
+        let duplicate_id = error(
+            r#"[
+                (id: 0, ident: "air", model: "none"),
+                (id: 1, ident: "a", textures: All("a")),
+                (id: 1, ident: "b", textures: All("a")),
+            ]"#,
+        );
+        assert!(duplicate_id.contains("uses the id 1"), "{}", duplicate_id);
+
+        let duplicate_ident = error(
+            r#"[(id: 0, ident: "air", model: "none"), (id: 1, ident: "air", model: "none")]"#,
+        );
+        assert!(
+            duplicate_ident.contains("more than once"),
+            "{}",
+            duplicate_ident
+        );
+
+        let unknown_model = error(r#"[(id: 0, ident: "air", model: "sphere")]"#);
+        assert!(
+            unknown_model.contains("unknown model sphere"),
+            "{}",
+            unknown_model
+        );
+
+        let unknown_texture = error(
+            r#"[(id: 0, ident: "air", model: "none"), (id: 1, ident: "a", textures: All("e"))]"#,
+        );
+        assert!(
+            unknown_texture.contains("unknown texture e"),
+            "{}",
+            unknown_texture
+        );
+
+        let no_textures = error(r#"[(id: 0, ident: "air", model: "none"), (id: 1, ident: "a")]"#);
+        assert!(no_textures.contains("no textures"), "{}", no_textures);
+
+        let opaque_layer = error(
+            r#"[(id: 0, ident: "air", model: "none"), (id: 1, ident: "a", textures: All("a"), layer: Translucent)]"#,
+        );
+        assert!(
+            opaque_layer.contains("has to be transparent"),
+            "{}",
+            opaque_layer
+        );
+
+        let fluid_overlap = error(
+            r#"[
+                (id: 0, ident: "air", model: "none"),
+                (id: 1, ident: "a", textures: All("a"), collision: false, fluid_levels: 4),
+                (id: 3, ident: "b", textures: All("a")),
+            ]"#,
+        );
+        assert!(fluid_overlap.contains("uses the id 3"), "{}", fluid_overlap);
+
+        // the lower levels of a fluid are named after it, in either order
+        for blocks in [
+            r#"(id: 1, ident: "a", textures: All("a"), collision: false, fluid_levels: 4),
+               (id: 5, ident: "a_3", textures: All("a"))"#,
+            r#"(id: 5, ident: "a_3", textures: All("a")),
+               (id: 1, ident: "a", textures: All("a"), collision: false, fluid_levels: 4)"#,
+        ] {
+            let level_ident = error(&format!(
+                r#"[(id: 0, ident: "air", model: "none"), {}]"#,
+                blocks
+            ));
+            assert!(level_ident.contains("a_3"), "{}", level_ident);
+        }
+
+        let solid_fluid = error(
+            r#"[(id: 0, ident: "air", model: "none"), (id: 1, ident: "a", textures: All("a"), fluid_levels: 2)]"#,
+        );
+        assert!(
+            solid_fluid.contains("can't have collision"),
+            "{}",
+            solid_fluid
+        );
+
+        let too_bright = error(r#"[(id: 0, ident: "air", model: "none", light_emission: 16)]"#);
+        assert!(too_bright.contains("light emission 16"), "{}", too_bright);
+
+        let no_air = error(r#"[(id: 1, ident: "stone", textures: All("a"))]"#);
+        assert!(no_air.contains("id 0"), "{}", no_air);
+
+        assert!(parse_block_dictionary("[(id: 0)]", &atlas).is_err());
+    }
+
+    #[test]
+    fn invalid_atlas_test() {
+        let outside_atlas = AtlasManifest::parse(r#"{"a": (16, 0)}"#, UV_SIZE)
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(
+            outside_atlas.contains("outside of the 16x16 atlas"),
+            "{}",
+            outside_atlas
+        );
+
+        assert!(AtlasManifest::parse(r#"{"a": 1}"#, UV_SIZE).is_err());
+    }
+}
@@ -0,0 +1,268 @@
+//! Caves carved out of the stone, large cheese caves where 3d noise is high and
+//! worm caves, tunnels that wind through many chunks.
+//!
+//! Worms start in cells of `WORM_CELL` blocks, seeded from the cell's position,
+//! so a chunk carves every worm that reaches it without knowing which chunks
+//! were generated before it.
+
+use std::f64::consts::TAU;
+
+use libnoise::prelude::*;
+
+use super::biome::terrain_column;
+use super::block::BlockDictionary;
+use super::generation::{
+    chunk_columns, column_index, global_position, terrain_block, GenerationArea, GenerationPass,
+    TerrainBlocks, SEA_LEVEL,
+};
+use super::random::WorldRandom;
+use super::{ChunkConfig, ChunkData, ChunkPos, Position};
+
+/// Tells the random numbers of worms apart from other features.
+const WORM_SALT: u64 = 1;
+/// Size of the cells worms start in, in blocks.
+const WORM_CELL: i32 = 64;
+/// Chance of a cell having a worm.
+const WORM_CHANCE: f64 = 0.6;
+/// Shortest and longest worms, in blocks.
+const WORM_LENGTH: (i32, i32) = (40, 120);
+/// Thinnest and thickest a worm gets.
+const WORM_RADIUS: (f64, f64) = (1.5, 3.0);
+/// Farthest a worm reaches from the cell it started in.
+const WORM_REACH: i32 = WORM_LENGTH.1 + 3;
+
+/// How fast the cheese noise changes horizontally and vertically.
+const CHEESE_SCALE: (f64, f64) = (0.02, 0.035);
+/// Noise above which the stone is carved into cheese caves.
+const CHEESE_THRESHOLD: f64 = 0.6;
+/// How deep below the ground cheese caves start.
+const CHEESE_DEPTH: f64 = 16.0;
+
+/// A tunnel of spheres, each a block further along the worm.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Worm {
+    /// Center and radius of every sphere.
+    pub points: Vec<([f64; 3], f64)>,
+}
+
+/// Get the worm starting in a cell, if it has one. Cells are positions divided
+/// by `WORM_CELL`.
+pub fn cell_worm(seed: u64, cell: &Position) -> Option<Worm> {
+    let mut random = WorldRandom::new(seed, WORM_SALT, cell);
+    if random.next_f64() >= WORM_CHANCE {
+        return None;
+    }
+
+    let size = WORM_CELL as f64;
+    let mut point = [
+        cell.0 as f64 * size + random.range(0.0, size),
+        cell.1 as f64 * size + random.range(0.0, size),
+        cell.2 as f64 * size + random.range(0.0, size),
+    ];
+    let mut yaw = random.range(0.0, TAU);
+    let mut pitch = random.range(-0.4, 0.4);
+    let mut radius = random.range(WORM_RADIUS.0, WORM_RADIUS.1);
+    let length = random.range_i32(WORM_LENGTH.0, WORM_LENGTH.1);
+
+    let mut points = Vec::new();
+    for _ in 0..length {
+        points.push((point, radius));
+
+        point[0] += yaw.cos() * pitch.cos();
+        point[1] += pitch.sin();
+        point[2] += yaw.sin() * pitch.cos();
+        yaw += random.range(-0.25, 0.25);
+        pitch = (pitch + random.range(-0.15, 0.15)).clamp(-0.7, 0.7);
+        radius = (radius + random.range(-0.2, 0.2)).clamp(WORM_RADIUS.0, WORM_RADIUS.1);
+    }
+
+    Some(Worm { points })
+}
+
+/// Get every worm that could reach the chunk or its border.
+pub fn worms_near(seed: u64, config: &ChunkConfig, pos: &ChunkPos) -> Vec<Worm> {
+    let cells = |chunk: i32| {
+        let min = chunk * config.depth - 1 - WORM_REACH;
+        let max = chunk * config.depth + config.depth + WORM_REACH;
+        min.div_euclid(WORM_CELL)..=max.div_euclid(WORM_CELL)
+    };
+
+    let mut worms = Vec::new();
+    for x in cells(pos.x) {
+        for y in cells(pos.y) {
+            for z in cells(pos.z) {
+                worms.extend(cell_worm(seed, &(x, y, z)));
+            }
+        }
+    }
+    worms
+}
+
+/// Check if carving out the block at a world space position would open the
+/// cave to the water of a sea or lake.
+fn near_water(config: &ChunkConfig, blocks: &TerrainBlocks, position: [i32; 3]) -> bool {
+    if position[1] > SEA_LEVEL + 1 {
+        return false;
+    }
+
+    let neighbors = [
+        (0, 0, 0),
+        (1, 0, 0),
+        (-1, 0, 0),
+        (0, 1, 0),
+        (0, -1, 0),
+        (0, 0, 1),
+        (0, 0, -1),
+    ];
+    neighbors.iter().any(|offset| {
+        let neighbor = [
+            position[0] + offset.0,
+            position[1] + offset.1,
+            position[2] + offset.2,
+        ];
+        let column = terrain_column(config, neighbor[0], neighbor[2]);
+        terrain_block(config, blocks, &column, neighbor) == blocks.water
+    })
+}
+
+/// Carves cheese and worm caves out of the stone, but never next to water.
+pub struct CavePass {
+    seed: u64,
+    blocks: TerrainBlocks,
+    cheese: Simplex<3>,
+}
+
+impl CavePass {
+    pub fn new(seed: u64, dict: &BlockDictionary) -> anyhow::Result<Self> {
+        Ok(Self {
+            seed,
+            blocks: TerrainBlocks::new(dict)?,
+            cheese: Source::simplex(seed.wrapping_add(3)),
+        })
+    }
+
+    /// Carve the block at a chunk local position if it's stone.
+    fn carve(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        position: &Position,
+        output: &mut ChunkData,
+    ) {
+        if output.get(position) == self.blocks.stone
+            && !near_water(config, &self.blocks, global_position(config, pos, position))
+        {
+            output.set(position, 0);
+        }
+    }
+}
+
+impl GenerationPass for CavePass {
+    fn name(&self) -> &'static str {
+        "caves"
+    }
+
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    ) {
+        let columns = chunk_columns(config, pos);
+        for position in area.positions(config) {
+            let column = &columns[column_index(config, position.0, position.2)];
+            let global = global_position(config, pos, &position);
+            if global[1] as f64 > column.height - CHEESE_DEPTH {
+                continue;
+            }
+
+            let noise = self.cheese.sample([
+                global[0] as f64 * CHEESE_SCALE.0,
+                global[1] as f64 * CHEESE_SCALE.1,
+                global[2] as f64 * CHEESE_SCALE.0,
+            ]);
+            if noise > CHEESE_THRESHOLD {
+                self.carve(config, pos, &position, output);
+            }
+        }
+
+        let origin = global_position(config, pos, &(0, 0, 0));
+        for worm in worms_near(self.seed, config, pos) {
+            for (point, radius) in worm.points {
+                // the blocks around the sphere, in chunk local positions
+                let range = |axis: usize| {
+                    let min = ((point[axis] - radius).floor() as i32 - origin[axis]).max(-1);
+                    let max =
+                        ((point[axis] + radius).ceil() as i32 - origin[axis]).min(config.depth);
+                    min..=max
+                };
+
+                for x in range(0) {
+                    for y in range(1) {
+                        for z in range(2) {
+                            let position = (x, y, z);
+                            let distance = (0..3)
+                                .map(|axis| {
+                                    let block = [x, y, z][axis] + origin[axis];
+                                    (block as f64 - point[axis]).powi(2)
+                                })
+                                .sum::<f64>();
+                            if distance <= radius * radius && area.contains(config, &position) {
+                                self.carve(config, pos, &position, output);
+                            }
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::generation::generate;
+    use crate::chunk::{chunk_position, local_position};
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 16, 3)
+    }
+
+    #[test]
+    fn cell_worm_test() {
+        let worms: Vec<Worm> = (0..20).filter_map(|x| cell_worm(10, &(x, -2, 0))).collect();
+        assert!(!worms.is_empty());
+        assert_eq!(cell_worm(10, &(3, -2, 0)), cell_worm(10, &(3, -2, 0)));
+
+        for worm in worms.iter() {
+            assert!(worm.points.len() >= WORM_LENGTH.0 as usize);
+            for window in worm.points.windows(2) {
+                let step: f64 = (0..3)
+                    .map(|axis| (window[1].0[axis] - window[0].0[axis]).powi(2))
+                    .sum();
+                assert!((step.sqrt() - 1.0).abs() < 1e-9);
+                assert!(window[0].1 >= WORM_RADIUS.0 && window[0].1 <= WORM_RADIUS.1);
+            }
+        }
+    }
+
+    #[test]
+    fn worm_carving_test() {
+        let config = create_mock_config();
+
+        // a worm deep under ground, every point of it is carved out
+        let worm = (0..20)
+            .find_map(|x| cell_worm(config.settings.seed, &(x, -3, 0)))
+            .unwrap();
+        for (point, _) in worm.points.iter().step_by(10) {
+            let position = (
+                point[0].round() as i32,
+                point[1].round() as i32,
+                point[2].round() as i32,
+            );
+            let chunk = generate(&config, &chunk_position(&config, &position));
+            assert_eq!(chunk.get(&local_position(&config, &position)), 0);
+        }
+    }
+}
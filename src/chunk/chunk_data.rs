@@ -0,0 +1,356 @@
+//! Storage for the blocks of a single chunk.
+
+use std::collections::HashMap;
+
+use super::Position;
+
+/// Palettes up to this long are searched for a block id, longer ones look it
+/// up in a map.
+const PALETTE_SEARCH_LENGTH: usize = 16;
+
+/// The blocks of a chunk, including the one block border around it that is
+/// generated so the chunk can be meshed on its own.
+///
+/// Blocks are stored in a flat array over the padded volume, as indices into a
+/// palette of block ids. A chunk made up of a single block, most often air,
+/// doesn't allocate the array at all. Block ids no block uses anymore stay in
+/// the palette until it's compacted, when it's full or the chunk is saved.
+#[derive(Clone, Debug)]
+pub struct ChunkData {
+    depth: i32,
+    palette: Vec<u32>,
+    /// Index of every block id in the palette, empty while the palette is
+    /// short enough to search.
+    palette_lookup: HashMap<u32, u16>,
+    indices: Vec<u16>,
+}
+
+impl ChunkData {
+    /// Create a chunk filled with air, `depth` is the number of blocks along
+    /// each axis not counting the border.
+    pub fn new(depth: i32) -> Self {
+        Self {
+            depth,
+            palette: vec![0],
+            palette_lookup: HashMap::new(),
+            indices: Vec::new(),
+        }
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    /// Number of blocks in the chunk, including the border.
+    fn volume(&self) -> usize {
+        let size = (self.depth + 2) as usize;
+        size * size * size
+    }
+
+    /// Get the index of a chunk local position in the flat array,
+    /// `None` if the position is outside of the chunk and its border.
+    fn index(&self, pos: &Position) -> Option<usize> {
+        let size = self.depth + 2;
+        let (x, y, z) = (pos.0 + 1, pos.1 + 1, pos.2 + 1);
+        if x < 0 || y < 0 || z < 0 || x >= size || y >= size || z >= size {
+            return None;
+        }
+        Some((x + size * (y + size * z)) as usize)
+    }
+
+    /// Get the chunk local position of an index in the flat array.
+    fn position(&self, index: usize) -> Position {
+        let size = (self.depth + 2) as usize;
+        (
+            (index % size) as i32 - 1,
+            (index / size % size) as i32 - 1,
+            (index / (size * size)) as i32 - 1,
+        )
+    }
+
+    fn get_index(&self, index: usize) -> u32 {
+        if self.indices.is_empty() {
+            return self.palette[0];
+        }
+        self.palette[self.indices[index] as usize]
+    }
+
+    /// Get the block id at a chunk local position. Positions outside of the
+    /// chunk and its border are air.
+    pub fn get(&self, pos: &Position) -> u32 {
+        match self.index(pos) {
+            Some(index) => self.get_index(index),
+            None => 0,
+        }
+    }
+
+    /// Set the block id at a chunk local position. Returns false, and does
+    /// nothing, if the position is outside of the chunk and its border.
+    pub fn set(&mut self, pos: &Position, block_id: u32) -> bool {
+        let index = match self.index(pos) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let palette_index = self.palette_index(block_id);
+        if self.indices.is_empty() {
+            if palette_index == 0 {
+                return true;
+            }
+            self.indices = vec![0; self.volume()];
+        }
+        self.indices[index] = palette_index;
+
+        true
+    }
+
+    /// Get the index of a block id in the palette, adding it if it isn't in
+    /// the palette yet. A full palette is compacted first.
+    fn palette_index(&mut self, block_id: u32) -> u16 {
+        let found = if self.palette_lookup.is_empty() {
+            self.palette
+                .iter()
+                .position(|b| *b == block_id)
+                .map(|index| index as u16)
+        } else {
+            self.palette_lookup.get(&block_id).copied()
+        };
+        if let Some(index) = found {
+            return index;
+        }
+
+        if self.palette.len() > u16::MAX as usize {
+            self.compact_palette();
+        }
+        let index = u16::try_from(self.palette.len())
+            .expect("A chunk can't have more different blocks than 16 bit indices reach");
+        self.palette.push(block_id);
+        if self.palette.len() > PALETTE_SEARCH_LENGTH {
+            if self.palette_lookup.is_empty() {
+                self.build_palette_lookup();
+            } else {
+                self.palette_lookup.insert(block_id, index);
+            }
+        }
+        index
+    }
+
+    fn build_palette_lookup(&mut self) {
+        self.palette_lookup.clear();
+        if self.palette.len() > PALETTE_SEARCH_LENGTH {
+            let indices = self.palette.iter().enumerate();
+            self.palette_lookup = indices.map(|(i, b)| (*b, i as u16)).collect();
+        }
+    }
+
+    /// Drop the block ids no block of the chunk uses anymore from the palette.
+    pub fn compact_palette(&mut self) {
+        if self.indices.is_empty() {
+            self.palette.truncate(1);
+            self.build_palette_lookup();
+            return;
+        }
+
+        let mut remap = vec![None; self.palette.len()];
+        let mut palette = Vec::new();
+        for index in self.indices.iter_mut() {
+            let old_index = *index as usize;
+            *index = *remap[old_index].get_or_insert_with(|| {
+                palette.push(self.palette[old_index]);
+                (palette.len() - 1) as u16
+            });
+        }
+        if palette.len() == 1 {
+            self.indices = Vec::new();
+        }
+        self.palette = palette;
+        self.build_palette_lookup();
+    }
+
+    /// Iterate over the positions and ids of every block that isn't air,
+    /// including the ones in the border.
+    pub fn iter(&self) -> impl Iterator<Item = (Position, u32)> + '_ {
+        (0..self.volume()).filter_map(move |index| {
+            let block_id = self.get_index(index);
+            if block_id == 0 {
+                return None;
+            }
+            Some((self.position(index), block_id))
+        })
+    }
+
+    /// Returns true if the chunk, including its border, is only air.
+    pub fn is_empty(&self) -> bool {
+        self.iter().next().is_none()
+    }
+
+    /// Replace the blocks in the border with air.
+    pub fn clear_border(&mut self) {
+        for index in 0..self.volume() {
+            let (x, y, z) = self.position(index);
+            let depth = self.depth;
+            if x < 0 || y < 0 || z < 0 || x >= depth || y >= depth || z >= depth {
+                self.set(&(x, y, z), 0);
+            }
+        }
+    }
+}
+
+impl PartialEq for ChunkData {
+    fn eq(&self, other: &Self) -> bool {
+        self.depth == other.depth
+            && (0..self.volume()).all(|index| self.get_index(index) == other.get_index(index))
+    }
+}
+
+impl Eq for ChunkData {}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::chunk::{generation::generate, ChunkConfig, ChunkPos};
+
+    #[test]
+    fn get_set_test() {
+        let mut chunk = ChunkData::new(8);
+        assert!(chunk.is_empty());
+        assert_eq!(chunk.get(&(0, 0, 0)), 0);
+
+        assert!(chunk.set(&(1, 2, 3), 5));
+        assert!(chunk.set(&(-1, 8, -1), 7));
+        assert_eq!(chunk.get(&(1, 2, 3)), 5);
+        assert_eq!(chunk.get(&(-1, 8, -1)), 7);
+        assert_eq!(chunk.get(&(3, 2, 1)), 0);
+
+        // outside of the border
+        assert!(!chunk.set(&(9, 0, 0), 5));
+        assert!(!chunk.set(&(0, -2, 0), 5));
+        assert_eq!(chunk.get(&(9, 0, 0)), 0);
+
+        chunk.set(&(1, 2, 3), 0);
+        chunk.set(&(-1, 8, -1), 0);
+        assert!(chunk.is_empty());
+        assert_eq!(chunk, ChunkData::new(8));
+    }
+
+    #[test]
+    fn palette_test() {
+        let mut chunk = ChunkData::new(4);
+
+        // more block ids than 16 bit indices reach are set over time, the
+        // palette is compacted instead of aliasing them
+        for i in 0..70_000u32 {
+            chunk.set(&((i % 3) as i32, 0, 0), i + 1);
+        }
+        assert_eq!(chunk.get(&(0, 0, 0)), 70_000);
+        assert_eq!(chunk.get(&(1, 0, 0)), 69_998);
+        assert_eq!(chunk.get(&(2, 0, 0)), 69_999);
+        assert_eq!(chunk.get(&(3, 0, 0)), 0);
+        assert!(chunk.palette.len() <= u16::MAX as usize + 1);
+
+        chunk.compact_palette();
+        assert_eq!(chunk.palette.len(), 4);
+        assert!(chunk.palette_lookup.is_empty());
+        assert_eq!(chunk.iter().count(), 3);
+
+        // a chunk of a single block doesn't keep its array
+        for i in 0..3 {
+            chunk.set(&(i, 0, 0), 0);
+        }
+        chunk.compact_palette();
+        assert_eq!(chunk.palette, [0]);
+        assert!(chunk.indices.is_empty());
+        assert_eq!(chunk, ChunkData::new(4));
+    }
+
+    #[test]
+    fn iter_test() {
+        let mut chunk = ChunkData::new(4);
+        chunk.set(&(0, 0, 0), 1);
+        chunk.set(&(-1, 4, 2), 2);
+        chunk.set(&(3, 1, 2), 3);
+
+        let mut blocks: Vec<(Position, u32)> = chunk.iter().collect();
+        blocks.sort();
+        assert_eq!(
+            blocks,
+            vec![((-1, 4, 2), 2), ((0, 0, 0), 1), ((3, 1, 2), 3)]
+        );
+    }
+
+    #[test]
+    fn clear_border_test() {
+        let mut chunk = ChunkData::new(4);
+        chunk.set(&(0, 0, 0), 1);
+        chunk.set(&(3, 3, 3), 1);
+        chunk.set(&(-1, 0, 0), 1);
+        chunk.set(&(4, 4, 4), 1);
+        chunk.clear_border();
+
+        let mut blocks: Vec<(Position, u32)> = chunk.iter().collect();
+        blocks.sort();
+        assert_eq!(blocks, vec![((0, 0, 0), 1), ((3, 3, 3), 1)]);
+    }
+
+    /// Compare looking up every block and its neighbors, like the mesher does,
+    /// against the hash map the chunks used to be stored in.
+    /// Run with `cargo test --release -- --ignored --nocapture lookup_benchmark`.
+    #[test]
+    #[ignore]
+    fn lookup_benchmark() {
+        let config = ChunkConfig::new(10, 32, 3);
+        let chunk = generate(&config, &ChunkPos::new(0, 0, 0));
+        let map: HashMap<Position, u32> = chunk.iter().collect();
+        let depth = config.depth;
+
+        let neighbors = [
+            (0, 0, 0),
+            (1, 0, 0),
+            (-1, 0, 0),
+            (0, 1, 0),
+            (0, -1, 0),
+            (0, 0, 1),
+            (0, 0, -1),
+        ];
+
+        let start = instant::now();
+        let mut map_sum = 0u64;
+        for _ in 0..10 {
+            for x in 0..depth {
+                for y in 0..depth {
+                    for z in 0..depth {
+                        for (i, j, k) in neighbors.iter() {
+                            map_sum += *map.get(&(x + i, y + j, z + k)).unwrap_or(&0) as u64;
+                        }
+                    }
+                }
+            }
+        }
+        let map_time = instant::now() - start;
+
+        let start = instant::now();
+        let mut chunk_sum = 0u64;
+        for _ in 0..10 {
+            for x in 0..depth {
+                for y in 0..depth {
+                    for z in 0..depth {
+                        for (i, j, k) in neighbors.iter() {
+                            chunk_sum += chunk.get(&(x + i, y + j, z + k)) as u64;
+                        }
+                    }
+                }
+            }
+        }
+        let chunk_time = instant::now() - start;
+
+        assert_eq!(map_sum, chunk_sum);
+        println!(
+            "HashMap: {:.2}ms, ChunkData: {:.2}ms, {:.1}x speedup",
+            map_time,
+            chunk_time,
+            map_time / chunk_time
+        );
+    }
+}
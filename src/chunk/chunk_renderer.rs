@@ -2,24 +2,105 @@ use std::collections::{HashMap, VecDeque};
 
 use crate::{
     engine::{
+        graphics::GraphicsContext,
         render::render_group::RenderGroup,
         render::render_object::RenderObject,
         render::render_pass::{RenderPass, RenderPassViews},
         render::uniform::{Uniform, UniformData},
     },
-    window_state,
+    player::camera_position,
     world::GameData,
 };
 
 use super::{
-    chunk_id, chunk_position,
+    block::RenderLayer,
+    chunk_position,
     culling::{get_neighbors, Side},
-    player_to_position, ChunkConfig, Position,
+    meshing::{ChunkMesh, Vertex},
+    player_to_position, ChunkConfig, ChunkPos, Position,
 };
 
+/// Chunks this many chunks or fewer from the camera's chunk sort their
+/// translucent quads again whenever the camera moves into another block.
+/// Farther chunks keep the order they were first sorted in.
+const TRANSLUCENT_SORT_DISTANCE: i32 = 2;
+
+/// The render objects of a chunk's render layers, indexed by `RenderLayer`.
+/// Layers the chunk has no faces in don't have one.
+pub type ChunkObjects = [Option<RenderObject>; 3];
+
+/// Name of the render group that draws a render layer.
+pub fn render_group_name(layer: RenderLayer) -> &'static str {
+    match layer {
+        RenderLayer::Opaque => "chunk_render_group",
+        RenderLayer::Cutout => "chunk_cutout_render_group",
+        RenderLayer::Translucent => "chunk_translucent_render_group",
+    }
+}
+
+/// Upload the mesh of a chunk to the GPU, with a render object for each render
+/// layer that has faces. Done on the main thread when the chunk is inserted.
+pub fn upload_chunk_mesh(device: &wgpu::Device, mesh: &ChunkMesh) -> ChunkObjects {
+    RenderLayer::ALL.map(|layer| {
+        let (vertices, indices) = mesh.layer(layer);
+        if indices.is_empty() {
+            return None;
+        }
+        Some(RenderObject::new(
+            device,
+            render_group_name(layer),
+            bytemuck::cast_slice(vertices),
+            bytemuck::cast_slice(indices),
+        ))
+    })
+}
+
+/// The quads of a chunk's translucent layer, kept on the CPU so they can be
+/// drawn back to front as the camera moves.
+pub struct TranslucentQuads {
+    /// Center of each quad, in blocks from the chunk's corner, and its indices.
+    quads: Vec<([f32; 3], [u32; 6])>,
+    /// Block the camera was in, relative to the chunk, when the quads were
+    /// last sorted.
+    sorted_for: Option<Position>,
+}
+
+impl TranslucentQuads {
+    pub fn new(vertices: &[Vertex], indices: &[u32]) -> Self {
+        let quads = indices
+            .chunks_exact(6)
+            .map(|quad| {
+                let positions = quad.iter().map(|i| vertices[*i as usize].position());
+                let (min, max) = positions.fold(([u32::MAX; 3], [0; 3]), |(min, max), p| {
+                    (
+                        [0, 1, 2].map(|a| min[a].min(p[a])),
+                        [0, 1, 2].map(|a| max[a].max(p[a])),
+                    )
+                });
+                let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) as f32 / 2.0);
+                (center, quad.try_into().unwrap())
+            })
+            .collect();
+        Self {
+            quads,
+            sorted_for: None,
+        }
+    }
+
+    /// Sort the quads from the farthest to the nearest to the camera, which
+    /// is relative to the chunk's corner, and get their indices in that order.
+    fn sort(&mut self, camera: glam::Vec3) -> Vec<u32> {
+        let distance = |center: &[f32; 3]| glam::Vec3::from(*center).distance_squared(camera);
+        self.quads
+            .sort_by(|(a, _), (b, _)| distance(b).total_cmp(&distance(a)));
+        self.quads.iter().flat_map(|(_, quad)| *quad).collect()
+    }
+}
+
 pub struct ChunkRenderPass {
     pub render_groups: HashMap<String, RenderGroup>,
-    pub render_objects: HashMap<String, RenderObject>,
+    pub render_objects: HashMap<ChunkPos, ChunkObjects>,
+    pub translucent_quads: HashMap<ChunkPos, TranslucentQuads>,
     pub uniforms: HashMap<String, Uniform>,
     pub clear_color: wgpu::Color,
 }
@@ -29,6 +110,7 @@ impl ChunkRenderPass {
         Self {
             render_groups: HashMap::new(),
             render_objects: HashMap::new(),
+            translucent_quads: HashMap::new(),
             uniforms: HashMap::new(),
             clear_color: wgpu::Color {
                 r: 0.1,
@@ -38,8 +120,16 @@ impl ChunkRenderPass {
             },
         }
     }
-    fn render_chunk<'a>(&'a self, chunk: &Position, render_pass: &mut wgpu::RenderPass<'a>) {
-        let wrapped_object = self.render_objects.get(&chunk_id(chunk));
+    fn render_chunk<'a>(
+        &'a self,
+        chunk: &ChunkPos,
+        layer: RenderLayer,
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        let wrapped_object = self
+            .render_objects
+            .get(chunk)
+            .and_then(|objects| objects[layer as usize].as_ref());
         if wrapped_object.is_none() {
             return;
         }
@@ -69,11 +159,82 @@ impl ChunkRenderPass {
         // set the vertex buffer
         render_pass.set_vertex_buffer(0, object.vertex_buffer.slice(..));
         // set the index buffer
-        render_pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint16);
-        let num_indices = object.index_buffer.size() as u32 / std::mem::size_of::<u16>() as u32;
+        render_pass.set_index_buffer(object.index_buffer.slice(..), wgpu::IndexFormat::Uint32);
+        let num_indices = object.index_buffer.size() as u32 / std::mem::size_of::<u32>() as u32;
         // draw
         render_pass.draw_indexed(0..num_indices, 0, 0..1);
     }
+
+    fn set_layer_pipeline<'a>(
+        &'a self,
+        layer: RenderLayer,
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        render_pass.set_pipeline(
+            &self
+                .render_groups
+                .get(render_group_name(layer))
+                .expect("No render group set for the render layer!")
+                .pipeline,
+        );
+    }
+
+    /// Write the indices of the translucent layers that weren't sorted yet, or
+    /// were sorted for another block near the camera, in back to front order.
+    fn sort_translucent_quads(
+        &mut self,
+        gfx: &GraphicsContext,
+        config: &ChunkConfig,
+        camera: glam::Vec3,
+    ) {
+        let depth = config.depth;
+        let camera_chunk = chunk_position(config, &player_to_position(&camera.into()));
+        for (chunk_pos, quads) in self.translucent_quads.iter_mut() {
+            let corner = glam::vec3(chunk_pos.x as f32, chunk_pos.y as f32, chunk_pos.z as f32)
+                * depth as f32;
+            let local = camera - corner;
+            let block = player_to_position(&local.into());
+
+            let near = [
+                chunk_pos.x - camera_chunk.x,
+                chunk_pos.y - camera_chunk.y,
+                chunk_pos.z - camera_chunk.z,
+            ]
+            .iter()
+            .all(|offset| offset.abs() <= TRANSLUCENT_SORT_DISTANCE);
+            let sorted = match quads.sorted_for {
+                Some(sorted_for) => !near || sorted_for == block,
+                None => false,
+            };
+            if sorted {
+                continue;
+            }
+
+            let object = self
+                .render_objects
+                .get(chunk_pos)
+                .and_then(|objects| objects[RenderLayer::Translucent as usize].as_ref());
+            if let Some(object) = object {
+                let indices = quads.sort(local);
+                gfx.queue
+                    .write_buffer(&object.index_buffer, 0, bytemuck::cast_slice(&indices));
+                quads.sorted_for = Some(block);
+            }
+        }
+    }
+
+    /// Draw a render layer of the chunks, in order.
+    fn render_layer<'a>(
+        &'a self,
+        layer: RenderLayer,
+        chunks: &[ChunkPos],
+        render_pass: &mut wgpu::RenderPass<'a>,
+    ) {
+        self.set_layer_pipeline(layer, render_pass);
+        for chunk_pos in chunks {
+            self.render_chunk(chunk_pos, layer, render_pass);
+        }
+    }
 }
 
 fn calculate_frustum_planes(renderer: &ChunkRenderPass) -> [glam::Vec4; 6] {
@@ -114,18 +275,18 @@ fn calculate_frustum_planes(renderer: &ChunkRenderPass) -> [glam::Vec4; 6] {
 /// Code is a mix of ChatGPT code and the article found [here](https://iquilezles.org/articles/frustumcorrect/).
 fn is_chunk_inside_frustum(
     config: &ChunkConfig,
-    chunk: &Position,
+    chunk: &ChunkPos,
     frustum_planes: &[glam::Vec4; 6],
 ) -> bool {
     let min = (
-        chunk.0 * config.depth,
-        chunk.1 * config.depth,
-        chunk.2 * config.depth,
+        chunk.x * config.depth,
+        chunk.y * config.depth,
+        chunk.z * config.depth,
     );
     let max = (
-        chunk.0 * config.depth + config.depth,
-        chunk.1 * config.depth + config.depth,
-        chunk.2 * config.depth + config.depth,
+        chunk.x * config.depth + config.depth,
+        chunk.y * config.depth + config.depth,
+        chunk.z * config.depth + config.depth,
     );
 
     for plane in frustum_planes {
@@ -188,6 +349,7 @@ fn is_chunk_inside_frustum(
 impl RenderPass<GameData> for ChunkRenderPass {
     fn render(
         &mut self,
+        gfx: &GraphicsContext,
         data: &mut GameData,
         views: RenderPassViews,
         _delta: f64,
@@ -199,9 +361,16 @@ impl RenderPass<GameData> for ChunkRenderPass {
             .depth
             .expect("No depth attachment specified on Object Render Pass...");
 
+        let player = data.physics_engine.get_rigid_body("player".to_string());
+        if player.is_none() {
+            return Ok(());
+        }
+        let pos = player.unwrap().translation();
+        let camera = camera_position(&data.physics_engine);
+        self.sort_translucent_quads(gfx, &data.chunk_config, camera);
+
         let mut encoder: wgpu::CommandEncoder =
-            window_state()
-                .device
+            gfx.device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some("Object Render Pass"),
                 });
@@ -225,19 +394,7 @@ impl RenderPass<GameData> for ChunkRenderPass {
                     stencil_ops: None,
                 }),
             });
-            render_pass.set_pipeline(
-                &self
-                    .render_groups
-                    .get("chunk_render_group")
-                    .unwrap()
-                    .pipeline,
-            );
-
-            let player = data.physics_engine.get_rigid_body("player".to_string());
-            if player.is_none() {
-                return Ok(());
-            }
-            let pos = player.unwrap().translation();
+            self.set_layer_pipeline(RenderLayer::Opaque, &mut render_pass);
 
             // let facing = calculate_frustum_bounds(&data.player);
             let frustum_planes = calculate_frustum_planes(&self);
@@ -252,10 +409,9 @@ impl RenderPass<GameData> for ChunkRenderPass {
 
             /*
             // Naive approach to rendering, just frustum culling
-            for chunk_id in data.loaded_chunks.keys() {
-                let chunk_pos = chunk_pos_from_id(chunk_id);
-                if is_chunk_inside_frustum(&data.chunk_config, &chunk_pos, &frustum_planes) {
-                    self.render_chunk(&chunk_pos, &mut render_pass);
+            for chunk_pos in data.loaded_chunks.keys() {
+                if is_chunk_inside_frustum(&data.chunk_config, chunk_pos, &frustum_planes) {
+                    self.render_chunk(chunk_pos, &mut render_pass);
                     data.drawn_chunks += 1;
                 } else {
                     data.chunks_removed_by_visibility += 1;
@@ -263,10 +419,10 @@ impl RenderPass<GameData> for ChunkRenderPass {
             }
             */
 
-            let mut search_queue: VecDeque<(Position, Option<Side>, Vec<Side>)> = VecDeque::new();
+            let mut search_queue: VecDeque<(ChunkPos, Option<Side>, Vec<Side>)> = VecDeque::new();
             search_queue.push_back((start_chunk_pos, None, vec![]));
-            let mut visited: Vec<(Side, Position)> = Vec::new();
-            let mut drawn_chunks: Vec<Position> = Vec::new();
+            let mut visited: Vec<(Side, ChunkPos)> = Vec::new();
+            let mut drawn_chunks: Vec<ChunkPos> = Vec::new();
 
             while !search_queue.is_empty() {
                 // the current chunk
@@ -277,8 +433,8 @@ impl RenderPass<GameData> for ChunkRenderPass {
 
                 // do what we need to do to the current chunk, ie. render it
                 if !drawn_chunks.contains(&chunk_pos) {
-                    self.render_chunk(&chunk_pos, &mut render_pass);
-                    drawn_chunks.push(chunk_pos.clone());
+                    self.render_chunk(&chunk_pos, RenderLayer::Opaque, &mut render_pass);
+                    drawn_chunks.push(chunk_pos);
                     data.drawn_chunks = data.drawn_chunks + 1;
                 }
 
@@ -316,7 +472,7 @@ impl RenderPass<GameData> for ChunkRenderPass {
                             */
 
                             // get the graph from the parent chunk
-                            if let Some(graph) = data.visibility_graphs.get(&chunk_id(&chunk_pos)) {
+                            if let Some(graph) = data.visibility_graphs.get(&chunk_pos) {
                                 // if we can't see through the chunk to the neighbors side
                                 // then don't queue it up
 
@@ -338,19 +494,35 @@ impl RenderPass<GameData> for ChunkRenderPass {
 
                         // push back this neighbor
                         search_queue.push_back((
-                            next_chunk_pos.clone(),
+                            next_chunk_pos,
                             Some(next_side.opposite()),
                             next_constraints,
                         ));
                     });
             }
 
+            // cutout faces are either drawn or discarded, so their order doesn't matter
+            self.render_layer(RenderLayer::Cutout, &drawn_chunks, &mut render_pass);
+
+            // translucent faces blend with what is already drawn behind them, so
+            // they go last and the farthest chunks are drawn first, with their
+            // quads sorted back to front
+            let depth = data.chunk_config.depth as f32;
+            let distance = |chunk_pos: &ChunkPos| {
+                let center = glam::vec3(chunk_pos.x as f32, chunk_pos.y as f32, chunk_pos.z as f32)
+                    * depth
+                    + depth / 2.0;
+                center.distance_squared(camera)
+            };
+            drawn_chunks.sort_by(|a, b| distance(b).total_cmp(&distance(a)));
+            self.render_layer(RenderLayer::Translucent, &drawn_chunks, &mut render_pass);
+
             /*
 
             // set up a search queue, start with the chunk the player is in.
-            let mut visited = Vec::<(Side, Position)>::new();
+            let mut visited = Vec::<(Side, ChunkPos)>::new();
             let mut chunks_to_draw = Vec::new();
-            let mut search_queue = VecDeque::<(Option<Side>, Position, Vec<Side>)>::from([(None, start_chunk_pos, vec![])]);
+            let mut search_queue = VecDeque::<(Option<Side>, ChunkPos, Vec<Side>)>::from([(None, start_chunk_pos, vec![])]);
             let mut nodes_traversed = 0;
 
             while !search_queue.is_empty() {
@@ -358,7 +530,7 @@ impl RenderPass<GameData> for ChunkRenderPass {
                     .pop_front()
                     .expect("Queue was made unexpectedly empty");
 
-                if !data.loaded_chunks.contains_key(&chunk_id(&chunk_pos)) {
+                if !data.loaded_chunks.contains_key(&chunk_pos) {
                     continue;
                 }
 
@@ -424,7 +596,7 @@ impl RenderPass<GameData> for ChunkRenderPass {
                         // check the chunk's visibility graph to see if we can reach it.
                         let visibility_graph = data
                             .visibility_graphs
-                            .get(&chunk_id(&chunk_pos)) // might need to use chunk vis graph, not next chunk
+                            .get(&chunk_pos) // might need to use chunk vis graph, not next chunk
                             .expect("Chunk is loaded, so visibility graph should be loaded too.");
                         if next_chunk_pos != start_chunk_pos {
                             if let Some(side) = from_side {
@@ -454,10 +626,43 @@ impl RenderPass<GameData> for ChunkRenderPass {
             */
         }
 
-        window_state()
-            .queue
-            .submit(std::iter::once(encoder.finish()));
+        gfx.queue.submit(std::iter::once(encoder.finish()));
         // println!("{}", instant::now() - now);
         Ok(())
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A square facing up at the height, from x and z at `from` to 1 past it.
+    fn square(vertices: &mut Vec<Vertex>, indices: &mut Vec<u32>, from: [f32; 2], height: f32) {
+        let first = vertices.len() as u32;
+        for (x, z) in [(0.0, 0.0), (1.0, 0.0), (1.0, 1.0), (0.0, 1.0)] {
+            let position = [from[0] + x, height, from[1] + z];
+            vertices.push(Vertex::from(position, [0.0, 0.0], 1.0));
+        }
+        indices.extend([0, 1, 2, 2, 3, 0].map(|i| first + i));
+    }
+
+    #[test]
+    fn translucent_sort_test() {
+        let (mut vertices, mut indices) = (Vec::new(), Vec::new());
+        square(&mut vertices, &mut indices, [2.0, 2.0], 4.0);
+        square(&mut vertices, &mut indices, [2.0, 2.0], 8.0);
+        square(&mut vertices, &mut indices, [6.0, 2.0], 6.0);
+        let mut quads = TranslucentQuads::new(&vertices, &indices);
+        assert_eq!(quads.quads[0].0, [2.5, 4.0, 2.5]);
+
+        // the quad nearest to the camera is drawn last
+        let first_index = |indices: &[u32]| indices.chunks(6).map(|q| q[0]).collect::<Vec<_>>();
+        let sorted = quads.sort(glam::vec3(2.5, 12.0, 2.5));
+        assert_eq!(sorted.len(), indices.len());
+        assert_eq!(first_index(&sorted), [0, 8, 4]);
+        let sorted = quads.sort(glam::vec3(2.5, 0.0, 2.5));
+        assert_eq!(first_index(&sorted), [4, 8, 0]);
+        let sorted = quads.sort(glam::vec3(20.0, 6.0, 2.5));
+        assert_eq!(first_index(&sorted)[2], 8);
+    }
+}
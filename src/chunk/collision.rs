@@ -1,11 +1,11 @@
 use rapier3d::prelude::*;
 
-use super::{ChunkConfig, ChunkData, Position};
+use super::{ChunkConfig, ChunkData, ChunkPos};
 
 /// creates a collider based on the chunk
 pub fn calculate_collider(
     chunk: &ChunkData,
-    chunk_pos: &Position,
+    chunk_pos: &ChunkPos,
     config: &ChunkConfig,
 ) -> Collider {
     let mut collider_data = Vec::new();
@@ -13,14 +13,12 @@ pub fn calculate_collider(
     for x in 0..config.depth {
         for y in 0..config.depth {
             for z in 0..config.depth {
-                if let Some(block_id) = chunk.get(&(x, y, z)) {
-                    if let Some(block) = config.dict.get(block_id) {
-                        if !block.transparent {
-                            collider_data.push((
-                                Isometry::translation(x as f32, y as f32, z as f32),
-                                SharedShape::cuboid(0.5, 0.5, 0.5),
-                            ));
-                        }
+                if let Some(block) = config.dict.get(&chunk.get(&(x, y, z))) {
+                    if block.collision {
+                        collider_data.push((
+                            Isometry::translation(x as f32, y as f32, z as f32),
+                            SharedShape::cuboid(0.5, 0.5, 0.5),
+                        ));
                     }
                 }
             }
@@ -31,12 +29,72 @@ pub fn calculate_collider(
         return ColliderBuilder::ball(0.5).build();
     }
 
-    let cx = chunk_pos.0 as f32 * config.depth as f32;
-    let cy = chunk_pos.1 as f32 * config.depth as f32;
-    let cz = chunk_pos.2 as f32 * config.depth as f32;
+    let cx = chunk_pos.x as f32 * config.depth as f32;
+    let cy = chunk_pos.y as f32 * config.depth as f32;
+    let cz = chunk_pos.z as f32 * config.depth as f32;
     let translation = vector![cx, cy, cz]; // Isometry3::translation(cx, cy, cz).to_matrix();
     ColliderBuilder::compound(collider_data)
         .translation(translation)
         .friction(0.0)
         .build()
 }
+
+/// creates a sensor collider for the fluids in the chunk, the player
+/// moves through it but can tell when they are swimming
+pub fn calculate_fluid_collider(
+    chunk: &ChunkData,
+    chunk_pos: &ChunkPos,
+    config: &ChunkConfig,
+) -> Option<Collider> {
+    let mut collider_data = Vec::new();
+
+    for x in 0..config.depth {
+        for y in 0..config.depth {
+            for z in 0..config.depth {
+                if let Some(block) = config.dict.get(&chunk.get(&(x, y, z))) {
+                    if block.fluid.is_some() {
+                        collider_data.push((
+                            Isometry::translation(x as f32, y as f32, z as f32),
+                            SharedShape::cuboid(0.5, 0.5, 0.5),
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    if collider_data.is_empty() {
+        return None;
+    }
+
+    let translation = vector![
+        chunk_pos.x as f32 * config.depth as f32,
+        chunk_pos.y as f32 * config.depth as f32,
+        chunk_pos.z as f32 * config.depth as f32
+    ];
+    Some(
+        ColliderBuilder::compound(collider_data)
+            .translation(translation)
+            .sensor(true)
+            .build(),
+    )
+}
+
+/// The colliders of a chunk, the blocks the player collides with and the
+/// fluids they can swim in.
+pub struct ChunkColliders {
+    pub solid: Collider,
+    pub fluid: Option<Collider>,
+}
+
+/// creates both colliders of the chunk
+pub fn calculate_colliders(
+    chunk: &ChunkData,
+    chunk_pos: &ChunkPos,
+    config: &ChunkConfig,
+) -> ChunkColliders {
+    ChunkColliders {
+        solid: calculate_collider(chunk, chunk_pos, config),
+        fluid: calculate_fluid_collider(chunk, chunk_pos, config),
+    }
+}
@@ -1,529 +1,510 @@
 use crate::chunk::{meshing::Vertex, ChunkConfig, ChunkData};
 
-use super::Position;
+use super::{
+    block::RenderLayer,
+    culling::Side,
+    lighting::{LightChannel, LightData},
+    offset_position, Position,
+};
 
 pub fn is_transparent(chunk: &ChunkData, chunk_config: &ChunkConfig, position: &Position) -> bool {
-    let block = chunk.get(&position).unwrap_or(&u32::MAX);
+    let block = chunk.get(position);
     chunk_config
         .dict
         .get(&block)
         .map_or(true, |b| b.transparent)
 }
 
-fn ao(
+/// Whether a face of the block at the position can be seen. The block in front
+/// of it has to be transparent, and faces between two blocks of the same
+/// translucent kind, like two blocks of water of any level, are hidden.
+pub fn is_face_visible(
     chunk: &ChunkData,
     chunk_config: &ChunkConfig,
-    p1: &Position,
-    p2: &Position,
-    p3: &Position,
-) -> f32 {
-    let mut output = 1.0;
-
-    if !is_transparent(chunk, chunk_config, &(p1.0, p1.1 + 1, p1.2))
-        || !is_transparent(chunk, chunk_config, &(p2.0, p2.1 + 1, p2.2))
-        || !is_transparent(chunk, chunk_config, &(p3.0, p3.1 + 1, p3.2))
-    {
-        output = 0.5;
+    position: &Position,
+    face: &Face,
+) -> bool {
+    let neighbor = offset_position(position, &face.normal);
+    if !is_transparent(chunk, chunk_config, &neighbor) {
+        return false;
     }
 
-    output
+    let block_id = chunk.get(position);
+    let translucent = chunk_config
+        .dict
+        .get(&block_id)
+        .is_some_and(|block| block.layer == RenderLayer::Translucent);
+    // the levels of a fluid are all the same kind as its source
+    let kind = |block_id: u32| {
+        chunk_config
+            .dict
+            .get(&block_id)
+            .and_then(|block| block.fluid)
+            .map_or(block_id, |fluid| fluid.source)
+    };
+    !(translucent && kind(chunk.get(&neighbor)) == kind(block_id))
 }
 
-pub fn cube_model(
-    chunk: &ChunkData,
-    chunk_config: &ChunkConfig,
-    position: &(i32, i32, i32),
-    vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
-) {
-    let dict = &chunk_config.dict;
-
-    let (p0, p1, p2) = position;
-    /*
-    let relative_position = (
-        get_local_block_pos(chunk_config, p0),
-        get_local_block_pos(chunk_config, p1),
-        get_local_block_pos(chunk_config, p2),
-    );
-    */
+/// A face of the cube, all positions are relative to the block.
+pub struct Face {
+    /// Side of the block the face is on.
+    pub side: Side,
+    /// Offset to the neighbor that has to be transparent for the face to be seen.
+    pub normal: Position,
+    /// Corners of the face.
+    pub corners: [Position; 4],
+    /// Corner of the block's texture at each corner of the face, side faces
+    /// have the top of the texture at the top of the block.
+    pub uvs: [[f32; 2]; 4],
+    /// Blocks that darken each corner when they aren't transparent, the two
+    /// blocks on the sides of the corner and then the block diagonal to it,
+    /// all in the plane in front of the face.
+    pub occluders: [[Position; 3]; 4],
+    /// The corners of the face's two triangles, split between corners 0 and 3.
+    pub indices: [u32; 6],
+    /// The same triangles split between corners 1 and 2, used when that
+    /// diagonal is brighter so the shading doesn't depend on the split.
+    pub flipped_indices: [u32; 6],
+}
 
-    // get target block info, ~~if air then return~~ won't be air b/c air's model isn't this method
-    let block = chunk.get(&position).unwrap();
-    let target_block = dict.get(&block);
-    let uv = target_block.unwrap().uv;
+pub const FACES: [Face; 6] = [
+    // top
+    Face {
+        side: Side::TOP,
+        normal: (0, 1, 0),
+        corners: [(0, 1, 1), (1, 1, 1), (0, 1, 0), (1, 1, 0)],
+        uvs: [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+        occluders: [
+            [(-1, 1, 0), (0, 1, 1), (-1, 1, 1)],
+            [(1, 1, 0), (0, 1, 1), (1, 1, 1)],
+            [(0, 1, -1), (-1, 1, 0), (-1, 1, -1)],
+            [(1, 1, 0), (0, 1, -1), (1, 1, -1)],
+        ],
+        indices: [0, 3, 2, 0, 1, 3],
+        flipped_indices: [1, 3, 2, 2, 0, 1],
+    },
+    // bottom
+    Face {
+        side: Side::BOTTOM,
+        normal: (0, -1, 0),
+        corners: [(0, 0, 1), (1, 0, 1), (0, 0, 0), (1, 0, 0)],
+        uvs: [[0.0, 0.0], [1.0, 0.0], [0.0, 1.0], [1.0, 1.0]],
+        occluders: [
+            [(-1, -1, 0), (0, -1, 1), (-1, -1, 1)],
+            [(1, -1, 0), (0, -1, 1), (1, -1, 1)],
+            [(-1, -1, 0), (0, -1, -1), (-1, -1, -1)],
+            [(1, -1, 0), (0, -1, -1), (1, -1, -1)],
+        ],
+        indices: [0, 2, 3, 0, 3, 1],
+        flipped_indices: [2, 3, 1, 1, 0, 2],
+    },
+    // front
+    Face {
+        side: Side::FRONT,
+        normal: (0, 0, 1),
+        corners: [(0, 0, 1), (1, 0, 1), (0, 1, 1), (1, 1, 1)],
+        uvs: [[0.0, 1.0], [1.0, 1.0], [0.0, 0.0], [1.0, 0.0]],
+        occluders: [
+            [(-1, 0, 1), (0, -1, 1), (-1, -1, 1)],
+            [(1, 0, 1), (0, -1, 1), (1, -1, 1)],
+            [(0, 1, 1), (-1, 0, 1), (-1, 1, 1)],
+            [(0, 1, 1), (1, 0, 1), (1, 1, 1)],
+        ],
+        indices: [0, 3, 2, 0, 1, 3],
+        flipped_indices: [1, 3, 2, 2, 0, 1],
+    },
+    // back
+    Face {
+        side: Side::BACK,
+        normal: (0, 0, -1),
+        corners: [(0, 0, 0), (1, 0, 0), (0, 1, 0), (1, 1, 0)],
+        uvs: [[1.0, 1.0], [0.0, 1.0], [1.0, 0.0], [0.0, 0.0]],
+        occluders: [
+            [(-1, 0, -1), (0, -1, -1), (-1, -1, -1)],
+            [(1, 0, -1), (0, -1, -1), (1, -1, -1)],
+            [(0, 1, -1), (-1, 0, -1), (-1, 1, -1)],
+            [(0, 1, -1), (1, 0, -1), (1, 1, -1)],
+        ],
+        indices: [0, 2, 3, 0, 3, 1],
+        flipped_indices: [2, 3, 1, 1, 0, 2],
+    },
+    // right
+    Face {
+        side: Side::RIGHT,
+        normal: (1, 0, 0),
+        corners: [(1, 0, 1), (1, 1, 1), (1, 0, 0), (1, 1, 0)],
+        uvs: [[0.0, 1.0], [0.0, 0.0], [1.0, 1.0], [1.0, 0.0]],
+        occluders: [
+            [(1, 0, 1), (1, -1, 0), (1, -1, 1)],
+            [(1, 1, 0), (1, 0, 1), (1, 1, 1)],
+            [(1, 0, -1), (1, -1, 0), (1, -1, -1)],
+            [(1, 1, 0), (1, 0, -1), (1, 1, -1)],
+        ],
+        indices: [0, 3, 1, 0, 2, 3],
+        flipped_indices: [2, 3, 1, 1, 0, 2],
+    },
+    // left
+    Face {
+        side: Side::LEFT,
+        normal: (-1, 0, 0),
+        corners: [(0, 0, 1), (0, 1, 1), (0, 0, 0), (0, 1, 0)],
+        uvs: [[1.0, 1.0], [1.0, 0.0], [0.0, 1.0], [0.0, 0.0]],
+        occluders: [
+            [(-1, 0, 1), (-1, -1, 0), (-1, -1, 1)],
+            [(-1, 1, 0), (-1, 0, 1), (-1, 1, 1)],
+            [(-1, 0, -1), (-1, -1, 0), (-1, -1, -1)],
+            [(-1, 1, 0), (-1, 0, -1), (-1, 1, -1)],
+        ],
+        indices: [0, 1, 3, 0, 3, 2],
+        flipped_indices: [1, 3, 2, 2, 0, 1],
+    },
+];
 
-    // get surrounding block transparency info
-    let top_block = is_transparent(chunk, chunk_config, &(*p0, p1 + 1, *p2));
-    let bottom_block = is_transparent(chunk, chunk_config, &(*p0, p1 - 1, *p2));
+/// Brightness of a corner by how many of its occluders let light through,
+/// from fully occluded to fully open.
+const AO_LEVELS: [f32; 4] = [0.5, 4.0 / 6.0, 5.0 / 6.0, 1.0];
 
-    let front_block = is_transparent(chunk, chunk_config, &(*p0, *p1, p2 + 1));
-    let back_block = is_transparent(chunk, chunk_config, &(*p0, *p1, p2 - 1));
+fn ao(
+    chunk: &ChunkData,
+    chunk_config: &ChunkConfig,
+    position: &Position,
+    occluders: &[Position; 3],
+) -> f32 {
+    let [side1, side2, corner] =
+        occluders.map(|o| !is_transparent(chunk, chunk_config, &offset_position(position, &o)));
 
-    let right_block = is_transparent(chunk, chunk_config, &(p0 + 1, *p1, *p2));
-    let left_block = is_transparent(chunk, chunk_config, &(p0 - 1, *p1, *p2));
+    // with both sides blocked the corner can't be seen, whatever is diagonal to it
+    let level = if side1 && side2 {
+        0
+    } else {
+        3 - (side1 as usize + side2 as usize + corner as usize)
+    };
 
-    // top
-    if top_block {
-        top_face(chunk, chunk_config, position, &uv, vertices, indices);
-    }
+    AO_LEVELS[level]
+}
 
-    // bottom
-    if bottom_block {
-        bottom_face(chunk, chunk_config, position, &uv, vertices, indices);
-    }
+/// Light at a corner of a face, the average light of the block in front of the
+/// face and the corner's occluders that let light through. Both channels are
+/// packed into a byte, skylight in the upper 4 bits.
+fn corner_light(
+    chunk: &ChunkData,
+    light: &LightData,
+    chunk_config: &ChunkConfig,
+    position: &Position,
+    face: &Face,
+    occluders: &[Position; 3],
+) -> u8 {
+    let front = offset_position(position, &face.normal);
+    let cells = std::iter::once(front).chain(
+        occluders
+            .iter()
+            .map(|o| offset_position(position, o))
+            .filter(|cell| is_transparent(chunk, chunk_config, cell)),
+    );
 
-    // front
-    if front_block {
-        front_face(chunk, chunk_config, position, &uv, vertices, indices);
+    let mut count = 0;
+    let mut sky = 0;
+    let mut block = 0;
+    for cell in cells {
+        count += 1;
+        sky += light.get(&cell, LightChannel::Sky) as u32;
+        block += light.get(&cell, LightChannel::Block) as u32;
     }
 
-    // back
-    if back_block {
-        back_face(chunk, chunk_config, position, &uv, vertices, indices);
-    }
+    let average = |sum: u32| ((sum + count / 2) / count) as u8;
+    average(sky) << 4 | average(block)
+}
 
-    if right_block {
-        right_face(chunk, chunk_config, position, &uv, vertices, indices);
-    }
+/// How each corner of a block's face is shaded.
+pub struct FaceShade {
+    /// Ambient occlusion of each corner.
+    pub ao: [f32; 4],
+    /// Light of each corner, skylight in the upper 4 bits.
+    pub light: [u8; 4],
+}
 
-    if left_block {
-        left_face(chunk, chunk_config, position, &uv, vertices, indices);
+impl FaceShade {
+    /// Whether every corner is shaded the same.
+    pub fn is_uniform(&self) -> bool {
+        self.ao.iter().all(|ao| *ao == self.ao[0])
+            && self.light.iter().all(|light| *light == self.light[0])
     }
 }
 
-fn top_face(
+/// Get the ambient occlusion and light of each corner of a block's face.
+pub fn face_shade(
     chunk: &ChunkData,
+    light: &LightData,
     chunk_config: &ChunkConfig,
-    position: &(i32, i32, i32),
-    uv: &[f32; 2],
-    vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
-) {
-    let (i, j, k) = position;
-    let x = *i as f32;
-    let y = *j as f32;
-    let z = *k as f32;
-    let u = uv[0];
-    let v = uv[1];
-    let duv = chunk_config.uv_size;
-
-    let last_index = vertices.len() as u16;
-    indices.extend_from_slice(&[
-        last_index + 0,
-        last_index + 3,
-        last_index + 2,
-        last_index + 0,
-        last_index + 1,
-        last_index + 3,
-    ]);
-    vertices.extend_from_slice(&[
-        Vertex::from(
-            [x, y + 1.0, z + 1.0],
-            [u, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j, *k),
-                &(*i, *j, *k + 1),
-                &(*i - 1, *j, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y + 1.0, z + 1.0],
-            [u + duv, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j, *k),
-                &(*i, *j, *k + 1),
-                &(*i + 1, *j, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y + 1.0, z],
-            [u, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i, *j, *k - 1),
-                &(*i - 1, *j, *k),
-                &(*i - 1, *j, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y + 1.0, z],
-            [u + duv, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j, *k),
-                &(*i, *j, *k - 1),
-                &(*i + 1, *j, *k - 1),
-            ),
-        ),
-    ]);
+    position: &Position,
+    face: &Face,
+) -> FaceShade {
+    FaceShade {
+        ao: face
+            .occluders
+            .map(|occluders| ao(chunk, chunk_config, position, &occluders)),
+        light: face
+            .occluders
+            .map(|occluders| corner_light(chunk, light, chunk_config, position, face, &occluders)),
+    }
 }
 
-fn bottom_face(
+pub fn cube_model(
     chunk: &ChunkData,
+    light: &LightData,
     chunk_config: &ChunkConfig,
     position: &(i32, i32, i32),
-    uv: &[f32; 2],
     vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
+    indices: &mut Vec<u32>,
 ) {
-    let (i, j, k) = position;
-    let x = *i as f32;
-    let y = *j as f32;
-    let z = *k as f32;
-    let u = uv[0];
-    let v = uv[1];
-    let duv = chunk_config.uv_size;
+    // get target block info, ~~if air then return~~ won't be air b/c air's model isn't this method
+    let block = chunk.get(position);
+    let block = chunk_config.dict.get(&block).unwrap();
 
-    let last_index = vertices.len() as u16;
-    indices.extend_from_slice(&[
-        last_index + 0,
-        last_index + 2,
-        last_index + 3,
-        last_index + 0,
-        last_index + 3,
-        last_index + 1,
-    ]);
-    vertices.extend_from_slice(&[
-        Vertex::from(
-            [x, y, z + 1.0],
-            [u, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j - 2, *k),
-                &(*i, *j - 2, *k + 1),
-                &(*i - 1, *j - 2, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y, z + 1.0],
-            [u + duv, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j - 2, *k),
-                &(*i, *j - 2, *k + 1),
-                &(*i + 1, *j - 2, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y, z],
-            [u, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j - 2, *k),
-                &(*i, *j - 2, *k - 1),
-                &(*i - 1, *j - 2, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y, z],
-            [u + duv, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j - 2, *k),
-                &(*i, *j - 2, *k - 1),
-                &(*i + 1, *j - 2, *k - 1),
-            ),
-        ),
-    ]);
+    // only add the faces next to a transparent block
+    for face in FACES.iter() {
+        if is_face_visible(chunk, chunk_config, position, face) {
+            let shade = face_shade(chunk, light, chunk_config, position, face);
+            let uv = block.uv(face.side);
+            push_face(chunk_config, position, face, &uv, &shade, vertices, indices);
+        }
+    }
 }
 
-fn front_face(
-    chunk: &ChunkData,
+/// Add a face of a block, textured with the block's uv.
+pub fn push_face(
     chunk_config: &ChunkConfig,
-    position: &(i32, i32, i32),
+    position: &Position,
+    face: &Face,
     uv: &[f32; 2],
+    shade: &FaceShade,
     vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
+    indices: &mut Vec<u32>,
 ) {
-    let (i, j, k) = position;
-    let x = *i as f32;
-    let y = *j as f32;
-    let z = *k as f32;
-    let u = uv[0];
-    let v = uv[1];
     let duv = chunk_config.uv_size;
 
-    let last_index = vertices.len() as u16;
-    indices.extend_from_slice(&[
-        last_index + 0,
-        last_index + 3,
-        last_index + 2,
-        last_index + 0,
-        last_index + 1,
-        last_index + 3,
-    ]);
-    vertices.extend_from_slice(&[
-        Vertex::from(
-            [x, y, z + 1.0],
-            [u, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j - 1, *k + 1),
-                &(*i, *j - 2, *k + 1),
-                &(*i - 1, *j - 2, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y, z + 1.0],
-            [u + duv, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j - 1, *k + 1),
-                &(*i, *j - 2, *k + 1),
-                &(*i + 1, *j - 2, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y + 1.0, z + 1.0],
-            [u, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i, *j, *k + 1),
-                &(*i - 1, *j - 1, *k + 1),
-                &(*i - 1, *j, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y + 1.0, z + 1.0],
-            [u + duv, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i, *j, *k + 1),
-                &(*i + 1, *j - 1, *k + 1),
-                &(*i + 1, *j, *k + 1),
-            ),
-        ),
-    ]);
+    // split the quad along its brighter diagonal, so the occlusion fades the
+    // same way whichever corner it is in
+    let ao = &shade.ao;
+    let face_indices = if ao[1] + ao[2] > ao[0] + ao[3] {
+        &face.flipped_indices
+    } else {
+        &face.indices
+    };
+
+    let last_index = vertices.len() as u32;
+    indices.extend(face_indices.iter().map(|i| last_index + i));
+    let corners = face.corners.iter().zip(face.uvs.iter());
+    for ((corner, face_uv), (ao, light)) in corners.zip(shade.ao.iter().zip(shade.light)) {
+        let (x, y, z) = offset_position(position, corner);
+        vertices.push(
+            Vertex::from(
+                [x as f32, y as f32, z as f32],
+                [uv[0] + duv * face_uv[0], uv[1] + duv * face_uv[1]],
+                *ao,
+            )
+            .lit(light),
+        );
+    }
 }
 
-fn back_face(
-    chunk: &ChunkData,
-    chunk_config: &ChunkConfig,
-    position: &(i32, i32, i32),
-    uv: &[f32; 2],
-    vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
-) {
-    let (i, j, k) = position;
-    let x = *i as f32;
-    let y = *j as f32;
-    let z = *k as f32;
-    let u = uv[0];
-    let v = uv[1];
-    let duv = chunk_config.uv_size;
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::generation::ao_test;
 
-    let last_index = vertices.len() as u16;
-    indices.extend_from_slice(&[
-        last_index + 0,
-        last_index + 2,
-        last_index + 3,
-        last_index + 0,
-        last_index + 3,
-        last_index + 1,
-    ]);
-    vertices.extend_from_slice(&[
-        Vertex::from(
-            [x, y, z],
-            [u, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j - 1, *k - 1),
-                &(*i, *j - 2, *k - 1),
-                &(*i - 1, *j - 2, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y, z],
-            [u + duv, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j - 1, *k - 1),
-                &(*i, *j - 2, *k - 1),
-                &(*i + 1, *j - 2, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y + 1.0, z],
-            [u, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i, *j, *k - 1),
-                &(*i - 1, *j - 1, *k - 1),
-                &(*i - 1, *j, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y + 1.0, z],
-            [u + duv, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i, *j, *k - 1),
-                &(*i + 1, *j - 1, *k - 1),
-                &(*i + 1, *j, *k - 1),
-            ),
-        ),
-    ]);
-}
+    /// Mesh a block and get the ambient occlusion levels of the corners of
+    /// each face, from 0 for fully occluded to 3, along with the face indices.
+    fn mesh_ao(chunk: &ChunkData, position: &Position) -> Vec<([u32; 4], Vec<u32>)> {
+        let config = ChunkConfig::new(10, 16, 3);
+        let light = LightData::new(config.depth);
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        cube_model(
+            chunk,
+            &light,
+            &config,
+            position,
+            &mut vertices,
+            &mut indices,
+        );
 
-fn right_face(
-    chunk: &ChunkData,
-    chunk_config: &ChunkConfig,
-    position: &(i32, i32, i32),
-    uv: &[f32; 2],
-    vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
-) {
-    let (i, j, k) = position;
-    let x = *i as f32;
-    let y = *j as f32;
-    let z = *k as f32;
-    let u = uv[0];
-    let v = uv[1];
-    let duv = chunk_config.uv_size;
+        vertices
+            .chunks(4)
+            .zip(indices.chunks(6))
+            .enumerate()
+            .map(|(i, (vertices, indices))| {
+                let ao = [0, 1, 2, 3].map(|c| (vertices[c].data & 0x7) - 3);
+                let indices = indices.iter().map(|index| index - i as u32 * 4).collect();
+                (ao, indices)
+            })
+            .collect()
+    }
 
-    let last_index = vertices.len() as u16;
-    indices.extend_from_slice(&[
-        last_index + 0,
-        last_index + 3,
-        last_index + 1,
-        last_index + 0,
-        last_index + 2,
-        last_index + 3,
-    ]);
-    vertices.extend_from_slice(&[
-        Vertex::from(
-            [x + 1.0, y, z + 1.0],
-            [u, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j - 1, *k + 1),
-                &(*i + 1, *j - 2, *k),
-                &(*i + 1, *j - 2, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y + 1.0, z + 1.0],
-            [u + duv, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j, *k + 1),
-                &(*i + 1, *j, *k),
-                &(*i + 1, *j - 1, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y, z],
-            [u, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j - 1, *k - 1),
-                &(*i + 1, *j - 2, *k),
-                &(*i + 1, *j - 2, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x + 1.0, y + 1.0, z],
-            [u + duv, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i + 1, *j, *k - 1),
-                &(*i + 1, *j, *k),
-                &(*i + 1, *j - 1, *k - 1),
-            ),
-        ),
-    ]);
-}
-fn left_face(
-    chunk: &ChunkData,
-    chunk_config: &ChunkConfig,
-    position: &(i32, i32, i32),
-    uv: &[f32; 2],
-    vertices: &mut Vec<Vertex>,
-    indices: &mut Vec<u16>,
-) {
-    let (i, j, k) = position;
-    let x = *i as f32;
-    let y = *j as f32;
-    let z = *k as f32;
-    let u = uv[0];
-    let v = uv[1];
-    let duv = chunk_config.uv_size;
+    fn unpack(vertex: &Vertex) -> ([u32; 3], [u32; 2]) {
+        let data = vertex.data;
+        (
+            [
+                (data >> 25) & 0x7F,
+                (data >> 18) & 0x7F,
+                (data >> 11) & 0x7F,
+            ],
+            [(data >> 7) & 0xF, (data >> 3) & 0xF],
+        )
+    }
+
+    #[test]
+    fn face_uv_test() {
+        let config = ChunkConfig::new(10, 8, 3);
+        let mut chunk = ChunkData::new(config.depth);
+        // grass, with its top, bottom and side textures at tiles (0, 0), (2, 0) and (3, 0)
+        chunk.set(&(2, 3, 4), 1);
+
+        let mut vertices = Vec::new();
+        let mut indices = Vec::new();
+        let light = LightData::new(config.depth);
+        cube_model(
+            &chunk,
+            &light,
+            &config,
+            &(2, 3, 4),
+            &mut vertices,
+            &mut indices,
+        );
+        assert_eq!(vertices.len(), 6 * 4);
+
+        for (face, vertices) in FACES.iter().zip(vertices.chunks(4)) {
+            let tile = match face.side {
+                Side::TOP => [0, 0],
+                Side::BOTTOM => [2, 0],
+                _ => [3, 0],
+            };
+
+            for (c, vertex) in vertices.iter().enumerate() {
+                let (position, uv) = unpack(vertex);
+                let corner = offset_position(&(2, 3, 4), &face.corners[c]);
+                assert_eq!(
+                    position,
+                    [corner.0 as u32, corner.1 as u32, corner.2 as u32]
+                );
+                assert_eq!(
+                    uv,
+                    [
+                        tile[0] + face.uvs[c][0] as u32,
+                        tile[1] + face.uvs[c][1] as u32
+                    ]
+                );
+
+                // the top of the texture is at the top of side faces
+                if face.normal.1 == 0 {
+                    assert_eq!(uv[1] == tile[1], position[1] == 4);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn ao_fixture_test() {
+        let chunk = ao_test(16);
 
-    let last_index = vertices.len() as u16;
-    indices.extend_from_slice(&[
-        last_index + 0,
-        last_index + 1,
-        last_index + 3,
-        last_index + 0,
-        last_index + 3,
-        last_index + 2,
-    ]);
-    vertices.extend_from_slice(&[
-        Vertex::from(
-            [x, y, z + 1.0],
-            [u, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j - 1, *k + 1),
-                &(*i - 1, *j - 2, *k),
-                &(*i - 1, *j - 2, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y + 1.0, z + 1.0],
-            [u + duv, v],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j, *k + 1),
-                &(*i - 1, *j, *k),
-                &(*i - 1, *j - 1, *k + 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y, z],
-            [u, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j - 1, *k - 1),
-                &(*i - 1, *j - 2, *k),
-                &(*i - 1, *j - 2, *k - 1),
-            ),
-        ),
-        Vertex::from(
-            [x, y + 1.0, z],
-            [u + duv, v + duv],
-            ao(
-                chunk,
-                chunk_config,
-                &(*i - 1, *j, *k - 1),
-                &(*i - 1, *j, *k),
-                &(*i - 1, *j - 1, *k - 1),
-            ),
-        ),
-    ]);
+        // each block sits in a hole in the middle of two rings of blocks, the
+        // faces looking into the rings are fully occluded, and the rest have
+        // one side blocked by a ring
+        let blocks = [
+            ((1, 1, 1), Side::TOP),
+            ((5, 1, 1), Side::FRONT),
+            ((9, 1, 1), Side::RIGHT),
+        ];
+        for (position, ring) in blocks {
+            let faces = mesh_ao(&chunk, &position);
+            assert_eq!(faces.len(), 6);
+            for (face, (ao, _)) in FACES.iter().zip(faces) {
+                let facing_ring = match ring {
+                    Side::TOP => face.normal.1 != 0,
+                    Side::FRONT => face.normal.2 != 0,
+                    _ => face.normal.0 != 0,
+                };
+                let expected = if facing_ring { [0; 4] } else { [1; 4] };
+                assert_eq!(ao, expected, "{:?} {:?}", position, face.side);
+            }
+        }
+
+        // a corner of the ring, open to the sky above
+        let faces = mesh_ao(&chunk, &(0, 2, 0));
+        let (top, _) = &faces[0];
+        assert_eq!(*top, [3; 4]);
+    }
+
+    #[test]
+    fn ao_levels_test() {
+        let top = |chunk: &ChunkData| mesh_ao(chunk, &(4, 4, 4))[0].clone();
+        let mut chunk = ChunkData::new(16);
+        chunk.set(&(4, 4, 4), 2);
+        assert_eq!(top(&chunk), ([3; 4], FACES[0].indices.to_vec()));
+
+        // the block diagonal to the top face's first corner
+        chunk.set(&(3, 5, 5), 2);
+        let (ao, indices) = top(&chunk);
+        assert_eq!(ao, [2, 3, 3, 3]);
+        // split along the brighter diagonal, away from the dark corner
+        assert_eq!(indices, FACES[0].flipped_indices.to_vec());
+
+        chunk.set(&(3, 5, 4), 2);
+        assert_eq!(top(&chunk).0, [1, 3, 2, 3]);
+
+        // both sides blocked, whatever is in the corner
+        chunk.set(&(4, 5, 5), 2);
+        assert_eq!(top(&chunk).0, [0, 2, 2, 3]);
+        chunk.set(&(3, 5, 5), 0);
+        assert_eq!(top(&chunk).0, [0, 2, 2, 3]);
+
+        // occluders of side faces are in front of them, not above them
+        let mut chunk = ChunkData::new(16);
+        chunk.set(&(4, 4, 4), 2);
+        chunk.set(&(4, 5, 5), 2);
+        let faces = mesh_ao(&chunk, &(4, 4, 4));
+        let front = FACES.iter().position(|f| f.side == Side::FRONT).unwrap();
+        assert_eq!(faces[front].0, [3, 3, 2, 2]);
+        for (face, (ao, _)) in FACES.iter().zip(faces.iter()) {
+            if face.side != Side::FRONT && face.side != Side::TOP {
+                assert_eq!(*ao, [3; 4], "{:?}", face.side);
+            }
+        }
+    }
+
+    #[test]
+    fn flipped_indices_test() {
+        for face in FACES.iter() {
+            let corner = |i: u32| {
+                let (x, y, z) = face.corners[i as usize];
+                glam::vec3(x as f32, y as f32, z as f32)
+            };
+            let normal = glam::vec3(
+                face.normal.0 as f32,
+                face.normal.1 as f32,
+                face.normal.2 as f32,
+            );
+            let facing = |triangle: &[u32]| {
+                let (a, b, c) = (
+                    corner(triangle[0]),
+                    corner(triangle[1]),
+                    corner(triangle[2]),
+                );
+                (b - a).cross(c - a).dot(normal).signum()
+            };
+
+            // both splits cover the face with triangles that wind the same way
+            let winding = facing(&face.indices[..3]);
+            for indices in [face.indices, face.flipped_indices] {
+                let mut used = indices.to_vec();
+                used.sort();
+                used.dedup();
+                assert_eq!(used, vec![0, 1, 2, 3]);
+                for triangle in indices.chunks(3) {
+                    assert_eq!(facing(triangle), winding, "{:?}", face.side);
+                }
+            }
+            assert_ne!(face.indices, face.flipped_indices);
+        }
+    }
 }
@@ -2,7 +2,7 @@ use std::collections::{HashMap, VecDeque};
 use std::slice::Iter;
 
 use super::{
-    super::util::vec_set::VecSet, chunk_id, ChunkConfig, ChunkData, ChunkStorage, Position,
+    super::util::vec_set::VecSet, ChunkConfig, ChunkData, ChunkPos, ChunkStorage, Position,
 };
 
 #[derive(Clone, Copy, PartialEq, PartialOrd, Debug)]
@@ -54,7 +54,7 @@ impl Side {
 /// A 6x6 matrix to keep track of which sides we can enter and exit from.
 #[derive(Debug)]
 pub struct VisibilityGraph([[bool; 6]; 6]);
-pub type VisibilityGraphStorage = HashMap<String, VisibilityGraph>;
+pub type VisibilityGraphStorage = HashMap<ChunkPos, VisibilityGraph>;
 
 impl VisibilityGraph {
     pub const EMPTY_GRAPH: VisibilityGraph = VisibilityGraph([
@@ -90,9 +90,8 @@ impl VisibilityGraph {
                     let block = chunk_data.get(&(x, y, z));
 
                     // start at an empty block
-                    if block.is_none()
-                        || config.dict.get(block.unwrap()).unwrap().transparent
-                            && !fill_seeds.contains(&(x, y, z))
+                    if config.dict.get(&block).unwrap().transparent
+                        && !fill_seeds.contains(&(x, y, z))
                     {
                         fill_seeds.insert((x, y, z));
                     }
@@ -185,7 +184,7 @@ fn flood_fill(
         // check if air
         // if not continue
         let block = chunk_data.get(&pos);
-        if block.is_some() && !config.dict.get(block.unwrap()).unwrap().transparent {
+        if !config.dict.get(&block).unwrap().transparent {
             continue;
         }
 
@@ -226,36 +225,36 @@ fn flood_fill(
 }
 
 /// Returns a vector of loaded chunks neighboring the passed in chunk_pos.
-pub fn get_neighbors(loaded_chunks: &ChunkStorage, pos: &Position) -> Vec<(Side, Position)> {
+pub fn get_neighbors(loaded_chunks: &ChunkStorage, pos: &ChunkPos) -> Vec<(Side, ChunkPos)> {
     let mut output = Vec::new();
 
-    let top = (pos.0, pos.1 + 1, pos.2);
-    if loaded_chunks.contains_key(&chunk_id(&top)) {
+    let top = pos.offset(0, 1, 0);
+    if loaded_chunks.contains_key(&top) {
         output.push((Side::TOP, top));
     }
 
-    let bottom = (pos.0, pos.1 - 1, pos.2);
-    if loaded_chunks.contains_key(&chunk_id(&bottom)) {
+    let bottom = pos.offset(0, -1, 0);
+    if loaded_chunks.contains_key(&bottom) {
         output.push((Side::BOTTOM, bottom));
     }
 
-    let left = (pos.0 - 1, pos.1, pos.2);
-    if loaded_chunks.contains_key(&chunk_id(&left)) {
+    let left = pos.offset(-1, 0, 0);
+    if loaded_chunks.contains_key(&left) {
         output.push((Side::LEFT, left));
     }
 
-    let right = (pos.0 + 1, pos.1, pos.2);
-    if loaded_chunks.contains_key(&chunk_id(&right)) {
+    let right = pos.offset(1, 0, 0);
+    if loaded_chunks.contains_key(&right) {
         output.push((Side::RIGHT, right));
     }
 
-    let front = (pos.0, pos.1, pos.2 - 1);
-    if loaded_chunks.contains_key(&chunk_id(&front)) {
+    let front = pos.offset(0, 0, -1);
+    if loaded_chunks.contains_key(&front) {
         output.push((Side::FRONT, front));
     }
 
-    let back = (pos.0, pos.1, pos.2 + 1);
-    if loaded_chunks.contains_key(&chunk_id(&back)) {
+    let back = pos.offset(0, 0, 1);
+    if loaded_chunks.contains_key(&back) {
         output.push((Side::BACK, back));
     }
 
@@ -273,14 +272,14 @@ mod tests {
     #[test]
     fn visibility_graph_full_chunk() {
         let config = create_mock_config();
-        let mut full_chunk_data = ChunkData::new();
+        let mut full_chunk_data = ChunkData::new(config.depth);
 
         // fill the chunk with blocks
         for x in 0..config.depth {
             for y in 0..config.depth {
                 for z in 0..config.depth {
                     // 2 is just some random transparent that we want to check
-                    full_chunk_data.insert((x, y, z), 2);
+                    full_chunk_data.set(&(x, y, z), 2);
                 }
             }
         }
@@ -302,7 +301,7 @@ mod tests {
     #[test]
     fn visibility_graph_empty_chunk() {
         let config = create_mock_config();
-        let empty_chunk_data = ChunkData::new();
+        let empty_chunk_data = ChunkData::new(config.depth);
         let vis_graph = VisibilityGraph::from_chunk(&config, &empty_chunk_data);
 
         // iter over each entry to check if any are false
@@ -316,7 +315,7 @@ mod tests {
     #[test]
     fn visibility_graph_split_x_chunk() {
         let config = create_mock_config();
-        let mut split_chunk_data = ChunkData::new();
+        let mut split_chunk_data = ChunkData::new(config.depth);
 
         // fill the chunk with blocks
         for x in 0..config.depth {
@@ -324,7 +323,7 @@ mod tests {
                 for z in 0..config.depth {
                     // 2 is just some random transparent that we want to check
                     if x == 3 {
-                        split_chunk_data.insert((x, y, z), 2);
+                        split_chunk_data.set(&(x, y, z), 2);
                     }
                 }
             }
@@ -354,7 +353,7 @@ mod tests {
     #[test]
     fn visibility_graph_split_y_chunk() {
         let config = create_mock_config();
-        let mut split_chunk_data = ChunkData::new();
+        let mut split_chunk_data = ChunkData::new(config.depth);
 
         // fill the chunk with blocks
         for x in 0..config.depth {
@@ -362,7 +361,7 @@ mod tests {
                 for z in 0..config.depth {
                     // 2 is just some random transparent that we want to check
                     if y == 3 {
-                        split_chunk_data.insert((x, y, z), 2);
+                        split_chunk_data.set(&(x, y, z), 2);
                     }
                 }
             }
@@ -392,7 +391,7 @@ mod tests {
     #[test]
     fn visibility_graph_split_z_chunk() {
         let config = create_mock_config();
-        let mut split_chunk_data = ChunkData::new();
+        let mut split_chunk_data = ChunkData::new(config.depth);
 
         // fill the chunk with blocks
         for x in 0..config.depth {
@@ -400,7 +399,7 @@ mod tests {
                 for z in 0..config.depth {
                     // 2 is just some random transparent that we want to check
                     if z == 3 {
-                        split_chunk_data.insert((x, y, z), 2);
+                        split_chunk_data.set(&(x, y, z), 2);
                     }
                 }
             }
@@ -0,0 +1,261 @@
+//! Changing the blocks of loaded chunks.
+
+use crate::world::GameData;
+
+use super::lighting::update_light;
+use super::{chunk_position, ChunkConfig, ChunkData, ChunkPos, ChunkStorage, Position};
+
+/// Set the block at a world space position in the chunk it belongs to, and in the
+/// border of every loaded neighbor the position is in.
+/// Returns the chunks that changed, the chunk the block belongs to first. Nothing
+/// changes if that chunk isn't loaded or already has the block.
+pub fn set_block(
+    config: &ChunkConfig,
+    loaded_chunks: &mut ChunkStorage,
+    position: &Position,
+    block_id: u32,
+) -> Vec<ChunkPos> {
+    let owner = chunk_position(config, position);
+    let local_position = |chunk_pos: &ChunkPos| {
+        (
+            position.0 - chunk_pos.x * config.depth,
+            position.1 - chunk_pos.y * config.depth,
+            position.2 - chunk_pos.z * config.depth,
+        )
+    };
+
+    match loaded_chunks.get(&owner) {
+        Some(chunk) if chunk.get(&local_position(&owner)) != block_id => {}
+        _ => return vec![],
+    }
+
+    let mut changed = vec![owner];
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                let chunk_pos = owner.offset(x, y, z);
+                if let Some(chunk) = loaded_chunks.get_mut(&chunk_pos) {
+                    if chunk.set(&local_position(&chunk_pos), block_id) && chunk_pos != owner {
+                        changed.push(chunk_pos);
+                    }
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+/// Set a block in the world, update the light around it, and mark the chunks
+/// it changed as dirty, so they are rebuilt in the background. Fluids around
+/// the block flow on the next fluid step.
+/// Returns false if nothing changed.
+pub fn edit_block(data: &mut GameData, position: &Position, block_id: u32) -> bool {
+    let changed = set_block(
+        &data.chunk_config,
+        &mut data.loaded_chunks,
+        position,
+        block_id,
+    );
+    if changed.is_empty() {
+        return false;
+    }
+
+    data.unsaved_chunks.insert(changed[0]);
+    // the light being computed for the chunk is out of date now
+    if !data.light.contains_key(&changed[0]) {
+        data.unlit_chunks.insert(changed[0]);
+    }
+    data.dirty_chunks.extend(changed);
+    data.dirty_chunks.extend(update_light(
+        &data.chunk_config,
+        &data.loaded_chunks,
+        &mut data.light,
+        position,
+    ));
+    data.fluids.schedule(position);
+
+    true
+}
+
+/// Copy the blocks of `from` into the border of `to`, where `offset` is the
+/// position of `to` relative to `from` in chunks.
+/// Returns true if any block in the border changed.
+fn copy_border(depth: i32, from: &ChunkData, to: &mut ChunkData, offset: &Position) -> bool {
+    // the range of `to`'s local positions that are inside of `from` on an axis
+    let range = |offset: i32| match offset {
+        0 => 0..depth,
+        1 => -1..0,
+        _ => depth..(depth + 1),
+    };
+
+    let mut changed = false;
+    for x in range(offset.0) {
+        for y in range(offset.1) {
+            for z in range(offset.2) {
+                let block_id = from.get(&(
+                    x + offset.0 * depth,
+                    y + offset.1 * depth,
+                    z + offset.2 * depth,
+                ));
+                if to.get(&(x, y, z)) != block_id {
+                    to.set(&(x, y, z), block_id);
+                    changed = true;
+                }
+            }
+        }
+    }
+
+    changed
+}
+
+/// Make the border of a loaded chunk match the blocks of its loaded neighbors,
+/// and the borders of its neighbors match the chunk's blocks.
+/// Returns the chunks whose border changed.
+pub fn sync_borders(
+    config: &ChunkConfig,
+    loaded_chunks: &mut ChunkStorage,
+    chunk_pos: &ChunkPos,
+) -> Vec<ChunkPos> {
+    let mut chunk = match loaded_chunks.remove(chunk_pos) {
+        Some(chunk) => chunk,
+        None => return vec![],
+    };
+
+    let mut changed = Vec::new();
+    let mut chunk_changed = false;
+    for x in -1..=1 {
+        for y in -1..=1 {
+            for z in -1..=1 {
+                if (x, y, z) == (0, 0, 0) {
+                    continue;
+                }
+
+                let neighbor_pos = chunk_pos.offset(x, y, z);
+                if let Some(neighbor) = loaded_chunks.get_mut(&neighbor_pos) {
+                    if copy_border(config.depth, neighbor, &mut chunk, &(-x, -y, -z)) {
+                        chunk_changed = true;
+                    }
+                    if copy_border(config.depth, &chunk, neighbor, &(x, y, z)) {
+                        changed.push(neighbor_pos);
+                    }
+                }
+            }
+        }
+    }
+    loaded_chunks.insert(*chunk_pos, chunk);
+
+    if chunk_changed {
+        changed.insert(0, *chunk_pos);
+    }
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 8, 3)
+    }
+
+    fn create_loaded_chunks(config: &ChunkConfig) -> ChunkStorage {
+        let mut loaded_chunks = ChunkStorage::new();
+        for x in -1..=1 {
+            for y in -1..=1 {
+                for z in -1..=1 {
+                    loaded_chunks.insert(ChunkPos::new(x, y, z), ChunkData::new(config.depth));
+                }
+            }
+        }
+        loaded_chunks
+    }
+
+    #[test]
+    fn set_block_inside_test() {
+        let config = create_mock_config();
+        let mut loaded_chunks = create_loaded_chunks(&config);
+
+        let changed = set_block(&config, &mut loaded_chunks, &(3, 4, 5), 2);
+        assert_eq!(changed, vec![ChunkPos::new(0, 0, 0)]);
+        assert_eq!(loaded_chunks[&ChunkPos::new(0, 0, 0)].get(&(3, 4, 5)), 2);
+
+        // setting the same block again changes nothing
+        assert!(set_block(&config, &mut loaded_chunks, &(3, 4, 5), 2).is_empty());
+
+        let changed = set_block(&config, &mut loaded_chunks, &(3, 4, 5), 0);
+        assert_eq!(changed, vec![ChunkPos::new(0, 0, 0)]);
+        assert_eq!(loaded_chunks[&ChunkPos::new(0, 0, 0)].get(&(3, 4, 5)), 0);
+    }
+
+    #[test]
+    fn set_block_border_test() {
+        let config = create_mock_config();
+        let mut loaded_chunks = create_loaded_chunks(&config);
+
+        // on the edge shared with the chunk in positive x
+        let changed = set_block(&config, &mut loaded_chunks, &(7, 4, 5), 2);
+        assert_eq!(
+            changed,
+            vec![ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0)]
+        );
+        assert_eq!(loaded_chunks[&ChunkPos::new(1, 0, 0)].get(&(-1, 4, 5)), 2);
+
+        // in the corner, so in the border of 7 other chunks
+        let changed = set_block(&config, &mut loaded_chunks, &(0, 0, 0), 3);
+        assert_eq!(changed.len(), 8);
+        assert_eq!(changed[0], ChunkPos::new(0, 0, 0));
+        assert_eq!(loaded_chunks[&ChunkPos::new(-1, 0, 0)].get(&(8, 0, 0)), 3);
+        assert_eq!(loaded_chunks[&ChunkPos::new(0, -1, 0)].get(&(0, 8, 0)), 3);
+        assert_eq!(loaded_chunks[&ChunkPos::new(-1, -1, -1)].get(&(8, 8, 8)), 3);
+    }
+
+    #[test]
+    fn set_block_unloaded_test() {
+        let config = create_mock_config();
+        let mut loaded_chunks = create_loaded_chunks(&config);
+
+        assert!(set_block(&config, &mut loaded_chunks, &(100, 0, 0), 2).is_empty());
+        assert!(loaded_chunks.values().all(|chunk| chunk.is_empty()));
+    }
+
+    #[test]
+    fn sync_borders_test() {
+        let config = create_mock_config();
+        let mut loaded_chunks = create_loaded_chunks(&config);
+
+        // blocks along the shared edges of the center chunk and its neighbors
+        let mut center = ChunkData::new(config.depth);
+        center.set(&(7, 2, 3), 2);
+        center.set(&(0, 0, 0), 3);
+        loaded_chunks.insert(ChunkPos::new(0, 0, 0), center);
+        loaded_chunks
+            .get_mut(&ChunkPos::new(0, 1, 0))
+            .unwrap()
+            .set(&(4, 0, 5), 1);
+
+        let mut changed = sync_borders(&config, &mut loaded_chunks, &ChunkPos::new(0, 0, 0));
+        changed.sort();
+        let mut expected = vec![
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(1, 0, 0),
+            ChunkPos::new(-1, 0, 0),
+            ChunkPos::new(0, -1, 0),
+            ChunkPos::new(0, 0, -1),
+            ChunkPos::new(-1, -1, 0),
+            ChunkPos::new(-1, 0, -1),
+            ChunkPos::new(0, -1, -1),
+            ChunkPos::new(-1, -1, -1),
+        ];
+        expected.sort();
+        assert_eq!(changed, expected);
+
+        let center = &loaded_chunks[&ChunkPos::new(0, 0, 0)];
+        assert_eq!(center.get(&(4, 8, 5)), 1);
+        assert_eq!(loaded_chunks[&ChunkPos::new(1, 0, 0)].get(&(-1, 2, 3)), 2);
+        assert_eq!(loaded_chunks[&ChunkPos::new(-1, -1, -1)].get(&(8, 8, 8)), 3);
+
+        // syncing again changes nothing
+        assert!(sync_borders(&config, &mut loaded_chunks, &ChunkPos::new(0, 0, 0)).is_empty());
+    }
+}
@@ -0,0 +1,435 @@
+//! Compact binary encoding of chunk data, shared by anything that needs to
+//! store or send chunks.
+//!
+//! Only the inside of the chunk is encoded, the one block generation border
+//! is skipped since it can be rebuilt from the neighboring chunks. The block
+//! ids in the chunk are stored once in a palette, and every block is stored as
+//! an index into that palette. The indices are either bit packed or run length
+//! encoded, whichever is smaller.
+//!
+//! Layout, all integers are little endian or LEB128 varints:
+//! - `u8` encoding version
+//! - `u16` chunk depth
+//! - `u8` packing, `0` for bit packed and `1` for run length encoded
+//! - varint palette length, followed by the palette's block ids as varints
+//! - bit packed: `u8` bits per index, then the indices packed LSB first
+//! - run length encoded: varint run count, then a varint length and a varint
+//!   palette index for each run
+//!
+//! Blocks are ordered by `y`, then `z`, then `x`, so horizontal layers of the
+//! same block end up next to each other.
+
+use std::collections::HashMap;
+
+use anyhow::bail;
+
+use super::{ChunkConfig, ChunkData};
+
+/// Version written at the start of the encoded data.
+pub const ENCODING_VERSION: u8 = 2;
+
+const PACKING_BITS: u8 = 0;
+const PACKING_RUNS: u8 = 1;
+
+/// Encode the inside of the chunk.
+pub fn encode_chunk(config: &ChunkConfig, chunk: &ChunkData) -> Vec<u8> {
+    let depth = config.depth;
+
+    // build the palette and the index of every block
+    let mut palette = Vec::<u32>::new();
+    let mut palette_lookup = HashMap::<u32, u32>::new();
+    let mut indices = Vec::with_capacity((depth * depth * depth) as usize);
+    for y in 0..depth {
+        for z in 0..depth {
+            for x in 0..depth {
+                let block_id = chunk.get(&(x, y, z));
+                let index = *palette_lookup.entry(block_id).or_insert_with(|| {
+                    palette.push(block_id);
+                    palette.len() as u32 - 1
+                });
+                indices.push(index);
+            }
+        }
+    }
+
+    let mut output = Vec::new();
+    output.push(ENCODING_VERSION);
+    output.extend_from_slice(&(depth as u16).to_le_bytes());
+
+    let bits = bits_per_index(palette.len());
+    let runs = count_runs(&indices);
+
+    // compare the size of both packings, not counting the palette
+    let packed_size = 1 + (indices.len() * bits as usize).div_ceil(8);
+    let runs_size: usize = varint_size(runs.len() as u64)
+        + runs
+            .iter()
+            .map(|(length, index)| varint_size(*length as u64) + varint_size(*index as u64))
+            .sum::<usize>();
+
+    if packed_size <= runs_size {
+        output.push(PACKING_BITS);
+        write_palette(&mut output, &palette);
+        output.push(bits);
+        write_packed(&mut output, &indices, bits);
+    } else {
+        output.push(PACKING_RUNS);
+        write_palette(&mut output, &palette);
+        write_varint(&mut output, runs.len() as u64);
+        for (length, index) in runs {
+            write_varint(&mut output, length as u64);
+            write_varint(&mut output, index as u64);
+        }
+    }
+
+    output
+}
+
+/// Decode data written with [encode_chunk]. The returned chunk has no
+/// generation border.
+pub fn decode_chunk(config: &ChunkConfig, bytes: &[u8]) -> anyhow::Result<ChunkData> {
+    let mut reader = Reader { bytes, position: 0 };
+
+    let version = reader.read_u8()?;
+    if version != ENCODING_VERSION {
+        bail!("Unknown chunk encoding version {}.", version);
+    }
+
+    let depth = u16::from_le_bytes([reader.read_u8()?, reader.read_u8()?]) as i32;
+    if depth != config.depth {
+        bail!(
+            "Chunk was encoded with depth {}, but the world uses depth {}.",
+            depth,
+            config.depth
+        );
+    }
+    let volume = (depth * depth * depth) as usize;
+
+    let packing = reader.read_u8()?;
+
+    let palette_length = reader.read_varint()?;
+    if palette_length == 0 || palette_length > volume as u64 {
+        bail!("Invalid palette length {}.", palette_length);
+    }
+    let mut palette = Vec::with_capacity(palette_length as usize);
+    for _ in 0..palette_length {
+        let block_id = reader.read_varint()?;
+        if block_id > u32::MAX as u64 {
+            bail!("Block id {} is out of range.", block_id);
+        }
+        palette.push(block_id as u32);
+    }
+
+    let mut indices = Vec::with_capacity(volume);
+    match packing {
+        PACKING_BITS => {
+            let bits = reader.read_u8()?;
+            if bits != bits_per_index(palette.len()) {
+                bail!("Invalid bits per index {}.", bits);
+            }
+            let packed = reader.read_bytes((volume * bits as usize).div_ceil(8))?;
+            for i in 0..volume {
+                indices.push(read_packed(packed, i, bits));
+            }
+        }
+        PACKING_RUNS => {
+            let run_count = reader.read_varint()?;
+            for _ in 0..run_count {
+                let length = reader.read_varint()?;
+                let index = reader.read_varint()?;
+                if length == 0 || length > (volume - indices.len()) as u64 {
+                    bail!("Invalid run length {}.", length);
+                }
+                if index > u32::MAX as u64 {
+                    bail!("Palette index {} is out of range.", index);
+                }
+                indices.extend(std::iter::repeat_n(index as u32, length as usize));
+            }
+            if indices.len() != volume {
+                bail!("Runs cover {} blocks, expected {}.", indices.len(), volume);
+            }
+        }
+        _ => bail!("Unknown chunk packing {}.", packing),
+    }
+
+    if !reader.is_empty() {
+        bail!("Unexpected data after the end of the chunk.");
+    }
+
+    let mut output = ChunkData::new(depth);
+    let mut indices = indices.into_iter();
+    for y in 0..depth {
+        for z in 0..depth {
+            for x in 0..depth {
+                let index = indices.next().unwrap() as usize;
+                let block_id = *palette
+                    .get(index)
+                    .ok_or_else(|| anyhow::anyhow!("Palette index {} is out of range.", index))?;
+                output.set(&(x, y, z), block_id);
+            }
+        }
+    }
+
+    Ok(output)
+}
+
+/// Size in bytes of the encoded chunk.
+pub fn encoded_size(config: &ChunkConfig, chunk: &ChunkData) -> usize {
+    encode_chunk(config, chunk).len()
+}
+
+/// The number of bits needed to store an index into a palette of `length` entries.
+fn bits_per_index(length: usize) -> u8 {
+    let mut bits = 0;
+    while (1usize << bits) < length {
+        bits += 1;
+    }
+    bits
+}
+
+fn count_runs(indices: &[u32]) -> Vec<(u32, u32)> {
+    let mut runs: Vec<(u32, u32)> = Vec::new();
+    for index in indices.iter() {
+        match runs.last_mut() {
+            Some((length, last)) if last == index => *length += 1,
+            _ => runs.push((1, *index)),
+        }
+    }
+    runs
+}
+
+fn write_palette(output: &mut Vec<u8>, palette: &[u32]) {
+    write_varint(output, palette.len() as u64);
+    for block_id in palette.iter() {
+        write_varint(output, *block_id as u64);
+    }
+}
+
+fn write_packed(output: &mut Vec<u8>, indices: &[u32], bits: u8) {
+    let start = output.len();
+    output.resize(start + (indices.len() * bits as usize).div_ceil(8), 0);
+
+    for (i, index) in indices.iter().enumerate() {
+        for bit in 0..bits as usize {
+            if index & (1 << bit) != 0 {
+                let position = i * bits as usize + bit;
+                output[start + position / 8] |= 1 << (position % 8);
+            }
+        }
+    }
+}
+
+fn read_packed(packed: &[u8], i: usize, bits: u8) -> u32 {
+    let mut index = 0;
+    for bit in 0..bits as usize {
+        let position = i * bits as usize + bit;
+        if packed[position / 8] & (1 << (position % 8)) != 0 {
+            index |= 1 << bit;
+        }
+    }
+    index
+}
+
+fn write_varint(output: &mut Vec<u8>, mut value: u64) {
+    loop {
+        let byte = (value & 0x7F) as u8;
+        value >>= 7;
+        if value == 0 {
+            output.push(byte);
+            return;
+        }
+        output.push(byte | 0x80);
+    }
+}
+
+fn varint_size(mut value: u64) -> usize {
+    let mut size = 1;
+    while value >= 0x80 {
+        value >>= 7;
+        size += 1;
+    }
+    size
+}
+
+/// Reads from a byte slice, erroring instead of panicking on malformed data.
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn read_u8(&mut self) -> anyhow::Result<u8> {
+        let byte = self.read_bytes(1)?;
+        Ok(byte[0])
+    }
+
+    fn read_bytes(&mut self, length: usize) -> anyhow::Result<&'a [u8]> {
+        if length > self.bytes.len() - self.position {
+            bail!("Unexpected end of chunk data.");
+        }
+        let bytes = &self.bytes[self.position..self.position + length];
+        self.position += length;
+        Ok(bytes)
+    }
+
+    fn read_varint(&mut self) -> anyhow::Result<u64> {
+        let mut value = 0u64;
+        for shift in (0..64).step_by(7) {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7F) as u64) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        bail!("Varint is too long.");
+    }
+
+    fn is_empty(&self) -> bool {
+        self.position == self.bytes.len()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::{
+        generation::{ao_test, generate},
+        ChunkPos,
+    };
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 8, 3)
+    }
+
+    /// The blocks of the chunk that are encoded, ie. without the border.
+    fn inside(config: &ChunkConfig, chunk: &ChunkData) -> ChunkData {
+        assert_eq!(chunk.depth(), config.depth);
+        let mut inside = chunk.clone();
+        inside.clear_border();
+        inside
+    }
+
+    /// Small xorshift generator so the fuzz tests are reproducible.
+    struct Random(u64);
+    impl Random {
+        fn next(&mut self) -> u64 {
+            self.0 ^= self.0 << 13;
+            self.0 ^= self.0 >> 7;
+            self.0 ^= self.0 << 17;
+            self.0
+        }
+        fn below(&mut self, max: u64) -> u64 {
+            self.next() % max
+        }
+    }
+
+    fn random_chunk(config: &ChunkConfig, random: &mut Random) -> ChunkData {
+        let palette_size = 1 + random.below(40);
+        let density = random.below(101);
+        let mut chunk = ChunkData::new(config.depth);
+        for x in -1..(config.depth + 1) {
+            for y in -1..(config.depth + 1) {
+                for z in -1..(config.depth + 1) {
+                    if random.below(100) < density {
+                        let block_id = 1 + random.below(palette_size) as u32 * 7919;
+                        chunk.set(&(x, y, z), block_id);
+                    }
+                }
+            }
+        }
+        chunk
+    }
+
+    #[test]
+    fn round_trip_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+
+        for chunk in [
+            ChunkData::new(config.depth),
+            ao_test(config.depth),
+            generate(&config, &ChunkPos::new(0, 0, 0)),
+            generate(&config, &ChunkPos::new(0, -1, 0)),
+            generate(&config, &ChunkPos::new(3, -2, 5)),
+        ] {
+            let decoded = decode_chunk(&config, &encode_chunk(&config, &chunk))?;
+            assert_eq!(decoded, inside(&config, &chunk));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn encoded_size_test() {
+        let config = create_mock_config();
+
+        // a single block type only needs the header and palette
+        let mut full_chunk = ChunkData::new(config.depth);
+        for x in 0..config.depth {
+            for y in 0..config.depth {
+                for z in 0..config.depth {
+                    full_chunk.set(&(x, y, z), 2);
+                }
+            }
+        }
+        assert!(encoded_size(&config, &full_chunk) < 16);
+        assert!(encoded_size(&config, &ChunkData::new(config.depth)) < 16);
+
+        // never larger than 2 bits for the 3 block types plus air
+        let volume = (config.depth * config.depth * config.depth) as usize;
+        let chunk = generate(&config, &ChunkPos::new(0, 0, 0));
+        assert!(encoded_size(&config, &chunk) <= 16 + volume / 4);
+    }
+
+    #[test]
+    fn decode_wrong_depth_test() {
+        let config = create_mock_config();
+        let other_config = ChunkConfig::new(10, 16, 3);
+
+        let bytes = encode_chunk(&config, &ao_test(config.depth));
+        assert!(decode_chunk(&other_config, &bytes).is_err());
+    }
+
+    #[test]
+    fn fuzz_round_trip_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+        let mut random = Random(0x9E37_79B9_7F4A_7C15);
+
+        for _ in 0..200 {
+            let chunk = random_chunk(&config, &mut random);
+            let decoded = decode_chunk(&config, &encode_chunk(&config, &chunk))?;
+            assert_eq!(decoded, inside(&config, &chunk));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn fuzz_malformed_data_test() {
+        let config = create_mock_config();
+        let mut random = Random(0x2545_F491_4F6C_DD1D);
+
+        for _ in 0..200 {
+            let chunk = random_chunk(&config, &mut random);
+            let bytes = encode_chunk(&config, &chunk);
+
+            // truncated data
+            let length = random.below(bytes.len() as u64) as usize;
+            assert!(decode_chunk(&config, &bytes[..length]).is_err());
+
+            // corrupted data can decode to anything, but must not panic
+            let mut corrupted = bytes.clone();
+            for _ in 0..(1 + random.below(8)) {
+                let i = random.below(corrupted.len() as u64) as usize;
+                corrupted[i] = random.next() as u8;
+            }
+            let _ = decode_chunk(&config, &corrupted);
+
+            // random garbage after a valid header
+            let mut garbage = bytes[..4].to_vec();
+            for _ in 0..random.below(512) {
+                garbage.push(random.next() as u8);
+            }
+            let _ = decode_chunk(&config, &garbage);
+        }
+    }
+}
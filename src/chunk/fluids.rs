@@ -0,0 +1,403 @@
+//! Flowing fluids, stepped a few times a second over the loaded chunks.
+//!
+//! Every level of a fluid is a block of its own, see `Fluid`. Fluid flows down
+//! first, a block below a fluid is filled one level below a source, and only
+//! spreads sideways from blocks resting on something it can't flow into, losing
+//! a level every block. Flowing blocks that aren't fed anymore dry up a level
+//! at a time. Chunks that aren't loaded are treated as solid.
+
+use indexmap::IndexSet;
+
+use crate::engine::input::Input;
+use crate::world::{Event, GameData};
+use crate::world_renderer::WorldRenderer;
+
+use super::block::Fluid;
+use super::editing::edit_block;
+use super::{
+    chunk_position, local_position, offset_position, ChunkConfig, ChunkData, ChunkPos,
+    ChunkStorage, Position, NEIGHBORS,
+};
+
+/// Milliseconds between two steps of the simulation.
+pub const FLUID_TICK: f64 = 250.0;
+
+const SIDES: [Position; 4] = [(1, 0, 0), (-1, 0, 0), (0, 0, 1), (0, 0, -1)];
+const UP: Position = (0, 1, 0);
+const DOWN: Position = (0, -1, 0);
+
+/// Get the block at a world space position, None if its chunk isn't loaded.
+fn block_at(config: &ChunkConfig, chunks: &ChunkStorage, position: &Position) -> Option<u32> {
+    let chunk = chunks.get(&chunk_position(config, position))?;
+    Some(chunk.get(&local_position(config, position)))
+}
+
+/// Get the fluid at a world space position, if there is one.
+fn fluid_at(config: &ChunkConfig, chunks: &ChunkStorage, position: &Position) -> Option<Fluid> {
+    let block_id = block_at(config, chunks, position)?;
+    config.dict.get(&block_id)?.fluid
+}
+
+/// Check if fluid on top of the position spreads sideways instead of flowing
+/// down, which it does on anything but air and flowing fluid.
+fn holds_fluid(config: &ChunkConfig, chunks: &ChunkStorage, position: &Position) -> bool {
+    match block_at(config, chunks, position) {
+        Some(0) => false,
+        Some(block_id) => config
+            .dict
+            .get(&block_id)
+            .and_then(|block| block.fluid)
+            .is_none_or(|fluid| fluid.is_source()),
+        None => true,
+    }
+}
+
+/// Get the block the position should change to, None if it stays the same.
+/// Only air and flowing fluid change, sources and other blocks stay.
+fn flow_target(config: &ChunkConfig, chunks: &ChunkStorage, position: &Position) -> Option<u32> {
+    let block_id = block_at(config, chunks, position)?;
+    match config.dict.get(&block_id)?.fluid {
+        Some(fluid) if fluid.is_source() => return None,
+        Some(_) => {}
+        None if block_id != 0 => return None,
+        None => {}
+    }
+
+    // fluid and the level it would fill the position with
+    let mut target: Option<(Fluid, u8)> = None;
+    if let Some(above) = fluid_at(config, chunks, &offset_position(position, &UP)) {
+        target = Some((above, above.levels - 1));
+    }
+    for side in SIDES {
+        let neighbor = offset_position(position, &side);
+        let fluid = match fluid_at(config, chunks, &neighbor) {
+            Some(fluid) => fluid,
+            None => continue,
+        };
+        if !holds_fluid(config, chunks, &offset_position(&neighbor, &DOWN)) {
+            continue;
+        }
+        let level = fluid.level - 1;
+        if target.is_none_or(|(_, target_level)| level > target_level) {
+            target = Some((fluid, level));
+        }
+    }
+
+    let target_id = match target {
+        Some((fluid, level)) if level > 0 => fluid.id(level),
+        _ => 0,
+    };
+    (target_id != block_id).then_some(target_id)
+}
+
+/// Blocks of fluid that might flow on the next step.
+///
+/// A step looks at every scheduled block against the world as it was before
+/// the step, so the order blocks were scheduled in doesn't change the result.
+pub struct FluidSimulation {
+    scheduled: IndexSet<Position>,
+    elapsed: f64,
+}
+
+impl FluidSimulation {
+    pub fn new() -> Self {
+        Self {
+            scheduled: IndexSet::new(),
+            elapsed: 0.0,
+        }
+    }
+
+    /// Check the block at the world space position and its neighbors on the
+    /// next step, used whenever a block changes.
+    pub fn schedule(&mut self, position: &Position) {
+        self.scheduled.insert(*position);
+        for neighbor in NEIGHBORS {
+            self.scheduled.insert(offset_position(position, &neighbor));
+        }
+    }
+
+    /// Schedule the fluids in a chunk that was just loaded, including the
+    /// fluids of its neighbors along its border, so fluid flows into it.
+    /// Only fluid next to air can flow, so the inside of a sea isn't scheduled.
+    pub fn schedule_chunk(
+        &mut self,
+        config: &ChunkConfig,
+        chunk: &ChunkData,
+        chunk_pos: &ChunkPos,
+    ) {
+        for x in -1..=config.depth {
+            for y in -1..=config.depth {
+                for z in -1..=config.depth {
+                    let is_fluid = config
+                        .dict
+                        .get(&chunk.get(&(x, y, z)))
+                        .is_some_and(|block| block.fluid.is_some());
+                    let next_to_air = NEIGHBORS
+                        .iter()
+                        .any(|neighbor| chunk.get(&offset_position(&(x, y, z), neighbor)) == 0);
+                    if is_fluid && next_to_air {
+                        self.schedule(&(
+                            chunk_pos.x * config.depth + x,
+                            chunk_pos.y * config.depth + y,
+                            chunk_pos.z * config.depth + z,
+                        ));
+                    }
+                }
+            }
+        }
+    }
+
+    /// True if nothing is scheduled, the fluids have settled.
+    pub fn is_settled(&self) -> bool {
+        self.scheduled.is_empty()
+    }
+
+    /// Count the milliseconds since the last step, returns true once the next
+    /// step is due.
+    pub fn advance(&mut self, delta: f64) -> bool {
+        self.elapsed += delta;
+        if self.elapsed < FLUID_TICK {
+            return false;
+        }
+        self.elapsed = 0.0;
+        true
+    }
+
+    /// Find the blocks the scheduled positions change to, the changes aren't
+    /// applied to the chunks, but the changed positions are scheduled again.
+    pub fn step(&mut self, config: &ChunkConfig, chunks: &ChunkStorage) -> Vec<(Position, u32)> {
+        let scheduled: Vec<Position> = self.scheduled.drain(..).collect();
+        let changes: Vec<(Position, u32)> = scheduled
+            .into_iter()
+            .filter_map(|position| {
+                flow_target(config, chunks, &position).map(|block_id| (position, block_id))
+            })
+            .collect();
+
+        for (position, _) in changes.iter() {
+            self.schedule(position);
+        }
+        changes
+    }
+}
+
+/// System for stepping the fluids every `FLUID_TICK`, the changed blocks are
+/// set like any other edit, so they are lit, rebuilt and saved.
+pub fn flow_fluids(
+    _renderer: &mut WorldRenderer,
+    _input: &mut Input,
+    data: &mut GameData,
+    _queue: &mut Vec<Event>,
+    delta: f64,
+) {
+    if data.fluids.is_settled() || !data.fluids.advance(delta) {
+        return;
+    }
+
+    let changes = data.fluids.step(&data.chunk_config, &data.loaded_chunks);
+    for (position, block_id) in changes {
+        edit_block(data, &position, block_id);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::editing::set_block;
+
+    const STONE: u32 = 2;
+    const WATER: u32 = 7;
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 8, 3)
+    }
+
+    /// A row of chunks along x and z, with a stone floor at y = 0.
+    fn create_floor(config: &ChunkConfig, radius: i32) -> ChunkStorage {
+        let mut chunks = ChunkStorage::new();
+        for x in -radius..=radius {
+            for z in -radius..=radius {
+                chunks.insert(ChunkPos::new(x, 0, z), ChunkData::new(config.depth));
+            }
+        }
+        let size = (radius * 2 + 1) * config.depth;
+        for x in 0..size {
+            for z in 0..size {
+                let position = (x - radius * config.depth, 0, z - radius * config.depth);
+                set_block(config, &mut chunks, &position, STONE);
+            }
+        }
+        chunks
+    }
+
+    /// Set a block and schedule it, like `edit_block` does.
+    fn place(
+        config: &ChunkConfig,
+        chunks: &mut ChunkStorage,
+        simulation: &mut FluidSimulation,
+        position: &Position,
+        block_id: u32,
+    ) {
+        set_block(config, chunks, position, block_id);
+        simulation.schedule(position);
+    }
+
+    /// Step the simulation until nothing changes, returns the number of steps.
+    fn settle(
+        config: &ChunkConfig,
+        chunks: &mut ChunkStorage,
+        simulation: &mut FluidSimulation,
+    ) -> usize {
+        for steps in 0..100 {
+            let changes = simulation.step(config, chunks);
+            if changes.is_empty() {
+                assert!(simulation.is_settled());
+                return steps;
+            }
+            for (position, block_id) in changes {
+                set_block(config, chunks, &position, block_id);
+            }
+        }
+        panic!("the fluid didn't settle");
+    }
+
+    /// Get the level of the water at the position, 0 if there is none.
+    fn level(config: &ChunkConfig, chunks: &ChunkStorage, position: &Position) -> u8 {
+        let block_id = block_at(config, chunks, position).unwrap();
+        match config.dict[&block_id].fluid {
+            Some(fluid) => {
+                assert_eq!(fluid.source, WATER);
+                fluid.level
+            }
+            None => 0,
+        }
+    }
+
+    #[test]
+    fn fluid_levels_test() {
+        let config = create_mock_config();
+        let water = config.dict[&WATER].fluid.unwrap();
+        assert!(water.is_source());
+        assert_eq!(water.levels, 8);
+        assert_eq!(water.id(8), WATER);
+        assert_eq!(water.id(1), WATER + 7);
+
+        let lowest = &config.dict[&(WATER + 7)];
+        assert_eq!(lowest.ident, "water_1");
+        assert_eq!(lowest.fluid.unwrap().level, 1);
+        assert!(!lowest.collision);
+    }
+
+    #[test]
+    fn spread_test() {
+        let config = create_mock_config();
+        let mut chunks = create_floor(&config, 1);
+        let mut simulation = FluidSimulation::new();
+
+        place(&config, &mut chunks, &mut simulation, &(4, 1, 4), WATER);
+        settle(&config, &mut chunks, &mut simulation);
+
+        // one level less every block, crossing into the next chunk
+        for distance in 0..=7 {
+            let expected = 8 - distance as u8;
+            assert_eq!(level(&config, &chunks, &(4 + distance, 1, 4)), expected);
+            assert_eq!(level(&config, &chunks, &(4, 1, 4 - distance)), expected);
+        }
+        assert_eq!(level(&config, &chunks, &(12, 1, 4)), 0);
+        assert_eq!(level(&config, &chunks, &(6, 1, 7)), 3);
+        assert_eq!(level(&config, &chunks, &(4, 2, 4)), 0);
+    }
+
+    #[test]
+    fn fall_test() {
+        let config = create_mock_config();
+        let mut chunks = create_floor(&config, 1);
+        let mut simulation = FluidSimulation::new();
+
+        place(&config, &mut chunks, &mut simulation, &(4, 6, 4), WATER);
+        settle(&config, &mut chunks, &mut simulation);
+
+        // falls straight down without spreading, then spreads along the floor
+        assert_eq!(level(&config, &chunks, &(5, 6, 4)), 0);
+        for y in 1..6 {
+            assert_eq!(level(&config, &chunks, &(4, y, 4)), 7);
+            assert_eq!(level(&config, &chunks, &(3, y + 1, 4)), 0);
+        }
+        assert_eq!(level(&config, &chunks, &(5, 1, 4)), 6);
+        assert_eq!(level(&config, &chunks, &(4, 1, 10)), 1);
+        assert_eq!(level(&config, &chunks, &(4, 1, 11)), 0);
+    }
+
+    #[test]
+    fn drain_test() {
+        let config = create_mock_config();
+        let mut chunks = create_floor(&config, 1);
+        let mut simulation = FluidSimulation::new();
+
+        place(&config, &mut chunks, &mut simulation, &(4, 3, 4), WATER);
+        settle(&config, &mut chunks, &mut simulation);
+        assert_eq!(level(&config, &chunks, &(7, 1, 4)), 4);
+
+        // without its source all of the water dries up
+        place(&config, &mut chunks, &mut simulation, &(4, 3, 4), 0);
+        settle(&config, &mut chunks, &mut simulation);
+        for x in -8..16 {
+            for z in -8..16 {
+                for y in 1..4 {
+                    assert_eq!(level(&config, &chunks, &(x, y, z)), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn blocked_test() {
+        let config = create_mock_config();
+        let mut chunks = create_floor(&config, 0);
+        let mut simulation = FluidSimulation::new();
+
+        // a wall next to the source, and the chunks past x = 7 aren't loaded
+        place(&config, &mut chunks, &mut simulation, &(5, 1, 3), STONE);
+        place(&config, &mut chunks, &mut simulation, &(6, 1, 3), WATER);
+        settle(&config, &mut chunks, &mut simulation);
+
+        assert_eq!(level(&config, &chunks, &(7, 1, 3)), 7);
+        assert_eq!(level(&config, &chunks, &(5, 1, 3)), 0);
+        assert_eq!(block_at(&config, &chunks, &(5, 1, 3)), Some(STONE));
+        assert_eq!(level(&config, &chunks, &(5, 1, 4)), 6);
+        assert_eq!(level(&config, &chunks, &(4, 1, 3)), 4);
+        assert!(block_at(&config, &chunks, &(8, 1, 3)).is_none());
+    }
+
+    #[test]
+    fn order_test() {
+        let config = create_mock_config();
+        let sources = [(2, 1, 2), (5, 4, 6), (1, 2, 7)];
+
+        // all sources at once, and one at a time in another order
+        let mut first = create_floor(&config, 1);
+        let mut simulation = FluidSimulation::new();
+        for source in sources.iter() {
+            place(&config, &mut first, &mut simulation, source, WATER);
+        }
+        settle(&config, &mut first, &mut simulation);
+
+        let mut second = create_floor(&config, 1);
+        for source in sources.iter().rev() {
+            place(&config, &mut second, &mut simulation, source, WATER);
+            settle(&config, &mut second, &mut simulation);
+        }
+
+        for x in -8..16 {
+            for z in -8..16 {
+                for y in 1..8 {
+                    let position = (x, y, z);
+                    assert_eq!(
+                        block_at(&config, &first, &position),
+                        block_at(&config, &second, &position)
+                    );
+                }
+            }
+        }
+    }
+}
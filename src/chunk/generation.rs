@@ -1,54 +1,363 @@
-use super::{ChunkConfig, ChunkData, Position};
+use std::sync::Arc;
 
-// need to rework this function
-pub fn ground_threshold(_config: &ChunkConfig, pos: i32) -> f64 {
-    let ground_level = 0;
-    let change = 32;
-    let _max_threshold = 0.5;
+use anyhow::anyhow;
+
+use super::{
+    biome::{terrain_column, Biome, TerrainColumn},
+    block::{find_block, BlockDictionary},
+    caves::CavePass,
+    meshing::should_mesh_block,
+    ores::OrePass,
+    settings::{GeneratorKind, WorldGenSettings},
+    storage::RegionStorage,
+    structures::{StructurePass, StructureTemplate},
+    ChunkConfig, ChunkData, ChunkPos, Position,
+};
+
+/// Air at or below sea level is filled with water.
+pub const SEA_LEVEL: i32 = 0;
+/// How far below the height of a column air is still filled with water, so
+/// pockets of air deep under ground stay dry.
+const SEA_DEPTH: f64 = 8.0;
+
+/// How far above the height of a column floating islands are thickest.
+const ISLAND_HEIGHT: f64 = 64.0;
+/// Blocks of height the noise of floating islands is spread over.
+const ISLAND_SPREAD: f64 = 24.0;
+/// Bias of the noise of floating islands where they are thickest, higher
+/// makes fewer and smaller islands.
+const ISLAND_BIAS: f64 = 0.3;
+/// Layers of dirt between the grass and the stone of flat worlds.
+const FLAT_DIRT: i32 = 3;
+
+/// Bias of the terrain noise at a height, the ground is around the column's
+/// height and the squish spreads the noise over more or fewer blocks.
+pub fn ground_threshold(column: &TerrainColumn, pos: i32) -> f64 {
     let min_threshold = -0.05;
 
-    let bias = (pos - ground_level) as f64 / change as f64;
+    let bias = (pos as f64 - column.height) / column.squish;
 
-    if pos > ground_level {
+    if bias > 0.0 {
         return bias;
     }
     bias.max(min_threshold)
 }
 
-// could abstract out the generating functions
-// would just overcomplicate things at the moment
-pub fn generate(config: &ChunkConfig, pos: &Position) -> ChunkData {
-    let mut output = ChunkData::new();
-    generate_terrain(config, pos, &mut output);
-    generate_foliage(config, pos, &mut output);
-    // generate ores
-    // generate structures
+/// Which blocks of a chunk a generation pass fills in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GenerationArea {
+    /// The whole chunk and its border, for a chunk that is generated.
+    Chunk,
+    /// Only the border, for a chunk whose inside was loaded from storage.
+    Border,
+}
+
+impl GenerationArea {
+    /// Check if a chunk local position, inside the chunk or its border, is
+    /// part of the area.
+    pub fn contains(&self, config: &ChunkConfig, position: &Position) -> bool {
+        match self {
+            GenerationArea::Chunk => true,
+            GenerationArea::Border => !should_mesh_block(config, position),
+        }
+    }
+
+    /// Get every chunk local position of the area.
+    pub fn positions(self, config: &ChunkConfig) -> impl Iterator<Item = Position> + '_ {
+        let range = -1..(config.depth + 1);
+        range
+            .clone()
+            .flat_map(move |x| range.clone().map(move |y| (x, y)))
+            .flat_map(move |(x, y)| (-1..(config.depth + 1)).map(move |z| (x, y, z)))
+            .filter(move |position| self.contains(config, position))
+    }
+}
+
+/// A stage of generating a chunk, the passes of a world run in order on the
+/// same chunk.
+///
+/// A pass has to decide every block from the world seed and the block's world
+/// position alone, never from which chunks were generated before, so chunks
+/// come out the same in any order and their borders match their neighbors.
+pub trait GenerationPass: Send + Sync {
+    /// Name of the pass, for logging and timing.
+    fn name(&self) -> &'static str;
+
+    /// Fill in the blocks of the area of the chunk at `pos`.
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    );
+}
+
+/// The passes a world is generated with, in order.
+pub type GenerationPasses = Arc<Vec<Box<dyn GenerationPass>>>;
+
+/// Get the passes of the default world: terrain, caves, surface blocks, ores
+/// and structures. The blocks the passes place are looked up in the
+/// dictionary.
+pub fn default_passes(
+    seed: u64,
+    dict: &BlockDictionary,
+    structures: Vec<StructureTemplate>,
+) -> anyhow::Result<GenerationPasses> {
+    Ok(Arc::new(vec![
+        Box::new(TerrainPass::new(dict)?),
+        Box::new(CavePass::new(seed, dict)?),
+        Box::new(SurfacePass::new(dict)?),
+        Box::new(OrePass::new(seed, dict)?),
+        Box::new(StructurePass::new(structures)),
+    ]))
+}
+
+/// Get the passes of a world generated with the settings.
+pub fn world_passes(
+    settings: &WorldGenSettings,
+    dict: &BlockDictionary,
+    structures: Vec<StructureTemplate>,
+) -> anyhow::Result<GenerationPasses> {
+    match settings.generator {
+        // the islands are shaped by the terrain noise
+        GeneratorKind::Terrain | GeneratorKind::FloatingIslands => {
+            default_passes(settings.seed, dict, structures)
+        }
+        GeneratorKind::Flat => Ok(Arc::new(vec![Box::new(FlatPass::new(dict)?)])),
+        GeneratorKind::Void => Ok(Arc::new(Vec::new())),
+    }
+}
 
+/// Run the world's generation passes over an area of the chunk.
+pub fn run_passes(
+    config: &ChunkConfig,
+    pos: &ChunkPos,
+    area: GenerationArea,
+    output: &mut ChunkData,
+) {
+    for pass in config.passes.iter() {
+        pass.generate(config, pos, area, output);
+    }
+}
+
+pub fn generate(config: &ChunkConfig, pos: &ChunkPos) -> ChunkData {
+    let mut output = ChunkData::new(config.depth);
+    run_passes(config, pos, GenerationArea::Chunk, &mut output);
     output
 }
 
-pub fn generate_foliage(config: &ChunkConfig, pos: &Position, output: &mut ChunkData) {
-    for (k, v) in output.iter_mut() {
-        let (x, y, z) = k;
-        let global_position = [
-            x + pos.0 * config.depth,
-            y + pos.1 * config.depth,
-            z + pos.2 * config.depth,
-        ];
-        if has_air_within_dist(config, global_position, 4) {
-            *v = 3;
+/// Generate only the one block border around the chunk, used when the
+/// inside of the chunk was loaded from storage.
+pub fn generate_border(config: &ChunkConfig, pos: &ChunkPos, output: &mut ChunkData) {
+    run_passes(config, pos, GenerationArea::Border, output);
+}
+
+/// Converts a chunk local position to a world space position.
+pub fn global_position(config: &ChunkConfig, pos: &ChunkPos, position: &Position) -> [i32; 3] {
+    [
+        position.0 + pos.x * config.depth,
+        position.1 + pos.y * config.depth,
+        position.2 + pos.z * config.depth,
+    ]
+}
+
+/// Get the terrain column of every column of the chunk and its border,
+/// see `column_index`.
+pub fn chunk_columns(config: &ChunkConfig, pos: &ChunkPos) -> Vec<TerrainColumn> {
+    let mut columns = Vec::new();
+    for x in (-1)..(config.depth + 1) {
+        for z in (-1)..(config.depth + 1) {
+            columns.push(terrain_column(
+                config,
+                x + pos.x * config.depth,
+                z + pos.z * config.depth,
+            ));
         }
-        if has_air_within_dist(config, global_position, 2) {
-            *v = 1;
+    }
+    columns
+}
+
+/// Index of a chunk local column in `chunk_columns`.
+pub fn column_index(config: &ChunkConfig, x: i32, z: i32) -> usize {
+    ((x + 1) * (config.depth + 2) + z + 1) as usize
+}
+
+/// Block ids of the stone and water the terrain is made of.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TerrainBlocks {
+    pub stone: u32,
+    pub water: u32,
+}
+
+impl TerrainBlocks {
+    /// Look up the blocks by their ident.
+    pub fn new(dict: &BlockDictionary) -> anyhow::Result<Self> {
+        let id = |ident| {
+            find_block(dict, ident)
+                .ok_or_else(|| anyhow!("The terrain block {} isn't defined", ident))
+        };
+        Ok(Self {
+            stone: id("stone")?,
+            water: id("water")?,
+        })
+    }
+}
+
+/// Fills the ground with stone, and the air below sea level with water.
+pub struct TerrainPass {
+    blocks: TerrainBlocks,
+}
+
+impl TerrainPass {
+    pub fn new(dict: &BlockDictionary) -> anyhow::Result<Self> {
+        Ok(Self {
+            blocks: TerrainBlocks::new(dict)?,
+        })
+    }
+}
+
+impl GenerationPass for TerrainPass {
+    fn name(&self) -> &'static str {
+        "terrain"
+    }
+
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    ) {
+        let columns = chunk_columns(config, pos);
+        for position in area.positions(config) {
+            let column = &columns[column_index(config, position.0, position.2)];
+            let global_position = global_position(config, pos, &position);
+            let block_id = terrain_block(config, &self.blocks, column, global_position);
+            if block_id != 0 {
+                output.set(&position, block_id);
+            }
+        }
+    }
+}
+
+/// Covers the stone near the surface with the surface and subsurface blocks
+/// of the column's biome.
+pub struct SurfacePass {
+    stone: u32,
+    /// Block ids of the surface and subsurface of each biome in `Biome::ALL`.
+    blocks: [(u32, u32); Biome::ALL.len()],
+}
+
+impl SurfacePass {
+    /// Create the pass, with the blocks of the biomes looked up by their ident.
+    pub fn new(dict: &BlockDictionary) -> anyhow::Result<Self> {
+        let id = |ident| {
+            find_block(dict, ident)
+                .ok_or_else(|| anyhow!("The biome block {} isn't defined", ident))
+        };
+        let mut blocks = [(0, 0); Biome::ALL.len()];
+        for (block, biome) in blocks.iter_mut().zip(Biome::ALL) {
+            let params = biome.params();
+            *block = (id(params.surface)?, id(params.subsurface)?);
+        }
+        Ok(Self {
+            stone: TerrainBlocks::new(dict)?.stone,
+            blocks,
+        })
+    }
+}
+
+impl GenerationPass for SurfacePass {
+    fn name(&self) -> &'static str {
+        "surface"
+    }
+
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    ) {
+        let columns = chunk_columns(config, pos);
+        for position in area.positions(config) {
+            if output.get(&position) != self.stone {
+                continue;
+            }
+
+            let column = &columns[column_index(config, position.0, position.2)];
+            let global_position = global_position(config, pos, &position);
+            let (surface, subsurface) = self.blocks[column.biome as usize];
+            if has_air_within_dist(config, column, global_position, 2) {
+                output.set(&position, surface);
+            } else if has_air_within_dist(config, column, global_position, 4) {
+                output.set(&position, subsurface);
+            }
+        }
+    }
+}
+
+/// Fills flat worlds with stone, a few layers of dirt and grass on top at the
+/// ground level.
+pub struct FlatPass {
+    grass: u32,
+    dirt: u32,
+    stone: u32,
+}
+
+impl FlatPass {
+    /// Create the pass, with its blocks looked up by their ident.
+    pub fn new(dict: &BlockDictionary) -> anyhow::Result<Self> {
+        let id = |ident| {
+            find_block(dict, ident)
+                .ok_or_else(|| anyhow!("The flat world block {} isn't defined", ident))
+        };
+        Ok(Self {
+            grass: id("grass")?,
+            dirt: id("dirt")?,
+            stone: id("stone")?,
+        })
+    }
+}
+
+impl GenerationPass for FlatPass {
+    fn name(&self) -> &'static str {
+        "flat"
+    }
+
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    ) {
+        let ground = config.settings.ground_level;
+        for position in area.positions(config) {
+            let y = global_position(config, pos, &position)[1];
+            let block_id = match ground - y {
+                0 => self.grass,
+                depth if (1..=FLAT_DIRT).contains(&depth) => self.dirt,
+                depth if depth > FLAT_DIRT => self.stone,
+                _ => continue,
+            };
+            output.set(&position, block_id);
         }
     }
 }
 
 use libnoise::prelude::*;
 
-pub fn has_air_within_dist(config: &ChunkConfig, pos: [i32; 3], dist: i32) -> bool {
+pub fn has_air_within_dist(
+    config: &ChunkConfig,
+    column: &TerrainColumn,
+    pos: [i32; 3],
+    dist: i32,
+) -> bool {
     for i in 0..dist.abs() {
-        if get_terrain_at(config, [pos[0], pos[1] + dist.signum() * i, pos[2]]) >= 0.0 {
+        let above = [pos[0], pos[1] + dist.signum() * i, pos[2]];
+        if terrain_density(config, column, above) >= 0.0 {
             return true;
         }
     }
@@ -56,112 +365,392 @@ pub fn has_air_within_dist(config: &ChunkConfig, pos: [i32; 3], dist: i32) -> bo
     false
 }
 
+/// Get the terrain noise at a position, the ground is where it's below 0.
 pub fn get_terrain_at(config: &ChunkConfig, global_position: [i32; 3]) -> f64 {
+    let column = terrain_column(config, global_position[0], global_position[2]);
+    terrain_density(config, &column, global_position)
+}
+
+/// Same as `get_terrain_at`, for a position in the column.
+pub fn terrain_density(
+    config: &ChunkConfig,
+    column: &TerrainColumn,
+    global_position: [i32; 3],
+) -> f64 {
+    let settings = &config.settings;
     let mut noise_position = [
-        config.noise_amplitude.0 * global_position[0] as f64,
-        config.noise_amplitude.1 * global_position[1] as f64,
-        config.noise_amplitude.2 * global_position[2] as f64,
+        settings.noise_amplitude.0 * global_position[0] as f64,
+        settings.noise_amplitude.1 * global_position[1] as f64,
+        settings.noise_amplitude.2 * global_position[2] as f64,
     ];
 
     let mut noise = 0.0;
     let mut amplitude = 0.5;
-    for _ in 0..4 {
+    for _ in 0..settings.octaves {
         noise += amplitude * (&config.noise).sample(noise_position);
-        noise_position[0] *= 2.0;
-        noise_position[1] *= 2.0;
-        noise_position[2] *= 2.0;
-        amplitude *= 0.5;
+        noise_position[0] *= settings.lacunarity;
+        noise_position[1] *= settings.lacunarity;
+        noise_position[2] *= settings.lacunarity;
+        amplitude *= settings.persistence;
     }
 
-    noise + ground_threshold(config, global_position[1])
-    // + island_threshold(config, global_position)
+    match settings.generator {
+        GeneratorKind::FloatingIslands => noise + island_threshold(column, global_position[1]),
+        _ => noise + ground_threshold(column, global_position[1]),
+    }
 }
 
-pub fn generate_terrain(config: &ChunkConfig, pos: &Position, output: &mut ChunkData) {
-    for x in (-1)..(config.depth + 1) {
-        for y in (-1)..(config.depth + 1) {
-            for z in (-1)..(config.depth + 1) {
-                let position = (x, y, z);
-
-                let global_position = [
-                    x + pos.0 * config.depth,
-                    y + pos.1 * config.depth,
-                    z + pos.2 * config.depth,
-                ];
-
-                if get_terrain_at(config, global_position) < 0.0 {
-                    output.insert(position, 2);
-                }
-            }
+/// Bias of the terrain noise of floating islands at a height, the islands
+/// are thickest `ISLAND_HEIGHT` above the column's height.
+pub fn island_threshold(column: &TerrainColumn, pos: i32) -> f64 {
+    let center = column.height + ISLAND_HEIGHT;
+    (pos as f64 - center).abs() / ISLAND_SPREAD + ISLAND_BIAS
+}
+
+/// Get the lowest and highest heights the ground of a column can be at, the
+/// noise can't make ground outside of them.
+fn ground_range(config: &ChunkConfig, column: &TerrainColumn) -> (f64, f64) {
+    let range = config.settings.noise_range();
+    match config.settings.generator {
+        GeneratorKind::FloatingIslands => {
+            let spread = ISLAND_SPREAD * (range - ISLAND_BIAS).max(0.0);
+            let center = column.height + ISLAND_HEIGHT;
+            (center - spread, center + spread)
         }
+        _ => (
+            column.height - column.squish * range,
+            column.height + column.squish * range,
+        ),
     }
 }
 
-pub fn load_chunk(config: &ChunkConfig, pos: &Position) -> ChunkData {
-    // load chuhnk from fs
+/// Get the height of the highest block of the terrain in a column, before
+/// caves are carved out of it, if it has any.
+pub fn ground_height(config: &ChunkConfig, column: &TerrainColumn, x: i32, z: i32) -> Option<i32> {
+    let (bottom, top) = ground_range(config, column);
+    (bottom.floor() as i32..=top.ceil() as i32)
+        .rev()
+        .find(|y| terrain_density(config, column, [x, *y, z]) < 0.0)
+}
 
-    // if no chunk data found,
-    // generate chunk
-    generate(config, pos)
+/// Get the block of the terrain at a position, before foliage.
+pub fn terrain_block(
+    config: &ChunkConfig,
+    blocks: &TerrainBlocks,
+    column: &TerrainColumn,
+    global_position: [i32; 3],
+) -> u32 {
+    if terrain_density(config, column, global_position) < 0.0 {
+        blocks.stone
+    } else if config.settings.generator == GeneratorKind::Terrain
+        && global_position[1] <= SEA_LEVEL
+        && global_position[1] as f64 > column.height - SEA_DEPTH
+    {
+        blocks.water
+    } else {
+        0
+    }
+}
+
+/// Load the chunk from storage, or generate it if it was never saved.
+/// Also returns whether the chunk was generated because it was never saved,
+/// and has to be saved. A chunk that fails to load is generated too, but isn't
+/// saved unless it's edited.
+pub fn load_chunk(
+    config: &ChunkConfig,
+    storage: &RegionStorage,
+    pos: &ChunkPos,
+) -> (ChunkData, bool) {
+    match storage.load(config, pos) {
+        Ok(Some(mut chunk)) => {
+            generate_border(config, pos, &mut chunk);
+            (chunk, false)
+        }
+        // if no chunk data found,
+        // generate chunk
+        Ok(None) => (generate(config, pos), true),
+        Err(e) => {
+            log::warn!(
+                "Failed to load chunk {}, generating it instead: {:#}",
+                pos,
+                e
+            );
+            (generate(config, pos), false)
+        }
+    }
 }
 
 #[allow(dead_code)]
-pub fn ao_test() -> ChunkData {
-    let mut output = ChunkData::new();
-
-    output.insert((0, 0, 0), 1);
-    output.insert((0, 0, 1), 1);
-    output.insert((1, 0, 0), 1);
-    output.insert((1, 1, 1), 1);
-    output.insert((2, 0, 0), 1);
-    output.insert((0, 0, 2), 1);
-    output.insert((1, 0, 2), 1);
-    output.insert((2, 0, 1), 1);
-    output.insert((2, 0, 2), 1);
-    output.insert((0, 2, 0), 1);
-    output.insert((0, 2, 1), 1);
-    output.insert((1, 2, 0), 1);
-    output.insert((2, 2, 0), 1);
-    output.insert((0, 2, 2), 1);
-    output.insert((1, 2, 2), 1);
-    output.insert((2, 2, 1), 1);
-    output.insert((2, 2, 2), 1);
-
-    output.insert((4, 0, 0), 1);
-    output.insert((4, 1, 0), 1);
-    output.insert((4, 2, 0), 1);
-    output.insert((5, 0, 0), 1);
-    output.insert((5, 2, 0), 1);
-    output.insert((6, 0, 0), 1);
-    output.insert((6, 1, 0), 1);
-    output.insert((6, 2, 0), 1);
-    output.insert((5, 1, 1), 1);
-    output.insert((4, 0, 2), 1);
-    output.insert((4, 1, 2), 1);
-    output.insert((4, 2, 2), 1);
-    output.insert((5, 0, 2), 1);
-    output.insert((5, 2, 2), 1);
-    output.insert((6, 0, 2), 1);
-    output.insert((6, 1, 2), 1);
-    output.insert((6, 2, 2), 1);
-
-    output.insert((8, 0, 0), 1);
-    output.insert((8, 0, 1), 1);
-    output.insert((8, 0, 2), 1);
-    output.insert((8, 1, 0), 1);
-    output.insert((8, 1, 2), 1);
-    output.insert((8, 2, 0), 1);
-    output.insert((8, 2, 1), 1);
-    output.insert((8, 2, 2), 1);
-    output.insert((9, 1, 1), 1);
-    output.insert((10, 0, 0), 1);
-    output.insert((10, 0, 1), 1);
-    output.insert((10, 0, 2), 1);
-    output.insert((10, 1, 0), 1);
-    output.insert((10, 1, 2), 1);
-    output.insert((10, 2, 0), 1);
-    output.insert((10, 2, 1), 1);
-    output.insert((10, 2, 2), 1);
+pub fn ao_test(depth: i32) -> ChunkData {
+    let mut output = ChunkData::new(depth);
+
+    output.set(&(0, 0, 0), 1);
+    output.set(&(0, 0, 1), 1);
+    output.set(&(1, 0, 0), 1);
+    output.set(&(1, 1, 1), 1);
+    output.set(&(2, 0, 0), 1);
+    output.set(&(0, 0, 2), 1);
+    output.set(&(1, 0, 2), 1);
+    output.set(&(2, 0, 1), 1);
+    output.set(&(2, 0, 2), 1);
+    output.set(&(0, 2, 0), 1);
+    output.set(&(0, 2, 1), 1);
+    output.set(&(1, 2, 0), 1);
+    output.set(&(2, 2, 0), 1);
+    output.set(&(0, 2, 2), 1);
+    output.set(&(1, 2, 2), 1);
+    output.set(&(2, 2, 1), 1);
+    output.set(&(2, 2, 2), 1);
+
+    output.set(&(4, 0, 0), 1);
+    output.set(&(4, 1, 0), 1);
+    output.set(&(4, 2, 0), 1);
+    output.set(&(5, 0, 0), 1);
+    output.set(&(5, 2, 0), 1);
+    output.set(&(6, 0, 0), 1);
+    output.set(&(6, 1, 0), 1);
+    output.set(&(6, 2, 0), 1);
+    output.set(&(5, 1, 1), 1);
+    output.set(&(4, 0, 2), 1);
+    output.set(&(4, 1, 2), 1);
+    output.set(&(4, 2, 2), 1);
+    output.set(&(5, 0, 2), 1);
+    output.set(&(5, 2, 2), 1);
+    output.set(&(6, 0, 2), 1);
+    output.set(&(6, 1, 2), 1);
+    output.set(&(6, 2, 2), 1);
+
+    output.set(&(8, 0, 0), 1);
+    output.set(&(8, 0, 1), 1);
+    output.set(&(8, 0, 2), 1);
+    output.set(&(8, 1, 0), 1);
+    output.set(&(8, 1, 2), 1);
+    output.set(&(8, 2, 0), 1);
+    output.set(&(8, 2, 1), 1);
+    output.set(&(8, 2, 2), 1);
+    output.set(&(9, 1, 1), 1);
+    output.set(&(10, 0, 0), 1);
+    output.set(&(10, 0, 1), 1);
+    output.set(&(10, 0, 2), 1);
+    output.set(&(10, 1, 0), 1);
+    output.set(&(10, 1, 2), 1);
+    output.set(&(10, 2, 0), 1);
+    output.set(&(10, 2, 1), 1);
+    output.set(&(10, 2, 2), 1);
 
     output
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::chunk::biome::{biome_at, Biome};
+    use crate::chunk::settings::WorldPreset;
+    use crate::chunk::structures::parse_structures;
+    use crate::chunk::{chunk_position, local_position};
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 16, 3)
+    }
+
+    /// Find a column of the biome, searching outwards from the origin.
+    fn find_column(config: &ChunkConfig, biome: Biome) -> (i32, i32) {
+        for distance in 0..100 {
+            for x in -distance..=distance {
+                for z in -distance..=distance {
+                    let position = (x * 64, 0, z * 64);
+                    if biome_at(config, &position) == biome {
+                        return (position.0, position.2);
+                    }
+                }
+            }
+        }
+        panic!("no {:?} biome", biome);
+    }
+
+    /// Get the generated block at a world space position.
+    fn block_at(config: &ChunkConfig, position: &Position) -> u32 {
+        let chunk = generate(config, &chunk_position(config, position));
+        chunk.get(&local_position(config, position))
+    }
+
+    #[test]
+    fn biome_surface_test() {
+        let config = create_mock_config();
+        for biome in Biome::ALL {
+            let (x, z) = find_column(&config, biome);
+            let ground = (-128..256)
+                .rev()
+                .find(|y| get_terrain_at(&config, [x, *y, z]) < 0.0)
+                .unwrap();
+
+            let params = biome.params();
+            let ident = |block_id: u32| config.dict[&block_id].ident.as_str();
+            assert_eq!(
+                ident(block_at(&config, &(x, ground, z))),
+                params.surface,
+                "{:?}",
+                biome
+            );
+            let below = ident(block_at(&config, &(x, ground - 2, z)));
+            assert!(below == params.subsurface || below == "air", "{:?}", biome);
+
+            if ground >= SEA_LEVEL {
+                assert_eq!(block_at(&config, &(x, ground + 1, z)), 0);
+            }
+        }
+
+        // oceans are covered by water up to sea level
+        let (x, z) = find_column(&config, Biome::Ocean);
+        let water = find_block(&config.dict, "water").unwrap();
+        assert_eq!(block_at(&config, &(x, SEA_LEVEL, z)), water);
+        assert_eq!(block_at(&config, &(x, SEA_LEVEL + 1, z)), 0);
+    }
+
+    /// Create a config for a world generated with a preset.
+    fn create_preset_config(preset: WorldPreset) -> ChunkConfig {
+        let mut config = create_mock_config();
+        config.settings = WorldGenSettings {
+            seed: config.settings.seed,
+            ..WorldGenSettings::preset(preset)
+        };
+        let structures =
+            parse_structures(include_str!("../../assets/structures.ron"), &config.dict).unwrap();
+        config.passes = world_passes(&config.settings, &config.dict, structures).unwrap();
+        config
+    }
+
+    #[test]
+    fn flat_test() {
+        let config = create_preset_config(WorldPreset::Superflat);
+        let ground = config.settings.ground_level;
+        let id = |ident| find_block(&config.dict, ident).unwrap();
+        assert_eq!(block_at(&config, &(5, ground + 1, -20)), 0);
+        assert_eq!(block_at(&config, &(5, ground, -20)), id("grass"));
+        assert_eq!(block_at(&config, &(5, ground - FLAT_DIRT, -20)), id("dirt"));
+        let stone = id("stone");
+        assert_eq!(block_at(&config, &(5, ground - FLAT_DIRT - 1, -20)), stone);
+        assert_eq!(block_at(&config, &(5, ground - 200, -20)), stone);
+
+        let config = create_preset_config(WorldPreset::Void);
+        assert!(config.passes.is_empty());
+        assert!(generate(&config, &ChunkPos::new(0, -1, 0)).is_empty());
+    }
+
+    #[test]
+    fn islands_test() {
+        let config = create_preset_config(WorldPreset::FloatingIslands);
+        let blocks = TerrainBlocks::new(&config.dict).unwrap();
+
+        // every column is empty from its height down, and some have islands
+        // above it with the biome's surface on top, unless a cave cut it out
+        let mut islands = 0;
+        for x in (0..640).step_by(16) {
+            let column = terrain_column(&config, x, 0);
+            let below = column.height.floor() as i32;
+            assert_eq!(terrain_block(&config, &blocks, &column, [x, below, 0]), 0);
+            let sea = terrain_block(&config, &blocks, &column, [x, SEA_LEVEL, 0]);
+            assert_eq!(sea, 0);
+
+            if let Some(ground) = ground_height(&config, &column, x, 0) {
+                assert!(ground as f64 > column.height + ISLAND_HEIGHT - ISLAND_SPREAD);
+                let block_id = block_at(&config, &(x, ground, 0));
+                if config.dict[&block_id].ident == column.biome.params().surface {
+                    islands += 1;
+                } else {
+                    assert_eq!(block_id, 0);
+                }
+            }
+        }
+        assert!(islands > 0);
+    }
+
+    #[test]
+    fn amplified_test() {
+        // the same hills, only taller
+        let default = create_preset_config(WorldPreset::Default);
+        let amplified = create_preset_config(WorldPreset::Amplified);
+        let spread = |config: &ChunkConfig| {
+            let heights: Vec<i32> = (0..640)
+                .step_by(8)
+                .filter_map(|x| ground_height(config, &terrain_column(config, x, 0), x, 0))
+                .collect();
+            heights.iter().max().unwrap() - heights.iter().min().unwrap()
+        };
+        assert!(spread(&amplified) > spread(&default) * 2);
+    }
+
+    #[test]
+    fn unknown_biome_block_test() {
+        let mut config = create_mock_config();
+        let snow = find_block(&config.dict, "snow").unwrap();
+        config.dict.remove(&snow);
+        let error = SurfacePass::new(&config.dict).err().unwrap().to_string();
+        assert!(error.contains("snow"), "{}", error);
+    }
+
+    #[test]
+    fn unknown_terrain_block_test() {
+        let mut config = create_mock_config();
+        let water = find_block(&config.dict, "water").unwrap();
+        config.dict.remove(&water);
+        let error = world_passes(&config.settings, &config.dict, Vec::new())
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.contains("water"), "{}", error);
+    }
+
+    #[test]
+    fn passes_test() {
+        let config = create_mock_config();
+        let names: Vec<&str> = config.passes.iter().map(|pass| pass.name()).collect();
+        assert_eq!(names, ["terrain", "caves", "surface", "ores", "structures"]);
+    }
+
+    #[test]
+    fn chunk_order_test() {
+        let config = create_mock_config();
+        let mut positions = Vec::new();
+        for x in 0..2 {
+            for y in -3..0 {
+                for z in 0..2 {
+                    positions.push(ChunkPos::new(x, y, z));
+                }
+            }
+        }
+        let chunks: HashMap<ChunkPos, ChunkData> = positions
+            .iter()
+            .rev()
+            .map(|pos| (*pos, generate(&config, pos)))
+            .collect();
+
+        for pos in positions.iter() {
+            let chunk = &chunks[pos];
+
+            // the same in any order, and without the chunks around it
+            assert_eq!(&generate(&config, pos), chunk);
+
+            // the border matches the neighbors, so caves and veins that
+            // cross chunks line up
+            for position in GenerationArea::Border.positions(&config) {
+                let global = global_position(&config, pos, &position);
+                let global = (global[0], global[1], global[2]);
+                if let Some(neighbor) = chunks.get(&chunk_position(&config, &global)) {
+                    assert_eq!(
+                        chunk.get(&position),
+                        neighbor.get(&local_position(&config, &global))
+                    );
+                }
+            }
+
+            // and the border of a stored chunk is generated the same
+            let mut stored = chunk.clone();
+            stored.clear_border();
+            generate_border(&config, pos, &mut stored);
+            assert_eq!(&stored, chunk);
+        }
+    }
+}
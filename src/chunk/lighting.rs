@@ -0,0 +1,827 @@
+//! Flood fill lighting, with a skylight channel lit from above and a block
+//! light channel lit by blocks that give off light.
+//!
+//! Light spreads to neighboring blocks that let light through, losing a level
+//! every block, except for full skylight which goes straight down without
+//! losing any. Chunks that aren't loaded are dark, but the sky is assumed to be
+//! open above a chunk when the chunk above it isn't loaded.
+//!
+//! A new chunk is lit on its own with `light_new_chunk`, which is slow and runs
+//! on the thread pool, and `merge_light` spreads its light across the borders
+//! into the loaded chunks around it.
+
+use std::collections::{HashMap, VecDeque};
+
+use indexmap::IndexSet;
+
+use super::{offset_position, ChunkConfig, ChunkData, ChunkPos, ChunkStorage, Position, NEIGHBORS};
+
+/// Light level of the open sky, and the brightest a block can give off.
+pub const MAX_LIGHT: u8 = 15;
+
+const DOWN: Position = (0, -1, 0);
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum LightChannel {
+    Sky,
+    Block,
+}
+
+/// The light levels of a chunk and its one block border, the border is a copy
+/// of the neighbors' light so the chunk can be meshed on its own.
+///
+/// Both channels are packed into a byte per block, skylight in the upper 4 bits.
+/// Like `ChunkData`, the array isn't allocated while every block has the same light.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LightData {
+    depth: i32,
+    fill: u8,
+    values: Vec<u8>,
+}
+
+impl LightData {
+    /// Create a chunk of light with no light at all.
+    pub fn new(depth: i32) -> Self {
+        Self::filled(depth, 0, 0)
+    }
+
+    /// Create a chunk of light with the same light everywhere.
+    pub fn filled(depth: i32, sky: u8, block: u8) -> Self {
+        Self {
+            depth,
+            fill: pack(sky, block),
+            values: Vec::new(),
+        }
+    }
+
+    pub fn depth(&self) -> i32 {
+        self.depth
+    }
+
+    fn index(&self, pos: &Position) -> Option<usize> {
+        let size = self.depth + 2;
+        let (x, y, z) = (pos.0 + 1, pos.1 + 1, pos.2 + 1);
+        if x < 0 || y < 0 || z < 0 || x >= size || y >= size || z >= size {
+            return None;
+        }
+        Some((x + size * (y + size * z)) as usize)
+    }
+
+    /// Get both channels packed into a byte, skylight in the upper 4 bits.
+    /// Positions outside of the chunk and its border have no light.
+    pub fn get_packed(&self, pos: &Position) -> u8 {
+        match self.index(pos) {
+            Some(_) if self.values.is_empty() => self.fill,
+            Some(index) => self.values[index],
+            None => 0,
+        }
+    }
+
+    /// Get the light level of a channel at a chunk local position.
+    pub fn get(&self, pos: &Position, channel: LightChannel) -> u8 {
+        let packed = self.get_packed(pos);
+        match channel {
+            LightChannel::Sky => packed >> 4,
+            LightChannel::Block => packed & 0xF,
+        }
+    }
+
+    /// Set the light level of a channel at a chunk local position. Returns false,
+    /// and does nothing, if the position is outside of the chunk and its border.
+    pub fn set(&mut self, pos: &Position, channel: LightChannel, level: u8) -> bool {
+        let index = match self.index(pos) {
+            Some(index) => index,
+            None => return false,
+        };
+
+        let packed = self.get_packed(pos);
+        let packed = match channel {
+            LightChannel::Sky => pack(level, packed & 0xF),
+            LightChannel::Block => pack(packed >> 4, level),
+        };
+
+        if self.values.is_empty() {
+            if packed == self.fill {
+                return true;
+            }
+            let size = (self.depth + 2) as usize;
+            self.values = vec![self.fill; size * size * size];
+        }
+        self.values[index] = packed;
+
+        true
+    }
+}
+
+fn pack(sky: u8, block: u8) -> u8 {
+    (sky.min(MAX_LIGHT) << 4) | block.min(MAX_LIGHT)
+}
+
+pub type LightStorage = HashMap<ChunkPos, LightData>;
+
+/// The blocks the light spreads through.
+#[derive(Clone, Copy)]
+enum Blocks<'a> {
+    Loaded(&'a ChunkStorage),
+    /// A single chunk, with nothing loaded around it.
+    Alone(ChunkPos, &'a ChunkData),
+}
+
+/// The loaded chunks and their light, looked up by world space positions.
+struct LightWorld<'a> {
+    config: &'a ChunkConfig,
+    blocks: Blocks<'a>,
+    lights: &'a mut LightStorage,
+    /// Chunks whose light, or the light in their border, changed.
+    changed: IndexSet<ChunkPos>,
+}
+
+impl<'a> LightWorld<'a> {
+    fn new(config: &'a ChunkConfig, blocks: Blocks<'a>, lights: &'a mut LightStorage) -> Self {
+        Self {
+            config,
+            blocks,
+            lights,
+            changed: IndexSet::new(),
+        }
+    }
+
+    fn chunk_pos(&self, pos: &Position) -> ChunkPos {
+        let depth = self.config.depth;
+        ChunkPos::new(
+            pos.0.div_euclid(depth),
+            pos.1.div_euclid(depth),
+            pos.2.div_euclid(depth),
+        )
+    }
+
+    fn local(&self, chunk_pos: &ChunkPos, pos: &Position) -> Position {
+        let depth = self.config.depth;
+        (
+            pos.0 - chunk_pos.x * depth,
+            pos.1 - chunk_pos.y * depth,
+            pos.2 - chunk_pos.z * depth,
+        )
+    }
+
+    fn is_loaded(&self, pos: &Position) -> bool {
+        self.lights.contains_key(&self.chunk_pos(pos))
+    }
+
+    fn block_id(&self, pos: &Position) -> u32 {
+        let chunk_pos = self.chunk_pos(pos);
+        let chunk = match self.blocks {
+            Blocks::Loaded(chunks) => chunks.get(&chunk_pos),
+            Blocks::Alone(alone_pos, chunk) => (alone_pos == chunk_pos).then_some(chunk),
+        };
+        match chunk {
+            Some(chunk) => chunk.get(&self.local(&chunk_pos, pos)),
+            None => 0,
+        }
+    }
+
+    fn passes_light(&self, pos: &Position) -> bool {
+        self.config
+            .dict
+            .get(&self.block_id(pos))
+            .is_none_or(|block| block.transparent)
+    }
+
+    /// The light the block at the position makes on its own.
+    fn source(&self, pos: &Position, channel: LightChannel) -> u8 {
+        match channel {
+            // open sky above
+            LightChannel::Sky
+                if self.passes_light(pos) && !self.is_loaded(&offset_position(pos, &(0, 1, 0))) =>
+            {
+                MAX_LIGHT
+            }
+            LightChannel::Sky => 0,
+            LightChannel::Block => self
+                .config
+                .dict
+                .get(&self.block_id(pos))
+                .map_or(0, |block| block.light_emission),
+        }
+    }
+
+    fn get(&self, pos: &Position, channel: LightChannel) -> u8 {
+        let chunk_pos = self.chunk_pos(pos);
+        match self.lights.get(&chunk_pos) {
+            Some(light) => light.get(&self.local(&chunk_pos, pos), channel),
+            None => 0,
+        }
+    }
+
+    /// Set the light at a position in the chunk it belongs to, and in the
+    /// border of every loaded neighbor the position is in.
+    fn set(&mut self, pos: &Position, channel: LightChannel, level: u8) {
+        let depth = self.config.depth;
+        let owner = self.chunk_pos(pos);
+        let local = self.local(&owner, pos);
+        let offsets = |local: i32| match local {
+            0 => -1..=0,
+            l if l == depth - 1 => 0..=1,
+            _ => 0..=0,
+        };
+
+        for x in offsets(local.0) {
+            for y in offsets(local.1) {
+                for z in offsets(local.2) {
+                    let chunk_pos = owner.offset(x, y, z);
+                    let local = self.local(&chunk_pos, pos);
+                    if let Some(light) = self.lights.get_mut(&chunk_pos) {
+                        if light.get(&local, channel) != level {
+                            light.set(&local, channel, level);
+                            self.changed.insert(chunk_pos);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    /// Spread the light of the positions in the queue to their neighbors.
+    fn propagate(&mut self, channel: LightChannel, mut queue: VecDeque<Position>) {
+        while let Some(pos) = queue.pop_front() {
+            let level = self.get(&pos, channel);
+            for offset in NEIGHBORS.iter() {
+                let neighbor = offset_position(&pos, offset);
+                if !self.is_loaded(&neighbor) || !self.passes_light(&neighbor) {
+                    continue;
+                }
+
+                let spread =
+                    if channel == LightChannel::Sky && *offset == DOWN && level == MAX_LIGHT {
+                        MAX_LIGHT
+                    } else {
+                        level.saturating_sub(1)
+                    };
+                if self.get(&neighbor, channel) < spread {
+                    self.set(&neighbor, channel, spread);
+                    queue.push_back(neighbor);
+                }
+            }
+        }
+    }
+
+    /// Remove the light that came from the positions in the queue, which are
+    /// already dark, along with the level they used to have.
+    /// The light of the positions around the removed light is spread back in.
+    fn remove(&mut self, channel: LightChannel, mut queue: VecDeque<(Position, u8)>) {
+        let mut relight = VecDeque::new();
+        while let Some((pos, level)) = queue.pop_front() {
+            for offset in NEIGHBORS.iter() {
+                let neighbor = offset_position(&pos, offset);
+                if !self.is_loaded(&neighbor) {
+                    continue;
+                }
+                let neighbor_level = self.get(&neighbor, channel);
+                if neighbor_level == 0 {
+                    continue;
+                }
+
+                // the neighbor might have been lit by the removed light
+                let lit_by_pos = neighbor_level < level
+                    || (channel == LightChannel::Sky
+                        && *offset == DOWN
+                        && level == MAX_LIGHT
+                        && neighbor_level == MAX_LIGHT);
+                if lit_by_pos {
+                    self.set(&neighbor, channel, 0);
+                    queue.push_back((neighbor, neighbor_level));
+
+                    let source = self.source(&neighbor, channel);
+                    if source > 0 {
+                        self.set(&neighbor, channel, source);
+                        relight.push_back(neighbor);
+                    }
+                } else {
+                    relight.push_back(neighbor);
+                }
+            }
+        }
+
+        self.propagate(channel, relight);
+    }
+
+    fn changed(self) -> Vec<ChunkPos> {
+        self.changed.into_iter().collect()
+    }
+}
+
+/// Light a chunk on its own, as if none of its neighbors were loaded and the
+/// sky was open above it. The border is left dark, `merge_light` fills it in.
+pub fn light_new_chunk(config: &ChunkConfig, chunk_pos: &ChunkPos, chunk: &ChunkData) -> LightData {
+    let depth = config.depth;
+    let mut lights = LightStorage::from([(*chunk_pos, LightData::new(depth))]);
+    let mut world = LightWorld::new(config, Blocks::Alone(*chunk_pos, chunk), &mut lights);
+    let origin = chunk_origin(config, chunk_pos);
+
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+    for x in 0..depth {
+        for y in 0..depth {
+            for z in 0..depth {
+                let pos = offset_position(&origin, &(x, y, z));
+                let emission = world.source(&pos, LightChannel::Block);
+                if emission > 0 {
+                    world.set(&pos, LightChannel::Block, emission);
+                    block.push_back(pos);
+                }
+                if y == depth - 1 && world.passes_light(&pos) {
+                    world.set(&pos, LightChannel::Sky, MAX_LIGHT);
+                    sky.push_back(pos);
+                }
+            }
+        }
+    }
+
+    world.propagate(LightChannel::Sky, sky);
+    world.propagate(LightChannel::Block, block);
+    lights.remove(chunk_pos).unwrap()
+}
+
+/// Add the light of a new chunk from `light_new_chunk` to the loaded chunks,
+/// and spread the light across its borders. The chunk's blocks have to be in
+/// `chunks` already.
+/// Returns the chunks whose light changed, the chunk itself first.
+pub fn merge_light(
+    config: &ChunkConfig,
+    chunks: &ChunkStorage,
+    lights: &mut LightStorage,
+    chunk_pos: &ChunkPos,
+    light: LightData,
+) -> Vec<ChunkPos> {
+    if !chunks.contains_key(chunk_pos) {
+        return vec![];
+    }
+    let depth = config.depth;
+    let above_loaded = lights.contains_key(&chunk_pos.offset(0, 1, 0));
+    let below_loaded = lights.contains_key(&chunk_pos.offset(0, -1, 0));
+    lights.insert(*chunk_pos, light);
+
+    let mut world = LightWorld::new(config, Blocks::Loaded(chunks), lights);
+    world.changed.insert(*chunk_pos);
+    let origin = chunk_origin(config, chunk_pos);
+    let on_edge = |local: i32| local == 0 || local == depth - 1;
+
+    let mut sky = VecDeque::new();
+    let mut block = VecDeque::new();
+    for x in -1..=depth {
+        for y in -1..=depth {
+            for z in -1..=depth {
+                let pos = offset_position(&origin, &(x, y, z));
+                let inside =
+                    (0..depth).contains(&x) && (0..depth).contains(&y) && (0..depth).contains(&z);
+
+                if inside {
+                    if !(on_edge(x) || on_edge(y) || on_edge(z)) {
+                        continue;
+                    }
+                    // copy the light at the edge into the borders of the
+                    // neighbors, they might remember the light of the chunk from
+                    // when it was loaded before, and spread it into them
+                    for (channel, queue) in [
+                        (LightChannel::Sky, &mut sky),
+                        (LightChannel::Block, &mut block),
+                    ] {
+                        let level = world.get(&pos, channel);
+                        world.set(&pos, channel, level);
+                        if level > 0 {
+                            queue.push_back(pos);
+                        }
+                    }
+                    continue;
+                }
+
+                // copy the light of the loaded neighbors into the border,
+                // and spread it into the chunk
+                let loaded = world.is_loaded(&pos);
+                let light = if loaded {
+                    [
+                        world.get(&pos, LightChannel::Sky),
+                        world.get(&pos, LightChannel::Block),
+                    ]
+                } else if y == depth && world.passes_light(&pos) {
+                    // open sky above
+                    [MAX_LIGHT, 0]
+                } else {
+                    continue;
+                };
+                let chunk_light = world.lights.get_mut(chunk_pos).unwrap();
+                chunk_light.set(&(x, y, z), LightChannel::Sky, light[0]);
+                chunk_light.set(&(x, y, z), LightChannel::Block, light[1]);
+                if loaded && light[0] > 0 {
+                    sky.push_back(pos);
+                }
+                if loaded && light[1] > 0 {
+                    block.push_back(pos);
+                }
+            }
+        }
+    }
+
+    world.propagate(LightChannel::Sky, sky);
+    world.propagate(LightChannel::Block, block);
+
+    // the chunk was lit as if the sky was open above it, and so was the chunk
+    // below it
+    if above_loaded {
+        remove_open_sky(&mut world, &offset_position(&origin, &(0, depth, 0)));
+    }
+    if below_loaded {
+        remove_open_sky(&mut world, &origin);
+    }
+
+    world.changed()
+}
+
+/// Light a chunk that was just loaded, and spread its light into its loaded
+/// neighbors. The chunk's blocks have to be in `chunks` already.
+/// Returns the chunks whose light changed, the chunk itself first.
+pub fn light_chunk(
+    config: &ChunkConfig,
+    chunks: &ChunkStorage,
+    lights: &mut LightStorage,
+    chunk_pos: &ChunkPos,
+) -> Vec<ChunkPos> {
+    let light = match chunks.get(chunk_pos) {
+        Some(chunk) => light_new_chunk(config, chunk_pos, chunk),
+        None => return vec![],
+    };
+    merge_light(config, chunks, lights, chunk_pos, light)
+}
+
+fn chunk_origin(config: &ChunkConfig, chunk_pos: &ChunkPos) -> Position {
+    let depth = config.depth;
+    (
+        chunk_pos.x * depth,
+        chunk_pos.y * depth,
+        chunk_pos.z * depth,
+    )
+}
+
+/// Remove the full skylight under the bottom layer of a chunk, starting at
+/// `bottom`, where the sky isn't open above it.
+fn remove_open_sky(world: &mut LightWorld, bottom: &Position) {
+    let depth = world.config.depth;
+    let mut removed = VecDeque::new();
+    for x in 0..depth {
+        for z in 0..depth {
+            let pos = offset_position(bottom, &(x, 0, z));
+            let below = offset_position(&pos, &DOWN);
+            if world.get(&below, LightChannel::Sky) == MAX_LIGHT
+                && (world.get(&pos, LightChannel::Sky) < MAX_LIGHT || !world.passes_light(&pos))
+            {
+                world.set(&below, LightChannel::Sky, 0);
+                removed.push_back((below, MAX_LIGHT));
+            }
+        }
+    }
+    world.remove(LightChannel::Sky, removed);
+}
+
+/// Update the light around a block that changed, the block has to be changed
+/// in `chunks` already.
+/// Returns the chunks whose light changed.
+pub fn update_light(
+    config: &ChunkConfig,
+    chunks: &ChunkStorage,
+    lights: &mut LightStorage,
+    position: &Position,
+) -> Vec<ChunkPos> {
+    let mut world = LightWorld::new(config, Blocks::Loaded(chunks), lights);
+    if !world.is_loaded(position) {
+        return vec![];
+    }
+
+    for channel in [LightChannel::Sky, LightChannel::Block] {
+        let level = world.get(position, channel);
+        world.set(position, channel, 0);
+        world.remove(channel, VecDeque::from([(*position, level)]));
+
+        // the block might give off light, or let the light around it through
+        let mut queue = VecDeque::new();
+        let source = world.source(position, channel);
+        if source > 0 {
+            world.set(position, channel, source);
+            queue.push_back(*position);
+        }
+        for offset in NEIGHBORS.iter() {
+            let neighbor = offset_position(position, offset);
+            if world.get(&neighbor, channel) > 0 {
+                queue.push_back(neighbor);
+            }
+        }
+        world.propagate(channel, queue);
+    }
+
+    world.changed()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::editing::set_block;
+    use crate::chunk::ChunkData;
+
+    const STONE: u32 = 2;
+    const LAMP: u32 = 4;
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 8, 3)
+    }
+
+    fn create_chunks(config: &ChunkConfig, positions: &[ChunkPos]) -> ChunkStorage {
+        positions
+            .iter()
+            .map(|pos| (*pos, ChunkData::new(config.depth)))
+            .collect()
+    }
+
+    /// Throw away the light of every chunk and light them all again, top down so
+    /// the sky is lit before the chunks below it.
+    fn light_all(config: &ChunkConfig, chunks: &ChunkStorage, lights: &mut LightStorage) {
+        lights.clear();
+
+        let mut chunk_positions: Vec<ChunkPos> = chunks.keys().copied().collect();
+        chunk_positions.sort_by_key(|pos| (-pos.y, pos.x, pos.z));
+        for chunk_pos in chunk_positions {
+            light_chunk(config, chunks, lights, &chunk_pos);
+        }
+    }
+
+    /// Light the chunks one at a time in the given order.
+    fn load(config: &ChunkConfig, chunks: &ChunkStorage, order: &[ChunkPos]) -> LightStorage {
+        let mut lights = LightStorage::new();
+        for chunk_pos in order {
+            light_chunk(config, chunks, &mut lights, chunk_pos);
+        }
+        lights
+    }
+
+    /// Get the light at a world space position.
+    fn light_at(config: &ChunkConfig, lights: &LightStorage, pos: &Position) -> (u8, u8) {
+        let chunks = ChunkStorage::new();
+        let mut lights = lights.clone();
+        let world = LightWorld::new(config, Blocks::Loaded(&chunks), &mut lights);
+        (
+            world.get(pos, LightChannel::Sky),
+            world.get(pos, LightChannel::Block),
+        )
+    }
+
+    /// The light inside of every chunk, by world space position.
+    fn insides(config: &ChunkConfig, lights: &LightStorage) -> HashMap<Position, u8> {
+        let depth = config.depth;
+        let mut output = HashMap::new();
+        for (chunk_pos, light) in lights.iter() {
+            for x in 0..depth {
+                for y in 0..depth {
+                    for z in 0..depth {
+                        let pos = (
+                            chunk_pos.x * depth + x,
+                            chunk_pos.y * depth + y,
+                            chunk_pos.z * depth + z,
+                        );
+                        output.insert(pos, light.get_packed(&(x, y, z)));
+                    }
+                }
+            }
+        }
+        output
+    }
+
+    #[test]
+    fn light_data_test() {
+        let mut light = LightData::new(8);
+        assert!(light.values.is_empty());
+        assert_eq!(light.get(&(3, 3, 3), LightChannel::Sky), 0);
+
+        // setting the light everything already has doesn't allocate
+        assert!(light.set(&(3, 3, 3), LightChannel::Block, 0));
+        assert!(light.values.is_empty());
+
+        assert!(light.set(&(3, 3, 3), LightChannel::Block, 7));
+        assert!(light.set(&(-1, 8, 0), LightChannel::Sky, 12));
+        assert!(!light.set(&(-2, 0, 0), LightChannel::Sky, 12));
+        assert_eq!(light.get(&(3, 3, 3), LightChannel::Block), 7);
+        assert_eq!(light.get(&(3, 3, 3), LightChannel::Sky), 0);
+        assert_eq!(light.get(&(-1, 8, 0), LightChannel::Sky), 12);
+        assert_eq!(light.get_packed(&(-1, 8, 0)), 12 << 4);
+        assert_eq!(light.get(&(9, 0, 0), LightChannel::Sky), 0);
+
+        let light = LightData::filled(8, MAX_LIGHT, 3);
+        assert_eq!(light.get(&(0, 7, 2), LightChannel::Sky), MAX_LIGHT);
+        assert_eq!(light.get(&(8, -1, 8), LightChannel::Block), 3);
+    }
+
+    #[test]
+    fn open_sky_test() {
+        let config = create_mock_config();
+        let origin = ChunkPos::new(0, 0, 0);
+        let chunks = create_chunks(&config, &[origin]);
+        let lights = load(&config, &chunks, &[origin]);
+
+        let light = &lights[&origin];
+        for x in 0..8 {
+            for y in 0..8 {
+                for z in 0..8 {
+                    assert_eq!(light.get(&(x, y, z), LightChannel::Sky), MAX_LIGHT);
+                    assert_eq!(light.get(&(x, y, z), LightChannel::Block), 0);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn roof_test() {
+        let config = create_mock_config();
+        let origin = ChunkPos::new(0, 0, 0);
+        let mut chunks = create_chunks(&config, &[origin]);
+
+        // a stone roof with a hole at (4, 6, 4)
+        let chunk = chunks.get_mut(&origin).unwrap();
+        for x in 0..8 {
+            for z in 0..8 {
+                if (x, z) != (4, 4) {
+                    chunk.set(&(x, 6, z), STONE);
+                }
+            }
+        }
+        let lights = load(&config, &chunks, &[origin]);
+        let sky = |pos: &Position| light_at(&config, &lights, pos).0;
+
+        // the sky goes straight down through the hole, and fades under the roof
+        assert_eq!(sky(&(2, 7, 2)), MAX_LIGHT);
+        assert_eq!(sky(&(2, 6, 2)), 0);
+        for y in 0..=6 {
+            assert_eq!(sky(&(4, y, 4)), MAX_LIGHT);
+        }
+        assert_eq!(sky(&(5, 5, 4)), 14);
+        assert_eq!(sky(&(7, 0, 4)), 12);
+        assert_eq!(sky(&(0, 3, 1)), 8);
+    }
+
+    #[test]
+    fn block_light_test() {
+        let config = create_mock_config();
+        let (left, right) = (ChunkPos::new(0, 0, 0), ChunkPos::new(1, 0, 0));
+        let mut chunks = create_chunks(&config, &[left, right]);
+        set_block(&config, &mut chunks, &(4, 4, 4), LAMP);
+
+        // the light crosses the border no matter which chunk is loaded first
+        for order in [[left, right], [right, left]] {
+            let lights = load(&config, &chunks, &order);
+            let block = |pos: &Position| light_at(&config, &lights, pos).1;
+
+            assert_eq!(block(&(4, 4, 4)), MAX_LIGHT);
+            assert_eq!(block(&(5, 4, 4)), 14);
+            assert_eq!(block(&(4, 1, 2)), 10);
+            assert_eq!(block(&(8, 4, 4)), 11);
+            assert_eq!(block(&(12, 5, 4)), 6);
+            assert_eq!(block(&(4, 4, -1)), 0);
+
+            // the border of each chunk matches its neighbor
+            assert_eq!(lights[&left].get(&(8, 4, 4), LightChannel::Block), 11);
+            assert_eq!(lights[&right].get(&(-1, 4, 4), LightChannel::Block), 12);
+        }
+    }
+
+    #[test]
+    fn load_above_test() {
+        let config = create_mock_config();
+        let (below, above) = (ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0));
+        let mut chunks = create_chunks(&config, &[below, above]);
+        let mut lights = load(&config, &chunks, &[below]);
+
+        // a solid chunk loaded above shades the chunk below
+        let chunk = chunks.get_mut(&above).unwrap();
+        for x in -1..=8 {
+            for y in -1..=8 {
+                for z in -1..=8 {
+                    chunk.set(&(x, y, z), STONE);
+                }
+            }
+        }
+        let changed = light_chunk(&config, &chunks, &mut lights, &above);
+        assert_eq!(changed[0], above);
+        assert!(changed.contains(&below));
+        assert!(insides(&config, &lights).values().all(|light| *light == 0));
+    }
+
+    #[test]
+    fn load_below_test() {
+        let config = create_mock_config();
+        let (below, above) = (ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0));
+        let mut chunks = create_chunks(&config, &[below, above]);
+        let chunk = chunks.get_mut(&above).unwrap();
+        for x in -1..=8 {
+            for y in -1..=8 {
+                for z in -1..=8 {
+                    chunk.set(&(x, y, z), STONE);
+                }
+            }
+        }
+
+        // the chunk below is lit on its own as if the sky was open, and loses
+        // the skylight once it's merged under the solid chunk
+        let light = light_new_chunk(&config, &below, &chunks[&below]);
+        assert_eq!(light.get(&(3, 0, 3), LightChannel::Sky), MAX_LIGHT);
+        assert_eq!(light.get(&(3, 8, 3), LightChannel::Sky), 0);
+
+        let mut lights = load(&config, &chunks, &[above]);
+        let changed = merge_light(&config, &chunks, &mut lights, &below, light);
+        assert_eq!(changed[0], below);
+        assert!(insides(&config, &lights).values().all(|light| *light == 0));
+    }
+
+    #[test]
+    fn load_order_test() {
+        let config = create_mock_config();
+        let mut positions = Vec::new();
+        for x in 0..2 {
+            for y in 0..2 {
+                for z in 0..2 {
+                    positions.push(ChunkPos::new(x, y, z));
+                }
+            }
+        }
+        let mut chunks = create_chunks(&config, &positions);
+
+        // scatter stone and a few lamps with a small random number generator
+        let mut seed: u32 = 12345;
+        let mut random = move || {
+            seed = seed.wrapping_mul(1103515245).wrapping_add(12345);
+            (seed >> 16) % 100
+        };
+        for x in 0..16 {
+            for y in 0..16 {
+                for z in 0..16 {
+                    match random() {
+                        0 => set_block(&config, &mut chunks, &(x, y, z), LAMP),
+                        r if r < 35 => set_block(&config, &mut chunks, &(x, y, z), STONE),
+                        _ => vec![],
+                    };
+                }
+            }
+        }
+
+        let mut expected = LightStorage::new();
+        light_all(&config, &chunks, &mut expected);
+        let expected = insides(&config, &expected);
+
+        let mut bottom_up = positions.clone();
+        bottom_up.sort_by_key(|pos| pos.y);
+        let mut reversed = positions.clone();
+        reversed.reverse();
+        for order in [positions, bottom_up, reversed] {
+            let lights = load(&config, &chunks, &order);
+            assert_eq!(insides(&config, &lights), expected);
+        }
+    }
+
+    #[test]
+    fn update_light_test() {
+        let config = create_mock_config();
+        let positions = [ChunkPos::new(0, 0, 0), ChunkPos::new(0, 1, 0)];
+        let mut chunks = create_chunks(&config, &positions);
+        for x in 0..8 {
+            for z in 0..8 {
+                set_block(&config, &mut chunks, &(x, 9, z), STONE);
+            }
+        }
+        let mut lights = load(&config, &chunks, &positions);
+        assert_eq!(light_at(&config, &lights, &(3, 3, 3)), (0, 0));
+
+        let edits = [
+            // a lamp under the roof, then a hole above it and one next to it
+            ((3, 3, 3), LAMP),
+            ((3, 9, 3), 0),
+            ((4, 9, 3), 0),
+            // a block in the light of both
+            ((3, 5, 3), STONE),
+            // closing the first hole and taking the lamp away
+            ((3, 9, 3), STONE),
+            ((3, 3, 3), 0),
+            ((4, 9, 3), STONE),
+        ];
+        for (position, block_id) in edits {
+            set_block(&config, &mut chunks, &position, block_id);
+            let changed = update_light(&config, &chunks, &mut lights, &position);
+            assert!(!changed.is_empty());
+
+            let mut expected = LightStorage::new();
+            light_all(&config, &chunks, &mut expected);
+            assert_eq!(
+                insides(&config, &lights),
+                insides(&config, &expected),
+                "after setting {:?} to {}",
+                position,
+                block_id
+            );
+        }
+        assert_eq!(light_at(&config, &lights, &(3, 3, 3)), (0, 0));
+    }
+}
@@ -1,13 +1,57 @@
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+use priomutex::Mutex;
+
 use crate::engine::input::Input;
 use crate::engine::matrix::Matrix;
 use crate::world::{Event, GameData};
 use crate::world_renderer::WorldRenderer;
 
-use super::collision::calculate_collider;
+use super::block::RenderLayer;
+use super::block::{load_block_dictionary, ATLAS_FILE, BLOCKS_FILE};
+use super::chunk_renderer::{upload_chunk_mesh, TranslucentQuads};
+use super::collision::{calculate_colliders, ChunkColliders};
 use super::culling::VisibilityGraph;
-use super::generation::load_chunk;
-use super::meshing::mesh_chunk;
-use super::{calc_lod, chunk_id, chunk_position, player_to_position};
+use super::editing::sync_borders;
+use super::generation::{load_chunk, world_passes};
+use super::lighting::{light_new_chunk, merge_light, LightData};
+use super::meshing::{mesh_chunk, ChunkMesh};
+use super::structures::load_structures;
+use super::{calc_lod, chunk_position, player_to_position, ChunkConfig, ChunkData, ChunkPos};
+
+/// A chunk loaded, rebuilt or relit on the thread pool, waiting to be taken
+/// in by `check_done_load_world`.
+pub struct LoadedChunk {
+    /// The blocks of a newly loaded chunk, None when an already loaded chunk
+    /// was rebuilt or relit.
+    pub chunk: Option<ChunkData>,
+    /// Whether the new chunk was generated because it was never saved, and
+    /// has to be saved.
+    pub generated: bool,
+    /// The light of a new or relit chunk, before it's merged with its
+    /// neighbors'.
+    pub light: Option<LightData>,
+    pub visibility_graph: VisibilityGraph,
+    /// None for new and relit chunks, which are meshed once their light is
+    /// merged.
+    pub mesh: Option<ChunkMesh>,
+    pub colliders: ChunkColliders,
+}
+
+/// The chunks done on the thread pool, by position.
+pub type DoneLoading = Arc<Mutex<IndexMap<ChunkPos, LoadedChunk>>>;
+
+/// Get the position of the chunk the player is in.
+fn player_chunk(data: &GameData) -> ChunkPos {
+    let mut position = (0, 0, 0);
+    if let Some(player) = data.physics_engine.get_rigid_body("player".to_string()) {
+        let player_pos = player.translation();
+        position = player_to_position(&(player_pos.x, player_pos.y, player_pos.z));
+    }
+
+    chunk_position(&data.chunk_config, &position)
+}
 
 // TODO (stetch):
 // - Frustrum culling
@@ -21,66 +65,214 @@ pub fn load_world(
 ) {
     let thread_pool = &data.thread_pool;
 
-    let mut position = (0, 0, 0);
-    if let Some(player) = data.physics_engine.get_rigid_body("player".to_string()) {
-        let player_pos = player.translation();
-        position = player_to_position(&(player_pos.x, player_pos.y, player_pos.z));
-    }
-
     // chunk loading dimensions
-    let (i, j, k) = chunk_position(&data.chunk_config, &position);
+    let center = player_chunk(data);
     let radius = data.chunk_config.load_radius as i32;
 
-    let mut chunks_to_remove: Vec<String> =
-        data.loaded_chunks.iter().map(|(k, _)| k.clone()).collect();
+    let mut chunks_to_remove: Vec<ChunkPos> = data.loaded_chunks.keys().copied().collect();
 
     let mut chunks_to_load = Vec::new();
     // calculate chunks to modify
-    for x in (i - radius)..(i + radius + 1) {
-        for y in (j - radius)..(j + radius + 1) {
-            for z in (k - radius)..(k + radius + 1) {
-                let chunk_id = chunk_id(&(x, y, z));
+    for x in -radius..(radius + 1) {
+        for y in -radius..(radius + 1) {
+            for z in -radius..(radius + 1) {
+                let chunk_pos = center.offset(x, y, z);
 
-                let index = chunks_to_remove.iter().position(|r| r == &chunk_id);
+                let index = chunks_to_remove.iter().position(|r| r == &chunk_pos);
                 if let Some(x) = index {
                     chunks_to_remove.swap_remove(x);
                 }
 
                 // if loaded chunks doesn't contain it, but it should
-                if !data.loaded_chunks.contains_key(&chunk_id) && !data.loading.contains(&chunk_id)
+                if !data.loaded_chunks.contains_key(&chunk_pos)
+                    && !data.loading.contains(&chunk_pos)
                 {
-                    chunks_to_load.push((chunk_id, (x, y, z)));
+                    chunks_to_load.push(chunk_pos);
                 }
             }
         }
     }
 
-    for (chunk_id, chunk_pos) in chunks_to_load.into_iter() {
-        data.loading.insert(chunk_id.clone());
+    for chunk_pos in chunks_to_load.into_iter() {
+        data.loading.insert(chunk_pos);
 
         let config = data.chunk_config.clone();
+        let storage = data.region_storage.clone();
         let done_loading = data.done_loading.clone();
         thread_pool.spawn(move || {
-            let chunk = load_chunk(&config, &chunk_pos);
-            let mesh = mesh_chunk(&chunk, &config, calc_lod());
-            let collider = calculate_collider(&chunk, &chunk_pos, &config);
+            let (chunk, generated) = load_chunk(&config, &storage, &chunk_pos);
+            let colliders = calculate_colliders(&chunk, &chunk_pos, &config);
             let visibility_graph = VisibilityGraph::from_chunk(&config, &chunk);
+            let light = light_new_chunk(&config, &chunk_pos, &chunk);
 
             // collider.set_translation(translation);
 
             let mut done_loading = done_loading.lock(5).unwrap();
             done_loading.insert(
-                chunk_id,
-                (chunk_pos, chunk, visibility_graph, mesh, collider),
+                chunk_pos,
+                LoadedChunk {
+                    chunk: Some(chunk),
+                    generated,
+                    light: Some(light),
+                    visibility_graph,
+                    mesh: None,
+                    colliders,
+                },
             );
         })
     }
 
     // remove unneeded chunks
     for c in chunks_to_remove {
-        data.loaded_chunks.remove(&c);
-        data.physics_engine.remove_collider(&c);
+        if let Some(chunk) = data.loaded_chunks.remove(&c) {
+            save_chunk(data, c, chunk);
+        }
+        data.chunk_lods.remove(&c);
+        data.light.remove(&c);
+        data.unlit_chunks.shift_remove(&c);
+        data.physics_engine.remove_chunk_collider(&c);
         renderer.chunk_render_pass.render_objects.remove(&c);
+        renderer.chunk_render_pass.translucent_quads.remove(&c);
+    }
+
+    // re-mesh the chunks that moved to another level of detail
+    for (chunk_pos, lod) in data.chunk_lods.iter() {
+        if *lod != calc_lod(&data.chunk_config, chunk_pos, &center) {
+            data.dirty_chunks.insert(*chunk_pos);
+        }
+    }
+}
+
+/// Write the unloaded chunk to storage on the thread pool, if it has changes
+/// that haven't been saved. It's loaded from the storage's queue until then.
+fn save_chunk(data: &mut GameData, chunk_pos: ChunkPos, mut chunk: ChunkData) {
+    if !data.unsaved_chunks.remove(&chunk_pos) {
+        return;
+    }
+    chunk.compact_palette();
+
+    data.region_storage.queue_save(chunk_pos, chunk);
+    let config = data.chunk_config.clone();
+    let storage = data.region_storage.clone();
+    data.thread_pool.spawn(move || {
+        if let Err(e) = storage.flush_chunk(&config, &chunk_pos) {
+            log::error!("Failed to save chunk {}: {:#}", chunk_pos, e);
+        }
+    });
+}
+
+/// Write all loaded chunks with unsaved changes, and the unloaded chunks that
+/// are still waiting to be written, to storage. Used before the game exits.
+pub fn save_loaded_chunks(data: &mut GameData) {
+    let storage = &data.region_storage;
+    for chunk_pos in data.unsaved_chunks.drain() {
+        if let Some(chunk) = data.loaded_chunks.get_mut(&chunk_pos) {
+            chunk.compact_palette();
+            if let Err(e) = storage.save(&data.chunk_config, &chunk_pos, chunk) {
+                log::error!("Failed to save chunk {}: {:#}", chunk_pos, e);
+            }
+        }
+    }
+    if let Err(e) = storage.flush(&data.chunk_config) {
+        log::error!("Failed to save the unloaded chunks: {:#}", e);
+    }
+}
+
+/// Rebuild the mesh, collider and visibility graph of dirty chunks on the
+/// thread pool, the results are swapped in by `check_done_load_world`.
+/// The mesh is built at the level of detail for the chunk's current distance
+/// to the player.
+/// Only one job runs for a chunk at a time, so a chunk that is still being
+/// built stays dirty until its job is done.
+/// Unlit chunks are lit first, and stay dirty until their light is merged.
+pub fn rebuild_dirty_chunks(
+    _renderer: &mut WorldRenderer,
+    _input: &mut Input,
+    data: &mut GameData,
+    _queue: &mut Vec<Event>,
+    _delta: f64,
+) {
+    relight_chunks(data);
+
+    let center = player_chunk(data);
+    let dirty_chunks: Vec<ChunkPos> = data.dirty_chunks.drain(..).collect();
+    for chunk_pos in dirty_chunks {
+        if data.loading.contains(&chunk_pos) || data.unlit_chunks.contains(&chunk_pos) {
+            data.dirty_chunks.insert(chunk_pos);
+            continue;
+        }
+
+        let chunk = match data.loaded_chunks.get(&chunk_pos) {
+            Some(chunk) => chunk.clone(),
+            None => continue,
+        };
+        let light = match data.light.get(&chunk_pos) {
+            Some(light) => light.clone(),
+            None => LightData::new(data.chunk_config.depth),
+        };
+        data.loading.insert(chunk_pos);
+        let lod = calc_lod(&data.chunk_config, &chunk_pos, &center);
+        data.chunk_lods.insert(chunk_pos, lod);
+
+        let config = data.chunk_config.clone();
+        let done_loading = data.done_loading.clone();
+        data.thread_pool.spawn(move || {
+            let mesh = mesh_chunk(&chunk, &light, &config, lod);
+            let colliders = calculate_colliders(&chunk, &chunk_pos, &config);
+            let visibility_graph = VisibilityGraph::from_chunk(&config, &chunk);
+
+            let mut done_loading = done_loading.lock(5).unwrap();
+            done_loading.insert(
+                chunk_pos,
+                LoadedChunk {
+                    chunk: None,
+                    generated: false,
+                    light: None,
+                    visibility_graph,
+                    mesh: Some(mesh),
+                    colliders,
+                },
+            );
+        })
+    }
+}
+
+/// Light the unlit chunks again on the thread pool, their light is merged by
+/// `check_done_load_world`.
+fn relight_chunks(data: &mut GameData) {
+    let unlit_chunks: Vec<ChunkPos> = data.unlit_chunks.drain(..).collect();
+    for chunk_pos in unlit_chunks {
+        if data.loading.contains(&chunk_pos) {
+            data.unlit_chunks.insert(chunk_pos);
+            continue;
+        }
+
+        let chunk = match data.loaded_chunks.get(&chunk_pos) {
+            Some(chunk) => chunk.clone(),
+            None => continue,
+        };
+        data.loading.insert(chunk_pos);
+
+        let config = data.chunk_config.clone();
+        let done_loading = data.done_loading.clone();
+        data.thread_pool.spawn(move || {
+            let colliders = calculate_colliders(&chunk, &chunk_pos, &config);
+            let visibility_graph = VisibilityGraph::from_chunk(&config, &chunk);
+            let light = light_new_chunk(&config, &chunk_pos, &chunk);
+
+            let mut done_loading = done_loading.lock(5).unwrap();
+            done_loading.insert(
+                chunk_pos,
+                LoadedChunk {
+                    chunk: None,
+                    generated: false,
+                    light: Some(light),
+                    visibility_graph,
+                    mesh: None,
+                    colliders,
+                },
+            );
+        })
     }
 }
 
@@ -98,32 +290,145 @@ pub fn check_done_load_world(
     let mut done_loading = data.done_loading.lock(0).unwrap();
 
     for _ in 0..5 {
-        // (chunk_pos, chunk, visibility_graph, mut mesh, collider)
-        let chunk = done_loading.pop();
-
-        if chunk.is_none() {
+        let Some((chunk_pos, loaded)) = done_loading.pop() else {
             break;
-        }
+        };
+        let LoadedChunk {
+            chunk,
+            generated,
+            light,
+            visibility_graph,
+            mesh,
+            colliders,
+        } = loaded;
+
+        data.loading.remove(&chunk_pos);
+        match chunk {
+            Some(chunk) => {
+                if generated {
+                    data.unsaved_chunks.insert(chunk_pos);
+                }
+                data.loaded_chunks.insert(chunk_pos, chunk);
 
-        let (chunk_id, (chunk_pos, chunk, visibility_graph, mut mesh, collider)) = chunk.unwrap();
+                // the border of the new chunk and its neighbors might not
+                // match if either was edited
+                for changed in sync_borders(&data.chunk_config, &mut data.loaded_chunks, &chunk_pos)
+                {
+                    data.dirty_chunks.insert(changed);
+                }
 
-        data.loading.remove(&chunk_id);
-        data.loaded_chunks.insert(chunk_id.clone(), chunk);
-        data.visibility_graphs
-            .insert(chunk_id.clone(), visibility_graph);
+                data.fluids.schedule_chunk(
+                    &data.chunk_config,
+                    &data.loaded_chunks[&chunk_pos],
+                    &chunk_pos,
+                );
+            }
+            // the chunk was unloaded while it was rebuilt
+            None if !data.loaded_chunks.contains_key(&chunk_pos) => continue,
+            None => {}
+        }
+
+        // merge the chunk's light with its neighbors', then mesh it and the
+        // neighbors its light reached. The light of a chunk that was edited
+        // while it was lit is out of date, it's lit again instead
+        if let Some(light) = light {
+            if !data.unlit_chunks.contains(&chunk_pos) {
+                data.dirty_chunks.extend(merge_light(
+                    &data.chunk_config,
+                    &data.loaded_chunks,
+                    &mut data.light,
+                    &chunk_pos,
+                    light,
+                ));
+            }
+        }
+        data.visibility_graphs.insert(chunk_pos, visibility_graph);
+        data.physics_engine
+            .insert_chunk_collider(chunk_pos, colliders.solid);
         data.physics_engine
-            .insert_collider(chunk_id.clone(), collider);
-        let (x, y, z) = chunk_pos;
-        let mat = Matrix::new(glam::Mat4::from_translation(glam::f32::vec3(
-            x as f32 * data.chunk_config.depth as f32,
-            y as f32 * data.chunk_config.depth as f32,
-            z as f32 * data.chunk_config.depth as f32,
-        )))
-        .uniform(&Matrix::create_layout(2));
-        mesh.uniforms.insert("model".to_string(), mat);
-        renderer
-            .chunk_render_pass
-            .render_objects
-            .insert(chunk_id.clone(), mesh);
+            .insert_chunk_fluid_collider(chunk_pos, colliders.fluid);
+        if let Some(mesh) = mesh {
+            insert_chunk_mesh(renderer, &data.chunk_config, chunk_pos, &mesh);
+        }
+    }
+}
+
+/// Upload the chunk's mesh, give the render objects of its render layers their
+/// model matrix and hand them to the renderer, replacing the chunk's old ones.
+pub fn insert_chunk_mesh(
+    renderer: &mut WorldRenderer,
+    config: &ChunkConfig,
+    chunk_pos: ChunkPos,
+    mesh: &ChunkMesh,
+) {
+    let device = &renderer.gfx.device;
+    let mut objects = upload_chunk_mesh(device, mesh);
+    let ChunkPos { x, y, z } = chunk_pos;
+    let translation = glam::Mat4::from_translation(glam::f32::vec3(
+        x as f32 * config.depth as f32,
+        y as f32 * config.depth as f32,
+        z as f32 * config.depth as f32,
+    ));
+    for object in objects.iter_mut().flatten() {
+        let mat =
+            Matrix::new(device, translation).uniform(device, &Matrix::create_layout(device, 2));
+        object.uniforms.insert("model".to_string(), mat);
+    }
+    let render_pass = &mut renderer.chunk_render_pass;
+    render_pass.render_objects.insert(chunk_pos, objects);
+    let (vertices, indices) = mesh.layer(RenderLayer::Translucent);
+    if indices.is_empty() {
+        render_pass.translucent_quads.remove(&chunk_pos);
+    } else {
+        let quads = TranslucentQuads::new(vertices, indices);
+        render_pass.translucent_quads.insert(chunk_pos, quads);
+    }
+}
+
+/// System for reloading the blocks and atlas files when F5 is pressed, every loaded
+/// chunk is lit again on the thread pool and rebuilt with the new blocks.
+/// The generation passes are rebuilt with the new blocks too, along with the
+/// structures made of them.
+/// The old blocks are kept if the files can't be loaded.
+pub fn reload_blocks(
+    _renderer: &mut WorldRenderer,
+    input: &mut Input,
+    data: &mut GameData,
+    _queue: &mut Vec<Event>,
+    delta: f64,
+) {
+    let f5 = input.get_key(winit::event::VirtualKeyCode::F5);
+    if f5 > delta || f5 <= 0.0 {
+        return;
+    }
+
+    let mut config = (*data.chunk_config).clone();
+    let reloaded = pollster::block_on(async {
+        let dict = load_block_dictionary(config.uv_size).await?;
+        let structures = load_structures(&dict).await?;
+        let passes = world_passes(&config.settings, &dict, structures)?;
+        anyhow::Ok((dict, passes))
+    });
+    match reloaded {
+        Ok((dict, passes)) => {
+            config.dict = dict;
+            config.passes = passes;
+            data.chunk_config = Arc::new(config);
+
+            // top down, so the sky is lit before the chunks below it. Chunks
+            // still loading were lit with the old blocks, and are lit again too
+            data.light.clear();
+            let mut chunk_positions: Vec<ChunkPos> = data.loaded_chunks.keys().copied().collect();
+            chunk_positions.extend(
+                data.loading
+                    .iter()
+                    .filter(|pos| !data.loaded_chunks.contains_key(pos)),
+            );
+            chunk_positions.sort_by_key(|pos| (-pos.y, pos.x, pos.z));
+            data.unlit_chunks.extend(chunk_positions);
+            data.dirty_chunks.extend(data.loaded_chunks.keys().copied());
+            log::info!("Reloaded blocks from {} and {}", BLOCKS_FILE, ATLAS_FILE);
+        }
+        Err(e) => log::error!("{:#}", e),
     }
 }
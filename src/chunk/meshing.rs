@@ -1,13 +1,37 @@
+use std::ops::Range;
+
 use wgpu::VertexBufferLayout;
 
-use super::super::engine::render::render_object::RenderObject;
+use super::block::{BlockModel, RenderLayer};
+use super::cube_model::{cube_model, face_shade, is_face_visible, push_face, Face, FACES};
+use super::lighting::{LightChannel, LightData};
 use super::{ChunkConfig, ChunkData, Position, LOD};
 
-/// 7 bits for each position, 4 bits for each texture, and 3 bits for lighting
-#[derive(Copy, Clone, bytemuck::Zeroable, bytemuck::Pod)]
+/// How the faces of the blocks in a chunk are turned into a mesh.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum MeshingMode {
+    /// Every visible face gets its own quad.
+    #[default]
+    PerFace,
+    /// Coplanar cube faces with the same texture, ambient occlusion and light
+    /// are merged into bigger quads, and the texture is tiled across them.
+    Greedy,
+}
+
+/// Deepest chunks the vertex format can hold, tiled quads store their size in
+/// 6 bits.
+pub const MAX_MESH_DEPTH: i32 = 63;
+
+/// 7 bits for each position, 4 bits for each texture, and 3 bits for ambient occlusion
+/// in sixths.
+/// The second word has 4 bits for skylight and 4 bits for block light, the rest is
+/// only used by tiled quads, 1 bit to mark the vertex as tiled and 6 bits for
+/// each texture coordinate in blocks.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, bytemuck::Zeroable, bytemuck::Pod)]
 #[repr(C)]
 pub struct Vertex {
     pub data: u32,
+    pub tile: u32,
 }
 
 impl Vertex {
@@ -21,9 +45,48 @@ impl Vertex {
         vertex |= ((uv[0] * 16.0).floor() as u32).rotate_left(7) & 0x0000_0780;
         vertex |= ((uv[1] * 16.0).floor() as u32).rotate_left(3) & 0x0000_0078;
 
-        vertex |= (ao * 2.0) as u32 & 0x0000_0007;
+        vertex |= (ao * 6.0).round() as u32 & 0x0000_0007;
+
+        Vertex {
+            data: vertex,
+            tile: 0,
+        }
+    }
+
+    /// The position of the vertex, in blocks from the chunk's corner.
+    pub fn position(&self) -> [u32; 3] {
+        [
+            (self.data >> 25) & 0x7F,
+            (self.data >> 18) & 0x7F,
+            (self.data >> 11) & 0x7F,
+        ]
+    }
+
+    /// The vertex with its position multiplied by `scale`.
+    pub fn scaled(mut self, scale: u32) -> Self {
+        let [x, y, z] = self.position();
+
+        self.data &= 0x0000_07FF;
+        self.data |= (x * scale).rotate_left(25) & 0xFE00_0000;
+        self.data |= (y * scale).rotate_left(18) & 0x01FC_0000;
+        self.data |= (z * scale).rotate_left(11) & 0x0003_F800;
+        self
+    }
 
-        Vertex { data: vertex }
+    /// The vertex lit by `light`, both light channels packed into a byte with
+    /// skylight in the upper 4 bits.
+    pub fn lit(mut self, light: u8) -> Self {
+        self.tile &= !0x000F_F000;
+        self.tile |= (light as u32) << 12;
+        self
+    }
+
+    /// Packs a vertex of a quad that tiles the texture at `uv` across it,
+    /// `tile` is the texture coordinate in blocks.
+    pub fn tiled(position: [f32; 3], uv: [f32; 2], tile: [u32; 2], ao: f32) -> Self {
+        let mut vertex = Self::from(position, uv, ao);
+        vertex.tile |= 0x8000_0000 | (tile[0] & 0x3F).rotate_left(6) | (tile[1] & 0x3F);
+        vertex
     }
 
     pub fn description<'a>() -> VertexBufferLayout<'a> {
@@ -38,6 +101,12 @@ impl Vertex {
                     shader_location: 0,
                     format: wgpu::VertexFormat::Uint32,
                 },
+                // tiling
+                wgpu::VertexAttribute {
+                    offset: mem::size_of::<u32>() as wgpu::BufferAddress,
+                    shader_location: 1,
+                    format: wgpu::VertexFormat::Uint32,
+                },
                 /*
                 // uv coords
                 wgpu::VertexAttribute {
@@ -70,27 +139,943 @@ pub fn should_mesh_block(config: &ChunkConfig, pos: &Position) -> bool {
     true
 }
 
-// see what's taking so long in this function/and why
-pub fn mesh_chunk(chunk: &ChunkData, config: &ChunkConfig, _lod: LOD) -> RenderObject {
-    let mut vertices = Vec::<Vertex>::new();
-    let mut indices = Vec::<u16>::new();
+/// The vertices and indices of a mesh.
+pub type Mesh = (Vec<Vertex>, Vec<u32>);
+
+/// The meshes of the blocks in each render layer of a chunk, indexed by `RenderLayer`.
+pub type LayerMeshes = [Mesh; 3];
+
+/// Where the vertices and indices of a render layer are in a `ChunkMesh`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct LayerRange {
+    pub vertices: Range<usize>,
+    pub indices: Range<usize>,
+}
+
+/// The mesh of a chunk, built on the CPU so it can be built on any thread and
+/// uploaded to the GPU later on the main thread.
+/// The render layers are stored one after another, and the indices of a layer
+/// count from the first vertex of the layer.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ChunkMesh {
+    pub vertices: Vec<Vertex>,
+    pub indices: Vec<u32>,
+    /// Where each render layer is, indexed by `RenderLayer`.
+    pub layers: [LayerRange; 3],
+}
+
+impl ChunkMesh {
+    /// Put the meshes of the render layers together into one mesh.
+    pub fn from_layers(meshes: LayerMeshes) -> Self {
+        let mut mesh = ChunkMesh::default();
+        for (range, (vertices, indices)) in mesh.layers.iter_mut().zip(meshes) {
+            range.vertices = mesh.vertices.len()..(mesh.vertices.len() + vertices.len());
+            range.indices = mesh.indices.len()..(mesh.indices.len() + indices.len());
+            mesh.vertices.extend(vertices);
+            mesh.indices.extend(indices);
+        }
+        mesh
+    }
+
+    /// Get the vertices and indices of a render layer.
+    pub fn layer(&self, layer: RenderLayer) -> (&[Vertex], &[u32]) {
+        let range = &self.layers[layer as usize];
+        (
+            &self.vertices[range.vertices.clone()],
+            &self.indices[range.indices.clone()],
+        )
+    }
+
+    /// Get the number of quads in a render layer.
+    pub fn face_count(&self, layer: RenderLayer) -> usize {
+        self.layers[layer as usize].indices.len() / 6
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.indices.is_empty()
+    }
+}
+
+/// Build the mesh of a chunk, it doesn't touch the GPU so it can run on the
+/// thread pool.
+pub fn mesh_chunk(
+    chunk: &ChunkData,
+    light: &LightData,
+    config: &ChunkConfig,
+    lod: LOD,
+) -> ChunkMesh {
+    ChunkMesh::from_layers(build_chunk_mesh(chunk, light, config, lod))
+}
+
+/// Build the vertices and indices of the chunk's mesh in each render layer, lit
+/// by the chunk's light.
+/// Below the max level of detail the chunk is downsampled first, and the mesh of
+/// the smaller chunk is scaled back up to the size of the chunk.
+pub fn build_chunk_mesh(
+    chunk: &ChunkData,
+    light: &LightData,
+    config: &ChunkConfig,
+    lod: LOD,
+) -> LayerMeshes {
+    let build = |chunk: &ChunkData, light: &LightData| match config.meshing {
+        MeshingMode::PerFace => build_per_face_mesh(chunk, light, config),
+        MeshingMode::Greedy => build_greedy_mesh(chunk, light, config),
+    };
+
+    if lod == LOD::MAX {
+        return build(chunk, light);
+    }
+
+    let meshes = build(&downsample(chunk, lod), &downsample_light(light, lod));
+    meshes.map(|(vertices, indices)| {
+        let vertices = vertices
+            .into_iter()
+            .map(|vertex| vertex.scaled(lod.step()))
+            .collect();
+        (vertices, indices)
+    })
+}
+
+/// Shrink the inside of a chunk into a chunk with a block for every cell of
+/// the level of detail.
+/// A cell is solid if at least half of its blocks are, and then gets the block
+/// at the top of the cell so the surface of the terrain keeps its look.
+/// The border of the smaller chunk is left empty, so the faces on the edges of
+/// the chunk are always meshed. These work as skirts that cover up the gaps
+/// between chunks at different levels of detail.
+pub fn downsample(chunk: &ChunkData, lod: LOD) -> ChunkData {
+    let step = lod.step() as i32;
+    let depth = chunk.depth() / step;
+    let mut output = ChunkData::new(depth);
+    if chunk.is_empty() {
+        return output;
+    }
+
+    for x in 0..depth {
+        for y in 0..depth {
+            for z in 0..depth {
+                let mut solid = 0;
+                let mut top = (i32::MIN, 0);
+                for dx in 0..step {
+                    for dy in 0..step {
+                        for dz in 0..step {
+                            let position = (x * step + dx, y * step + dy, z * step + dz);
+                            let block_id = chunk.get(&position);
+                            if block_id != 0 {
+                                solid += 1;
+                                if position.1 > top.0 {
+                                    top = (position.1, block_id);
+                                }
+                            }
+                        }
+                    }
+                }
+
+                if solid * 2 >= step * step * step {
+                    output.set(&(x, y, z), top.1);
+                }
+            }
+        }
+    }
+
+    output
+}
+
+/// Shrink the light of a chunk like `downsample`, a cell gets the brightest
+/// light of its blocks. The border keeps the light of the chunk's border.
+pub fn downsample_light(light: &LightData, lod: LOD) -> LightData {
+    let step = lod.step() as i32;
+    let depth = light.depth() / step;
+    let mut output = LightData::new(depth);
+
+    // the positions of the blocks in the chunk a cell covers on one axis
+    let blocks = |cell: i32| match cell {
+        -1 => -1..=-1,
+        c if c == depth => light.depth()..=light.depth(),
+        c => (c * step)..=(c * step + step - 1),
+    };
+
+    for x in -1..=depth {
+        for y in -1..=depth {
+            for z in -1..=depth {
+                let mut brightest = [0, 0];
+                for bx in blocks(x) {
+                    for by in blocks(y) {
+                        for bz in blocks(z) {
+                            let position = (bx, by, bz);
+                            for (level, channel) in brightest
+                                .iter_mut()
+                                .zip([LightChannel::Sky, LightChannel::Block])
+                            {
+                                *level = light.get(&position, channel).max(*level);
+                            }
+                        }
+                    }
+                }
+                output.set(&(x, y, z), LightChannel::Sky, brightest[0]);
+                output.set(&(x, y, z), LightChannel::Block, brightest[1]);
+            }
+        }
+    }
+
+    output
+}
+
+fn build_per_face_mesh(chunk: &ChunkData, light: &LightData, config: &ChunkConfig) -> LayerMeshes {
+    let mut meshes = LayerMeshes::default();
+
+    // loop over the inside of the chunk,
+    // the border we generated isn't meshed
+    let depth = chunk.depth();
+    for x in 0..depth {
+        for y in 0..depth {
+            for z in 0..depth {
+                let position = (x, y, z);
+                let block_id = chunk.get(&position);
+                if block_id == 0 {
+                    continue;
+                }
+
+                let block = config.dict.get(&block_id);
+                let block = block.unwrap_or(config.dict.get(&0).unwrap());
+                let (vertices, indices) = &mut meshes[block.layer as usize];
+                (block.model)(chunk, light, config, &position, vertices, indices);
+            }
+        }
+    }
+
+    meshes
+}
+
+fn is_cube(model: BlockModel) -> bool {
+    std::ptr::fn_addr_eq(model, cube_model as BlockModel)
+}
+
+/// The uv, ambient occlusion, light and render layer of a face in the greedy
+/// mesher's mask, faces are merged when all of them match.
+type MaskFace = ([f32; 2], f32, u8, RenderLayer);
+
+fn build_greedy_mesh(chunk: &ChunkData, light: &LightData, config: &ChunkConfig) -> LayerMeshes {
+    let mut meshes = LayerMeshes::default();
+    let depth = chunk.depth();
+
+    // blocks that aren't cubes can't be merged
+    for x in 0..depth {
+        for y in 0..depth {
+            for z in 0..depth {
+                let position = (x, y, z);
+                let block_id = chunk.get(&position);
+                if block_id == 0 {
+                    continue;
+                }
+
+                let block = config.dict.get(&block_id);
+                let block = block.unwrap_or(config.dict.get(&0).unwrap());
+                if !is_cube(block.model) {
+                    let (vertices, indices) = &mut meshes[block.layer as usize];
+                    (block.model)(chunk, light, config, &position, vertices, indices);
+                }
+            }
+        }
+    }
+
+    for face in FACES.iter() {
+        let (normal_axis, u_axis, v_axis) = face_axes(face);
+
+        for slice in 0..depth {
+            // the visible faces in the slice
+            let mut mask: Vec<Option<MaskFace>> = vec![None; (depth * depth) as usize];
+
+            for b in 0..depth {
+                for a in 0..depth {
+                    let mut position = [0; 3];
+                    position[normal_axis] = slice;
+                    position[u_axis] = a;
+                    position[v_axis] = b;
+                    let position = (position[0], position[1], position[2]);
+
+                    let block = match config.dict.get(&chunk.get(&position)) {
+                        Some(block) if is_cube(block.model) => block,
+                        _ => continue,
+                    };
+                    if !is_face_visible(chunk, config, &position, face) {
+                        continue;
+                    }
+                    let (vertices, indices) = &mut meshes[block.layer as usize];
+
+                    // faces with ambient occlusion or light that changes across them
+                    // would be shaded differently when merged, so they are added as is
+                    let shade = face_shade(chunk, light, config, &position, face);
+                    if !shade.is_uniform() {
+                        push_face(
+                            config,
+                            &position,
+                            face,
+                            &block.uv(face.side),
+                            &shade,
+                            vertices,
+                            indices,
+                        );
+                        continue;
+                    }
+
+                    mask[(a + b * depth) as usize] = Some((
+                        block.uv(face.side),
+                        shade.ao[0],
+                        shade.light[0],
+                        block.layer,
+                    ));
+                }
+            }
+
+            // merge the faces into rectangles, growing along u and then along v
+            for b in 0..depth {
+                let mut a = 0;
+                while a < depth {
+                    let key = match mask[(a + b * depth) as usize] {
+                        Some(key) => key,
+                        None => {
+                            a += 1;
+                            continue;
+                        }
+                    };
+
+                    let mut width = 1;
+                    while a + width < depth && mask[(a + width + b * depth) as usize] == Some(key) {
+                        width += 1;
+                    }
+
+                    let mut height = 1;
+                    while b + height < depth
+                        && (a..(a + width))
+                            .all(|i| mask[(i + (b + height) * depth) as usize] == Some(key))
+                    {
+                        height += 1;
+                    }
+
+                    for j in b..(b + height) {
+                        for i in a..(a + width) {
+                            mask[(i + j * depth) as usize] = None;
+                        }
+                    }
+
+                    let mut origin = [0; 3];
+                    origin[normal_axis] = slice;
+                    origin[u_axis] = a;
+                    origin[v_axis] = b;
+                    let (uv, ao, light, layer) = key;
+                    let (vertices, indices) = &mut meshes[layer as usize];
+                    push_merged_face(
+                        face,
+                        &origin,
+                        (u_axis, width),
+                        (v_axis, height),
+                        (uv, ao, light),
+                        vertices,
+                        indices,
+                    );
+
+                    a += width;
+                }
+            }
+        }
+    }
+
+    meshes
+}
+
+/// Get the axis a face points along, and the axes its texture's u and v run along.
+fn face_axes(face: &Face) -> (usize, usize, usize) {
+    let axis = |a: &Position, b: &Position| {
+        if a.0 != b.0 {
+            0
+        } else if a.1 != b.1 {
+            1
+        } else {
+            2
+        }
+    };
+    // the corner across the texture from the first corner on one axis
+    let across = |axis: usize| {
+        (1..4)
+            .find(|c| {
+                face.uvs[*c][axis] != face.uvs[0][axis]
+                    && face.uvs[*c][1 - axis] == face.uvs[0][1 - axis]
+            })
+            .unwrap()
+    };
+    let u_axis = axis(&face.corners[0], &face.corners[across(0)]);
+    let v_axis = axis(&face.corners[0], &face.corners[across(1)]);
+    (3 - u_axis - v_axis, u_axis, v_axis)
+}
+
+/// Add a face stretched over `width` blocks along its u axis and `height` blocks
+/// along its v axis, with the texture tiled once per block.
+fn push_merged_face(
+    face: &Face,
+    origin: &[i32; 3],
+    (u_axis, width): (usize, i32),
+    (v_axis, height): (usize, i32),
+    (uv, ao, light): ([f32; 2], f32, u8),
+    vertices: &mut Vec<Vertex>,
+    indices: &mut Vec<u32>,
+) {
+    let last_index = vertices.len() as u32;
+    indices.extend(face.indices.iter().map(|i| last_index + i));
+    for (corner, face_uv) in face.corners.iter().zip(face.uvs.iter()) {
+        let mut position = [
+            (origin[0] + corner.0) as f32,
+            (origin[1] + corner.1) as f32,
+            (origin[2] + corner.2) as f32,
+        ];
+        let corner = [corner.0, corner.1, corner.2];
+        position[u_axis] = (origin[u_axis] + corner[u_axis] * width) as f32;
+        position[v_axis] = (origin[v_axis] + corner[v_axis] * height) as f32;
+
+        let tile = [
+            (face_uv[0] as i32 * width) as u32,
+            (face_uv[1] as i32 * height) as u32,
+        ];
+        vertices.push(Vertex::tiled(position, uv, tile, ao).lit(light));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use super::*;
+    use crate::chunk::{
+        calc_lod,
+        culling::Side,
+        generation::{ao_test, generate},
+        lighting::light_chunk,
+        ChunkPos,
+    };
+
+    fn unpack_position(vertex: &Vertex) -> [u32; 3] {
+        [
+            (vertex.data >> 25) & 0x7F,
+            (vertex.data >> 18) & 0x7F,
+            (vertex.data >> 11) & 0x7F,
+        ]
+    }
+
+    /// Light a chunk as if it was the only one loaded.
+    fn chunk_light(chunk: &ChunkData) -> LightData {
+        let config = create_mock_config(MeshingMode::default());
+        let chunk_pos = ChunkPos::new(0, 0, 0);
+        let chunks = HashMap::from([(chunk_pos, chunk.clone())]);
+        let mut lights = HashMap::new();
+        light_chunk(&config, &chunks, &mut lights, &chunk_pos);
+        lights.remove(&chunk_pos).unwrap()
+    }
+
+    fn create_mock_config(meshing: MeshingMode) -> ChunkConfig {
+        let mut config = ChunkConfig::new(10, 16, 3);
+        config.meshing = meshing;
+        config
+    }
+
+    fn test_chunks(config: &ChunkConfig) -> Vec<ChunkData> {
+        vec![
+            ao_test(config.depth),
+            generate(config, &ChunkPos::new(0, 0, 0)),
+            generate(config, &ChunkPos::new(0, -1, 0)),
+            generate(config, &ChunkPos::new(2, -1, 1)),
+        ]
+    }
+
+    /// A unit square of a face, by the axis it faces along, which way it faces,
+    /// and its position.
+    type FaceCell = (usize, bool, [u32; 3]);
+
+    /// Rasterize the quads of a mesh into the unit squares they cover, along
+    /// with the texture, ambient occlusion and light of each square.
+    fn rasterize(vertices: &[Vertex], indices: &[u32]) -> HashMap<FaceCell, ([u32; 2], Vec<u32>)> {
+        let unpack = |vertex: &Vertex| {
+            let data = vertex.data;
+            (
+                [
+                    (data >> 25) & 0x7F,
+                    (data >> 18) & 0x7F,
+                    (data >> 11) & 0x7F,
+                ],
+                [(data >> 7) & 0xF, (data >> 3) & 0xF],
+                // ambient occlusion and light
+                (data & 0x7) | (vertex.tile & 0x000F_F000),
+            )
+        };
+
+        let mut output = HashMap::new();
+        for quad in indices.chunks(6) {
+            let mut corners: Vec<u32> = quad.to_vec();
+            corners.sort();
+            corners.dedup();
+            assert_eq!(corners.len(), 4);
+            let corners: Vec<_> = corners
+                .iter()
+                .map(|i| unpack(&vertices[*i as usize]))
+                .collect();
+
+            let min = |axis: usize| corners.iter().map(|c| c.0[axis]).min().unwrap();
+            let max = |axis: usize| corners.iter().map(|c| c.0[axis]).max().unwrap();
+            let axis = (0..3).find(|axis| min(*axis) == max(*axis)).unwrap();
+
+            // which way the first triangle faces
+            let p = |i: u32| {
+                let (position, _, _) = unpack(&vertices[i as usize]);
+                glam::vec3(position[0] as f32, position[1] as f32, position[2] as f32)
+            };
+            let normal = (p(quad[1]) - p(quad[0])).cross(p(quad[2]) - p(quad[0]));
+            let facing = normal[axis] > 0.0;
+
+            let uv = corners.iter().map(|c| c.1).min().unwrap();
+            let mut ao: Vec<u32> = corners.iter().map(|c| c.2).collect();
+            ao.sort();
+
+            let mut cells = 0;
+            for x in min(0)..max(0).max(min(0) + 1) {
+                for y in min(1)..max(1).max(min(1) + 1) {
+                    for z in min(2)..max(2).max(min(2) + 1) {
+                        let previous = output.insert((axis, facing, [x, y, z]), (uv, ao.clone()));
+                        assert!(previous.is_none(), "faces overlap");
+                        cells += 1;
+                    }
+                }
+            }
+
+            // tiled quads repeat the texture once per block
+            let tiled = vertices[quad[0] as usize].tile & 0x8000_0000;
+            if tiled != 0 {
+                let tiles: Vec<[u32; 2]> = quad
+                    .iter()
+                    .map(|i| {
+                        let tile = vertices[*i as usize].tile;
+                        [(tile >> 6) & 0x3F, tile & 0x3F]
+                    })
+                    .collect();
+                let width = tiles.iter().map(|t| t[0]).max().unwrap();
+                let height = tiles.iter().map(|t| t[1]).max().unwrap();
+                assert_eq!(width * height, cells);
+            }
+        }
+
+        output
+    }
+
+    #[test]
+    fn greedy_mesh_coverage_test() {
+        let per_face_config = create_mock_config(MeshingMode::PerFace);
+        let greedy_config = create_mock_config(MeshingMode::Greedy);
+
+        for chunk in test_chunks(&per_face_config) {
+            let [(per_face_vertices, per_face_indices), ..] =
+                build_chunk_mesh(&chunk, &chunk_light(&chunk), &per_face_config, LOD::MAX);
+            let [(greedy_vertices, greedy_indices), ..] =
+                build_chunk_mesh(&chunk, &chunk_light(&chunk), &greedy_config, LOD::MAX);
+
+            assert_eq!(
+                rasterize(&per_face_vertices, &per_face_indices),
+                rasterize(&greedy_vertices, &greedy_indices)
+            );
+        }
+    }
+
+    #[test]
+    fn greedy_mesh_triangle_count_test() {
+        let per_face_config = create_mock_config(MeshingMode::PerFace);
+        let greedy_config = create_mock_config(MeshingMode::Greedy);
+
+        let mut per_face_triangles = 0;
+        let mut greedy_triangles = 0;
+        for chunk in test_chunks(&per_face_config) {
+            let [(_, per_face_indices), ..] =
+                build_chunk_mesh(&chunk, &chunk_light(&chunk), &per_face_config, LOD::MAX);
+            let [(_, greedy_indices), ..] =
+                build_chunk_mesh(&chunk, &chunk_light(&chunk), &greedy_config, LOD::MAX);
+
+            assert!(greedy_indices.len() <= per_face_indices.len());
+            per_face_triangles += per_face_indices.len() / 3;
+            greedy_triangles += greedy_indices.len() / 3;
+        }
+        assert!(greedy_triangles < per_face_triangles);
+
+        // a solid chunk only needs one quad per side
+        let mut full_chunk = ChunkData::new(greedy_config.depth);
+        for x in 0..greedy_config.depth {
+            for y in 0..greedy_config.depth {
+                for z in 0..greedy_config.depth {
+                    full_chunk.set(&(x, y, z), 2);
+                }
+            }
+        }
+        // evenly lit, so every side merges into one quad
+        let light = LightData::filled(greedy_config.depth, 15, 0);
+        let [(vertices, indices), ..] =
+            build_chunk_mesh(&full_chunk, &light, &greedy_config, LOD::MAX);
+        assert_eq!(vertices.len(), 6 * 4);
+        assert_eq!(indices.len(), 6 * 6);
+    }
+
+    #[test]
+    fn calc_lod_test() {
+        let config = create_mock_config(MeshingMode::PerFace);
+        let player_chunk = ChunkPos::new(1, -2, 3);
+
+        assert_eq!(calc_lod(&config, &player_chunk, &player_chunk), LOD::MAX);
+        assert_eq!(
+            calc_lod(&config, &player_chunk.offset(2, -1, 0), &player_chunk),
+            LOD::MAX
+        );
+        assert_eq!(
+            calc_lod(&config, &player_chunk.offset(0, 3, 1), &player_chunk),
+            LOD::MED
+        );
+        assert_eq!(
+            calc_lod(&config, &player_chunk.offset(-4, 0, 4), &player_chunk),
+            LOD::MED
+        );
+        assert_eq!(
+            calc_lod(&config, &player_chunk.offset(0, 0, -5), &player_chunk),
+            LOD::MIN
+        );
+
+        // chunks that can't be split into cells of the level use more detail
+        let mut config = config;
+        config.depth = 18;
+        assert_eq!(
+            calc_lod(&config, &player_chunk.offset(0, 0, 5), &player_chunk),
+            LOD::MED
+        );
+        config.depth = 17;
+        assert_eq!(
+            calc_lod(&config, &player_chunk.offset(0, 0, 5), &player_chunk),
+            LOD::MAX
+        );
+    }
+
+    #[test]
+    fn downsample_test() {
+        let mut chunk = ChunkData::new(8);
+        // a cell with 4 of its 8 blocks set is solid, and gets the top block
+        chunk.set(&(0, 0, 0), 2);
+        chunk.set(&(1, 0, 0), 2);
+        chunk.set(&(0, 0, 1), 2);
+        chunk.set(&(1, 1, 1), 1);
+        // a cell with less than half of its blocks set is empty
+        chunk.set(&(2, 0, 0), 3);
+        chunk.set(&(3, 1, 1), 3);
+        chunk.set(&(3, 0, 1), 3);
+        // the border is ignored
+        chunk.set(&(-1, 0, 0), 2);
+        chunk.set(&(8, 8, 8), 2);
+
+        let cells = downsample(&chunk, LOD::MED);
+        assert_eq!(cells.depth(), 4);
+        assert_eq!(cells.iter().collect::<Vec<_>>(), vec![((0, 0, 0), 1)]);
+
+        let mut full_chunk = ChunkData::new(8);
+        for x in -1..9 {
+            for y in -1..9 {
+                for z in -1..9 {
+                    full_chunk.set(&(x, y, z), 2);
+                }
+            }
+        }
+        let cells = downsample(&full_chunk, LOD::MIN);
+        assert_eq!(cells.depth(), 2);
+        assert_eq!(cells.iter().count(), 8);
+        assert_eq!(cells.get(&(-1, 0, 0)), 0);
+    }
+
+    #[test]
+    fn lod_mesh_test() {
+        for meshing in [MeshingMode::PerFace, MeshingMode::Greedy] {
+            let config = create_mock_config(meshing);
+
+            // a chunk surrounded by solid blocks has no faces at full detail,
+            // but the skirts around a downsampled chunk are always there
+            let mut full_chunk = ChunkData::new(config.depth);
+            for x in -1..=config.depth {
+                for y in -1..=config.depth {
+                    for z in -1..=config.depth {
+                        full_chunk.set(&(x, y, z), 2);
+                    }
+                }
+            }
+            let [(_, indices), ..] =
+                build_chunk_mesh(&full_chunk, &chunk_light(&full_chunk), &config, LOD::MAX);
+            assert!(indices.is_empty());
+
+            for lod in [LOD::MED, LOD::MIN] {
+                let [(vertices, indices), ..] =
+                    build_chunk_mesh(&full_chunk, &chunk_light(&full_chunk), &config, lod);
+                assert!(!indices.is_empty());
+
+                // the mesh is scaled back up to cover the whole chunk
+                let positions: Vec<[u32; 3]> = vertices.iter().map(unpack_position).collect();
+                let depth = config.depth as u32;
+                for axis in 0..3 {
+                    assert_eq!(positions.iter().map(|p| p[axis]).min(), Some(0));
+                    assert_eq!(positions.iter().map(|p| p[axis]).max(), Some(depth));
+                    assert!(positions.iter().all(|p| p[axis] % lod.step() == 0));
+                }
+            }
+
+            // lower levels of detail have less faces
+            let chunk = generate(&config, &ChunkPos::new(0, -1, 0));
+            let [(_, max), ..] = build_chunk_mesh(&chunk, &chunk_light(&chunk), &config, LOD::MAX);
+            let [(_, med), ..] = build_chunk_mesh(&chunk, &chunk_light(&chunk), &config, LOD::MED);
+            let [(_, min), ..] = build_chunk_mesh(&chunk, &chunk_light(&chunk), &config, LOD::MIN);
+            assert!(med.len() < max.len());
+            assert!(min.len() < med.len());
+        }
+    }
+
+    #[test]
+    fn scaled_vertex_test() {
+        let vertex = Vertex::tiled([3.0, 16.0, 0.0], [0.125, 0.0625], [2, 1], 0.5);
+        let scaled = vertex.scaled(4);
+        assert_eq!(unpack_position(&scaled), [12, 64, 0]);
+        assert_eq!(scaled.data & 0x7FF, vertex.data & 0x7FF);
+        assert_eq!(scaled.tile, vertex.tile);
+    }
+
+    #[test]
+    fn lit_mesh_test() {
+        let config = create_mock_config(MeshingMode::PerFace);
+        let mut chunk = ChunkData::new(config.depth);
+        // a room with a stone floor and roof, lit by a lamp
+        for x in -1..=16 {
+            for z in -1..=16 {
+                chunk.set(&(x, 0, z), 2);
+                chunk.set(&(x, 15, z), 2);
+            }
+        }
+        chunk.set(&(8, 1, 8), 4);
+        let light = chunk_light(&chunk);
 
-    // loop over all blocks stored
-    for (position, block_id) in chunk.iter() {
-        // check for the boundary that we generated
-        // and don't want to mesh
-        if !should_mesh_block(config, position) {
-            continue;
+        let [(vertices, _), ..] = build_chunk_mesh(&chunk, &light, &config, LOD::MAX);
+        let sky = |vertex: &Vertex| (vertex.tile >> 16) & 0xF;
+        let block = |vertex: &Vertex| (vertex.tile >> 12) & 0xF;
+        for vertex in vertices.iter() {
+            match unpack_position(vertex) {
+                [_, 16, _] => assert_eq!(sky(vertex), 15),
+                [0, 1, 0] => assert_eq!(block(vertex), 0),
+                _ => assert_eq!(sky(vertex), 0),
+            }
         }
+        assert!(vertices.iter().map(block).max().unwrap() >= 13);
 
-        let block = config.dict.get(block_id);
-        let model = block.unwrap_or(config.dict.get(&0).unwrap()).model;
-        model(&chunk, config, position, &mut vertices, &mut indices);
+        // a cell is as bright as the brightest of its blocks
+        let cells = downsample_light(&light, LOD::MED);
+        assert_eq!(cells.get(&(4, 0, 4), LightChannel::Block), 15);
+        // (1, 1, 1) is 14 blocks from the lamp
+        assert_eq!(cells.get(&(0, 0, 0), LightChannel::Block), 1);
     }
 
-    RenderObject::new(
-        "chunk_render_group",
-        bytemuck::cast_slice(vertices.as_slice()),
-        bytemuck::cast_slice(indices.as_slice()),
-    )
+    #[test]
+    fn render_layer_test() {
+        let mut chunk = ChunkData::new(16);
+        // stone next to two blocks of water, and two blocks of glass
+        chunk.set(&(2, 2, 2), 2);
+        chunk.set(&(3, 2, 2), 7);
+        chunk.set(&(4, 2, 2), 7);
+        chunk.set(&(7, 2, 2), 5);
+        chunk.set(&(8, 2, 2), 5);
+        let light = chunk_light(&chunk);
+
+        let per_face_config = create_mock_config(MeshingMode::PerFace);
+        let greedy_config = create_mock_config(MeshingMode::Greedy);
+        let per_face = build_chunk_mesh(&chunk, &light, &per_face_config, LOD::MAX);
+        let greedy = build_chunk_mesh(&chunk, &light, &greedy_config, LOD::MAX);
+
+        let faces = |layer: RenderLayer| per_face[layer as usize].1.len() / 6;
+        // the stone's face is seen through the water
+        assert_eq!(faces(RenderLayer::Opaque), 6);
+        // no faces between the blocks of water, or on the water against the stone
+        assert_eq!(faces(RenderLayer::Translucent), 9);
+        // cutout blocks keep the faces between them
+        assert_eq!(faces(RenderLayer::Cutout), 12);
+
+        for layer in RenderLayer::ALL {
+            let (vertices, indices) = &per_face[layer as usize];
+            let (greedy_vertices, greedy_indices) = &greedy[layer as usize];
+            assert_eq!(
+                rasterize(vertices, indices),
+                rasterize(greedy_vertices, greedy_indices),
+                "{:?}",
+                layer
+            );
+        }
+    }
+
+    /// A chunk with nothing but the blocks, evenly lit by the sky.
+    fn blocks_chunk(blocks: &[(Position, u32)]) -> (ChunkData, LightData) {
+        let mut chunk = ChunkData::new(16);
+        for (position, block_id) in blocks {
+            chunk.set(position, *block_id);
+        }
+        (chunk, LightData::filled(16, 15, 0))
+    }
+
+    #[test]
+    fn chunk_mesh_test() {
+        let per_face_config = create_mock_config(MeshingMode::PerFace);
+        let greedy_config = create_mock_config(MeshingMode::Greedy);
+
+        // a block of stone has a quad on every side
+        let (chunk, light) = blocks_chunk(&[((5, 6, 7), 2)]);
+        for config in [&per_face_config, &greedy_config] {
+            let mesh = mesh_chunk(&chunk, &light, config, LOD::MAX);
+            assert_eq!(mesh.face_count(RenderLayer::Opaque), 6);
+            assert_eq!(mesh.vertices.len(), 6 * 4);
+            assert_eq!(mesh.indices.len(), 6 * 6);
+            assert_eq!(mesh.face_count(RenderLayer::Cutout), 0);
+            assert_eq!(mesh.face_count(RenderLayer::Translucent), 0);
+        }
+
+        // the faces between two blocks are hidden, and the greedy mesher
+        // merges the long sides
+        let (chunk, light) = blocks_chunk(&[((5, 6, 7), 2), ((6, 6, 7), 2)]);
+        let per_face = mesh_chunk(&chunk, &light, &per_face_config, LOD::MAX);
+        let greedy = mesh_chunk(&chunk, &light, &greedy_config, LOD::MAX);
+        assert_eq!(per_face.face_count(RenderLayer::Opaque), 10);
+        assert_eq!(greedy.face_count(RenderLayer::Opaque), 6);
+
+        // an empty chunk has nothing to upload
+        let (chunk, light) = blocks_chunk(&[]);
+        assert!(mesh_chunk(&chunk, &light, &per_face_config, LOD::MAX).is_empty());
+    }
+
+    #[test]
+    fn checkerboard_mesh_test() {
+        // every other block of the chunk is stone, so every block shows all
+        // of its faces and neither mesher can merge any of them
+        for meshing in [MeshingMode::PerFace, MeshingMode::Greedy] {
+            let mut config = ChunkConfig::new(10, 32, 3);
+            config.meshing = meshing;
+            let mut chunk = ChunkData::new(config.depth);
+            for x in 0..config.depth {
+                for y in 0..config.depth {
+                    for z in 0..config.depth {
+                        if (x + y + z) % 2 == 0 {
+                            chunk.set(&(x, y, z), 2);
+                        }
+                    }
+                }
+            }
+            let light = LightData::filled(config.depth, 15, 0);
+            let mesh = mesh_chunk(&chunk, &light, &config, LOD::MAX);
+
+            // more vertices than 16 bit indices reach, none of them wrapped
+            let blocks = (config.depth * config.depth * config.depth / 2) as usize;
+            assert_eq!(mesh.face_count(RenderLayer::Opaque), blocks * 6);
+            assert!(mesh.vertices.len() > u16::MAX as usize);
+            let (vertices, indices) = mesh.layer(RenderLayer::Opaque);
+            assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+            assert_eq!(indices.iter().max(), Some(&(vertices.len() as u32 - 1)));
+        }
+    }
+
+    #[test]
+    fn chunk_mesh_layers_test() {
+        let config = create_mock_config(MeshingMode::PerFace);
+        // stone, glass and water, each in its own layer
+        let (chunk, light) = blocks_chunk(&[((2, 2, 2), 2), ((6, 2, 2), 5), ((10, 2, 2), 7)]);
+        let meshes = build_chunk_mesh(&chunk, &light, &config, LOD::MAX);
+        let mesh = ChunkMesh::from_layers(meshes.clone());
+
+        let mut end = (0, 0);
+        for layer in RenderLayer::ALL {
+            let (vertices, indices) = &meshes[layer as usize];
+            assert_eq!(mesh.layer(layer), (&vertices[..], &indices[..]));
+            assert_eq!(mesh.face_count(layer), 6, "{:?}", layer);
+
+            // the layers follow each other, and their indices stay inside them
+            let range = &mesh.layers[layer as usize];
+            assert_eq!((range.vertices.start, range.indices.start), end);
+            end = (range.vertices.end, range.indices.end);
+            let (vertices, indices) = mesh.layer(layer);
+            assert!(indices.iter().all(|i| (*i as usize) < vertices.len()));
+        }
+        assert_eq!(end, (mesh.vertices.len(), mesh.indices.len()));
+    }
+
+    #[test]
+    fn mesh_winding_test() {
+        // blocks far enough apart that every quad belongs to one of them
+        let blocks = [((2, 2, 2), 2), ((6, 9, 4), 5), ((11, 4, 12), 3)];
+        let (chunk, light) = blocks_chunk(&blocks);
+
+        for meshing in [MeshingMode::PerFace, MeshingMode::Greedy] {
+            let config = create_mock_config(meshing);
+            let mesh = mesh_chunk(&chunk, &light, &config, LOD::MAX);
+            let mut triangles = 0;
+            for layer in RenderLayer::ALL {
+                let (vertices, indices) = mesh.layer(layer);
+                let p = |i: u32| {
+                    let [x, y, z] = unpack_position(&vertices[i as usize]);
+                    glam::vec3(x as f32, y as f32, z as f32)
+                };
+                for triangle in indices.chunks(3) {
+                    let [a, b, c] = [p(triangle[0]), p(triangle[1]), p(triangle[2])];
+                    let middle = (a + b + c) / 3.0;
+                    let block = blocks
+                        .iter()
+                        .map(|((x, y, z), _)| glam::vec3(*x as f32, *y as f32, *z as f32) + 0.5)
+                        .find(|center| (middle - *center).abs().max_element() <= 0.5)
+                        .unwrap();
+
+                    // counter clockwise seen from outside the block, so back
+                    // face culling keeps the outside
+                    let normal = (b - a).cross(c - a);
+                    assert!(normal.dot(middle - block) > 0.0, "{:?}", meshing);
+                    triangles += 1;
+                }
+            }
+            assert_eq!(triangles, 3 * 6 * 2);
+        }
+    }
+
+    #[test]
+    fn packed_vertex_test() {
+        let vertex = Vertex::from([5.0, 6.0, 7.0], [0.25, 0.5], 0.5);
+        assert_eq!(
+            vertex.data,
+            5 << 25 | 6 << 18 | 7 << 11 | 4 << 7 | 8 << 3 | 3
+        );
+        assert_eq!(vertex.tile, 0);
+        assert_eq!(vertex.lit(0xF3).tile, 0xF3 << 12);
+
+        let tiled = Vertex::tiled([0.0, 0.0, 0.0], [0.0, 0.0], [3, 2], 1.0).lit(0x1F);
+        assert_eq!(tiled.tile, 0x8000_0000 | 0x1F << 12 | 3 << 6 | 2);
+
+        // the top of a lone block of stone, it isn't shaded and sees the sky
+        let config = create_mock_config(MeshingMode::PerFace);
+        let (chunk, light) = blocks_chunk(&[((5, 6, 7), 2)]);
+        let mesh = mesh_chunk(&chunk, &light, &config, LOD::MAX);
+        let uv = config.dict.get(&2).unwrap().uv(Side::TOP);
+        let (vertices, _) = mesh.layer(RenderLayer::Opaque);
+        let top = FACES
+            .iter()
+            .position(|face| face.side == Side::TOP)
+            .unwrap();
+        for (vertex, (corner, face_uv)) in vertices[top * 4..top * 4 + 4]
+            .iter()
+            .zip(FACES[top].corners.iter().zip(FACES[top].uvs.iter()))
+        {
+            let position = [5 + corner.0, 6 + corner.1, 7 + corner.2].map(|c| c as u32);
+            assert_eq!(unpack_position(vertex), position);
+            assert_eq!(position[1], 7);
+            let tile_uv = [0, 1].map(|i| (uv[i] * 16.0) as u32 + face_uv[i] as u32);
+            assert_eq!(
+                [(vertex.data >> 7) & 0xF, (vertex.data >> 3) & 0xF],
+                tile_uv
+            );
+            assert_eq!(vertex.data & 0x7, 6);
+            assert_eq!(vertex.tile, 0xF0 << 12);
+        }
+    }
 }
@@ -3,17 +3,31 @@
 
 #![allow(dead_code)]
 
-use std::collections::HashMap;
+use std::{collections::HashMap, fmt};
 
+pub mod biome;
 pub mod block;
+pub mod caves;
+pub mod chunk_data;
 pub mod chunk_renderer;
 pub mod collision;
 pub mod cube_model;
 pub mod culling;
+pub mod editing;
+pub mod encoding;
+pub mod fluids;
 pub mod generation;
+pub mod lighting;
 pub mod loading;
 pub mod meshing;
+pub mod ores;
+pub mod random;
+pub mod raycast;
+pub mod settings;
+pub mod storage;
+pub mod structures;
 use block::BlockDictionary;
+pub use chunk_data::ChunkData;
 
 /// We load chunks by an area of
 /// depth + 2 * depth + 2 * depth + 2
@@ -24,66 +38,160 @@ use block::BlockDictionary;
 /// chunk generation
 
 pub type Position = (i32, i32, i32);
-pub type ChunkData = HashMap<Position, u32>;
-pub type ChunkStorage = HashMap<String, ChunkData>;
+
+/// Offsets to the six blocks sharing a face with a block, down first.
+pub const NEIGHBORS: [Position; 6] = [
+    (0, -1, 0),
+    (0, 1, 0),
+    (1, 0, 0),
+    (-1, 0, 0),
+    (0, 0, 1),
+    (0, 0, -1),
+];
+
+/// Get the position at an offset from another.
+pub fn offset_position(position: &Position, offset: &Position) -> Position {
+    (
+        position.0 + offset.0,
+        position.1 + offset.1,
+        position.2 + offset.2,
+    )
+}
+
+/// Size of the game's chunks in blocks, region files can only be opened with
+/// the depth they were saved with.
+pub const CHUNK_DEPTH: i32 = 32;
+pub type ChunkStorage = HashMap<ChunkPos, ChunkData>;
+
+/// Position of a chunk, ie. a world space position divided by the chunk depth.
+/// Used as the key for everything that is stored per chunk.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ChunkPos {
+    pub x: i32,
+    pub y: i32,
+    pub z: i32,
+}
+
+impl ChunkPos {
+    pub const fn new(x: i32, y: i32, z: i32) -> Self {
+        Self { x, y, z }
+    }
+
+    /// The chunk position moved by the given amount of chunks.
+    pub const fn offset(&self, x: i32, y: i32, z: i32) -> Self {
+        Self::new(self.x + x, self.y + y, self.z + z)
+    }
+}
+
+impl From<Position> for ChunkPos {
+    fn from(pos: Position) -> Self {
+        Self::new(pos.0, pos.1, pos.2)
+    }
+}
+
+impl From<ChunkPos> for Position {
+    fn from(pos: ChunkPos) -> Self {
+        (pos.x, pos.y, pos.z)
+    }
+}
+
+/// Formats the chunk position the way chunks used to be identified,
+/// ie. "chunk,x,y,z", only for logging and debugging.
+impl fmt::Display for ChunkPos {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "chunk,{},{},{}", self.x, self.y, self.z)
+    }
+}
 
 use libnoise::prelude::*;
 
-use self::{block::Block, cube_model::cube_model};
+use self::{
+    biome::Climate,
+    block::{parse_block_dictionary, AtlasManifest},
+    generation::{default_passes, world_passes, GenerationPasses},
+    meshing::MeshingMode,
+    settings::WorldGenSettings,
+    structures::{parse_structures, StructureTemplate},
+};
 
+#[derive(Clone)]
 pub struct ChunkConfig {
     // initialized noise function
     // height bias
     // squish bias
     pub noise: libnoise::Simplex<3>, // fn([f64; 3]) -> f64, //Arc<dyn NoiseFn<f64, 3> + Send + Sync>, // fn([f64; 3]) -> f64, // chunk size?
-    pub noise_amplitude: (f64, f64, f64),
+    /// Temperature and humidity noise the biomes are picked from.
+    pub climate: Climate,
+    /// Seed and shape of the terrain the world is generated with.
+    pub settings: WorldGenSettings,
+    /// Stages the chunks are generated in, in order.
+    pub passes: GenerationPasses,
     pub depth: i32,
 
     pub uv_size: f32,
     pub load_radius: u32,
+    /// Chunk distances from the player past which chunks are meshed at
+    /// medium and then minimum level of detail.
+    pub lod_distances: [u32; 2],
+    pub meshing: MeshingMode,
 
     pub dict: BlockDictionary,
 }
 
 impl ChunkConfig {
+    /// Create the config of a world generated with the settings, with the
+    /// blocks of the dictionary and the structures built from them.
+    /// Fails if a block the world is generated with isn't in the dictionary.
+    pub fn from_settings(
+        settings: WorldGenSettings,
+        dict: BlockDictionary,
+        structures: Vec<StructureTemplate>,
+        depth: i32,
+        load_radius: u32,
+    ) -> anyhow::Result<Self> {
+        debug_assert!(
+            depth <= meshing::MAX_MESH_DEPTH,
+            "Chunks {} blocks deep can't be meshed",
+            depth
+        );
+        Ok(Self {
+            noise: Simplex::new(settings.seed),
+            climate: Climate::new(settings.seed),
+            passes: world_passes(&settings, &dict, structures)?,
+            settings,
+            depth,
+            load_radius,
+            lod_distances: [2, 4],
+            meshing: MeshingMode::default(),
+            uv_size: 0.0625,
+            dict,
+        })
+    }
+
     #[allow(dead_code)]
-    fn new(seed: u32, depth: i32, load_radius: u32) -> Self {
+    pub(crate) fn new(seed: u32, depth: i32, load_radius: u32) -> Self {
+        let dict = parse_block_dictionary(
+            include_str!("../../assets/blocks.ron"),
+            &AtlasManifest::parse(include_str!("../../assets/atlas.ron"), 0.0625).unwrap(),
+        )
+        .unwrap();
+        let structures =
+            parse_structures(include_str!("../../assets/structures.ron"), &dict).unwrap();
         Self {
             noise: Simplex::new(seed as u64),
+            climate: Climate::new(seed as u64),
+            settings: WorldGenSettings {
+                seed: seed as u64,
+                noise_amplitude: (0.001, 0.01, 0.001),
+                ..WorldGenSettings::default()
+            },
+            passes: default_passes(seed as u64, &dict, structures).unwrap(),
             depth,
             load_radius,
+            lod_distances: [2, 4],
+            meshing: MeshingMode::default(),
             uv_size: 0.0625,
-            noise_amplitude: (0.001, 0.01, 0.001),
-            dict: BlockDictionary::from([
-                (0, Block::default()),
-                (
-                    1,
-                    Block {
-                        model: cube_model,
-                        transparent: false,
-                        ident: "grass".to_owned(),
-                        uv: [0.0, 0.0],
-                    },
-                ),
-                (
-                    2,
-                    Block {
-                        model: cube_model,
-                        transparent: false,
-                        ident: "stone".to_owned(),
-                        uv: [0.0625, 0.0],
-                    },
-                ),
-                (
-                    3,
-                    Block {
-                        model: cube_model,
-                        transparent: false,
-                        ident: "dirt".to_owned(),
-                        uv: [0.125, 0.0],
-                    },
-                ),
-            ]),
+            dict,
         }
     }
 }
@@ -114,9 +222,9 @@ pub fn local_block_pos(chunk_config: &ChunkConfig, pos: i32) -> i32 {
     ((pos % size) + size) % size
 }
 
-/// convert a world space position tuple to get a chunk pos tuple
-pub fn chunk_position(chunk_config: &ChunkConfig, pos: &Position) -> Position {
-    (
+/// convert a world space position tuple to get a chunk pos
+pub fn chunk_position(chunk_config: &ChunkConfig, pos: &Position) -> ChunkPos {
+    ChunkPos::new(
         global_chunk_pos(chunk_config, pos.0),
         global_chunk_pos(chunk_config, pos.1),
         global_chunk_pos(chunk_config, pos.2),
@@ -128,68 +236,58 @@ pub fn global_chunk_pos(chunk_config: &ChunkConfig, pos: i32) -> i32 {
     (pos as f32 / chunk_config.depth as f32).floor() as i32
 }
 
-/// take a position and return a chunk_id
-pub fn chunk_id(pos: &Position) -> String {
-    format!("chunk,{},{},{}", pos.0, pos.1, pos.2)
-}
-
-pub fn chunk_pos_from_id(chunk_id: &String) -> Position {
-    let mut pos = (0, 0, 0);
-
-    let mut c = 0;
-    for part in chunk_id.split(",") {
-        if c == 0 {
-            c += 1;
-            continue;
-        }
-
-        if c == 1 {
-            pos.0 = part.parse().unwrap();
-        }
-        if c == 2 {
-            pos.1 = part.parse().unwrap();
-        }
-        if c == 3 {
-            pos.2 = part.parse().unwrap();
-        }
-        c += 1;
-    }
-
-    pos
-}
-
 #[allow(dead_code)]
 pub fn get_block(
     chunk_config: &ChunkConfig,
-    loaded_chunks: &HashMap<String, ChunkData>,
+    loaded_chunks: &ChunkStorage,
     raw_position: &Position,
 ) -> u32 {
-    let chunk_pos = chunk_id(&chunk_position(chunk_config, raw_position));
+    let chunk_pos = chunk_position(chunk_config, raw_position);
     let chunk_query = loaded_chunks.get(&chunk_pos);
     if let Some(chunk_data) = chunk_query {
         let block_pos = local_position(chunk_config, raw_position);
-        let block = chunk_data.get(&block_pos);
-
-        if let Some(block_id) = block {
-            return *block_id;
-        }
+        return chunk_data.get(&block_pos);
     }
 
     0
 }
 
 // Level of detail
-// how many blocks comprise the chunk's mesh
-// max level of detail is used as the chunk size
+// how many blocks comprise one cell of the chunk's mesh,
+// the chunk depth has to be a multiple of the cell size
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct LOD(u32);
 impl LOD {
-    const MIN: LOD = LOD(4);
-    const MED: LOD = LOD(8);
-    const MAX: LOD = LOD(16);
+    pub const MIN: LOD = LOD(4);
+    pub const MED: LOD = LOD(2);
+    pub const MAX: LOD = LOD(1);
+
+    /// How many blocks wide a cell of the mesh is.
+    pub fn step(&self) -> u32 {
+        self.0
+    }
 }
 
-// Level of detail
+/// Pick the level of detail of a chunk from how many chunks away from the
+/// player's chunk it is, chunks at a lower level of detail are meshed with
+/// less faces.
+pub fn calc_lod(chunk_config: &ChunkConfig, chunk_pos: &ChunkPos, player_chunk: &ChunkPos) -> LOD {
+    let distance = (chunk_pos.x - player_chunk.x)
+        .abs()
+        .max((chunk_pos.y - player_chunk.y).abs())
+        .max((chunk_pos.z - player_chunk.z).abs()) as u32;
 
-pub fn calc_lod() -> LOD {
-    LOD::MAX
+    let mut lod = if distance <= chunk_config.lod_distances[0] {
+        LOD::MAX
+    } else if distance <= chunk_config.lod_distances[1] {
+        LOD::MED
+    } else {
+        LOD::MIN
+    };
+
+    // use more detail if the chunk can't be split into cells of this size
+    while chunk_config.depth % lod.step() as i32 != 0 {
+        lod = LOD(lod.0 / 2);
+    }
+    lod
 }
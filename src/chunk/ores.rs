@@ -0,0 +1,300 @@
+//! Veins of ore in the stone, more common the deeper they are.
+//!
+//! Like worm caves, veins are placed in cells seeded from their position, so
+//! a vein crossing chunk borders comes out the same in every chunk.
+
+use anyhow::anyhow;
+
+use super::block::{find_block, BlockDictionary};
+use super::generation::{global_position, GenerationArea, GenerationPass, TerrainBlocks};
+use super::random::WorldRandom;
+use super::{ChunkConfig, ChunkData, ChunkPos, Position};
+
+/// Tells the random numbers of ores apart from other features, every ore adds
+/// its index.
+const ORE_SALT: u64 = 100;
+/// Size of the cells veins are placed in, in blocks.
+const ORE_CELL: i32 = 32;
+/// Farthest a block of a vein is from its center.
+const ORE_REACH: i32 = 4;
+
+/// A kind of ore and where its veins are found.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OreKind {
+    /// Ident of the ore's block.
+    pub block: &'static str,
+    /// Most veins there can be in a cell.
+    pub veins: u32,
+    /// Radius of the blobs a vein is made of.
+    pub radius: f64,
+    /// Height above which there are no veins.
+    pub top: i32,
+    /// Height below which every vein of the cell is placed, between it and
+    /// the top veins get rarer the higher they are.
+    pub peak: i32,
+}
+
+impl OreKind {
+    /// Get the chance of a vein being placed at a height.
+    pub fn chance(&self, y: i32) -> f64 {
+        ((self.top - y) as f64 / (self.top - self.peak) as f64).clamp(0.0, 1.0)
+    }
+}
+
+/// Every ore, rarer ores last, they replace more common ores where veins
+/// overlap.
+pub const ORES: [OreKind; 3] = [
+    OreKind {
+        block: "coal_ore",
+        veins: 12,
+        radius: 1.6,
+        top: 48,
+        peak: 0,
+    },
+    OreKind {
+        block: "iron_ore",
+        veins: 8,
+        radius: 1.4,
+        top: 0,
+        peak: -48,
+    },
+    OreKind {
+        block: "gold_ore",
+        veins: 3,
+        radius: 1.2,
+        top: -32,
+        peak: -96,
+    },
+];
+
+/// A vein of ore, a few blobs around its center.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Vein {
+    /// Index of the ore in `ORES`.
+    pub ore: usize,
+    /// Center and radius of every blob.
+    pub blobs: Vec<([f64; 3], f64)>,
+}
+
+/// Get the veins in a cell, cells are positions divided by `ORE_CELL`.
+pub fn cell_veins(seed: u64, cell: &Position) -> Vec<Vein> {
+    let size = ORE_CELL as f64;
+    let mut veins = Vec::new();
+    for (ore, kind) in ORES.iter().enumerate() {
+        let mut random = WorldRandom::new(seed, ORE_SALT + ore as u64, cell);
+        for _ in 0..kind.veins {
+            let center = [
+                cell.0 as f64 * size + random.range(0.0, size),
+                cell.1 as f64 * size + random.range(0.0, size),
+                cell.2 as f64 * size + random.range(0.0, size),
+            ];
+            let blobs = (0..3)
+                .map(|_| {
+                    let offset = [(); 3].map(|_| random.range(-1.0, 1.0));
+                    let blob = [0, 1, 2].map(|axis| center[axis] + offset[axis]);
+                    (blob, kind.radius * random.range(0.7, 1.0))
+                })
+                .collect();
+
+            if random.next_f64() < kind.chance(center[1] as i32) {
+                veins.push(Vein { ore, blobs });
+            }
+        }
+    }
+    veins
+}
+
+/// Places veins of ore in the stone.
+pub struct OrePass {
+    seed: u64,
+    /// Block id of the stone, then of each ore in `ORES`.
+    blocks: [u32; ORES.len() + 1],
+}
+
+impl OrePass {
+    /// Create the pass, with the blocks of the ores looked up by their ident.
+    pub fn new(seed: u64, dict: &BlockDictionary) -> anyhow::Result<Self> {
+        let mut blocks = [TerrainBlocks::new(dict)?.stone; ORES.len() + 1];
+        for (block, kind) in blocks[1..].iter_mut().zip(ORES.iter()) {
+            *block = find_block(dict, kind.block)
+                .ok_or_else(|| anyhow!("The ore block {} isn't defined", kind.block))?;
+        }
+        Ok(Self { seed, blocks })
+    }
+}
+
+impl GenerationPass for OrePass {
+    fn name(&self) -> &'static str {
+        "ores"
+    }
+
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    ) {
+        let cells = |chunk: i32| {
+            let min = chunk * config.depth - 1 - ORE_REACH;
+            let max = chunk * config.depth + config.depth + ORE_REACH;
+            min.div_euclid(ORE_CELL)..=max.div_euclid(ORE_CELL)
+        };
+
+        let origin = global_position(config, pos, &(0, 0, 0));
+        for cell_x in cells(pos.x) {
+            for cell_y in cells(pos.y) {
+                for cell_z in cells(pos.z) {
+                    for vein in cell_veins(self.seed, &(cell_x, cell_y, cell_z)) {
+                        for (center, radius) in vein.blobs.iter() {
+                            let blocks = &self.blocks[..=vein.ore + 1];
+                            place_blob(config, &origin, area, blocks, center, *radius, output);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Turn the stone, and the more common ores, inside of a blob into the ore.
+/// `blocks` are the block ids of the ore, last, and of the stone and the more
+/// common ores it replaces.
+fn place_blob(
+    config: &ChunkConfig,
+    origin: &[i32; 3],
+    area: GenerationArea,
+    blocks: &[u32],
+    center: &[f64; 3],
+    radius: f64,
+    output: &mut ChunkData,
+) {
+    // the blocks around the blob, in chunk local positions
+    let range = |axis: usize| {
+        let min = ((center[axis] - radius).floor() as i32 - origin[axis]).max(-1);
+        let max = ((center[axis] + radius).ceil() as i32 - origin[axis]).min(config.depth);
+        min..=max
+    };
+
+    for x in range(0) {
+        for y in range(1) {
+            for z in range(2) {
+                let position = (x, y, z);
+                let distance: f64 = [x, y, z]
+                    .iter()
+                    .enumerate()
+                    .map(|(axis, local)| ((local + origin[axis]) as f64 - center[axis]).powi(2))
+                    .sum();
+                if distance > radius * radius || !area.contains(config, &position) {
+                    continue;
+                }
+
+                // which ore wins where veins overlap doesn't depend on the
+                // order they are placed in
+                let block_id = output.get(&position);
+                let (ore, replaced) = blocks.split_last().unwrap();
+                if replaced.contains(&block_id) {
+                    output.set(&position, *ore);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::generation::generate;
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 16, 3)
+    }
+
+    #[test]
+    fn ore_chance_test() {
+        let [coal, iron, gold] = ORES;
+        assert_eq!(coal.chance(60), 0.0);
+        assert_eq!(coal.chance(24), 0.5);
+        assert_eq!(coal.chance(-100), 1.0);
+        assert_eq!(iron.chance(0), 0.0);
+        assert!(iron.chance(-10) < iron.chance(-30));
+        assert_eq!(gold.chance(-32), 0.0);
+        assert_eq!(gold.chance(-200), 1.0);
+    }
+
+    #[test]
+    fn ore_depth_test() {
+        // count the veins in layers of cells from y = -128 to 64
+        let mut counts = [[0; 3]; 6];
+        for (layer, count) in counts.iter_mut().enumerate() {
+            for x in 0..10 {
+                for z in 0..10 {
+                    let cell = (x, layer as i32 - 4, z);
+                    for vein in cell_veins(10, &cell) {
+                        count[vein.ore] += 1;
+                    }
+                }
+            }
+        }
+
+        // nothing above the top of each ore, and more veins deep down
+        let layer = |height: i32| ((height + 128) / ORE_CELL) as usize;
+        for (ore, kind) in ORES.iter().enumerate() {
+            assert!(
+                counts[layer(kind.top + ORE_CELL - 1)..]
+                    .iter()
+                    .all(|count| count[ore] == 0),
+                "{:?}",
+                counts
+            );
+        }
+        assert!(counts[layer(-32)][0] > counts[layer(32)][0], "{:?}", counts);
+        assert!(
+            counts[layer(-64)][1] > counts[layer(-32)][1],
+            "{:?}",
+            counts
+        );
+        assert!(
+            counts[layer(-128)][2] > counts[layer(-64)][2],
+            "{:?}",
+            counts
+        );
+        assert!(
+            counts[layer(-128)][2] < counts[layer(-128)][1],
+            "{:?}",
+            counts
+        );
+    }
+
+    #[test]
+    fn unknown_ore_test() {
+        let mut config = create_mock_config();
+        assert!(OrePass::new(10, &config.dict).is_ok());
+
+        let iron = find_block(&config.dict, "iron_ore").unwrap();
+        config.dict.remove(&iron);
+        let error = OrePass::new(10, &config.dict).err().unwrap().to_string();
+        assert!(error.contains("iron_ore"), "{}", error);
+    }
+
+    #[test]
+    fn ore_generation_test() {
+        let config = create_mock_config();
+
+        // ore only replaces stone, so it's never in the air or the surface
+        let blocks = ORES.map(|kind| find_block(&config.dict, kind.block).unwrap());
+        let mut ores = 0;
+        for chunk_x in 0..4 {
+            let chunk = generate(&config, &ChunkPos::new(chunk_x, -4, 0));
+            for (position, block_id) in chunk.iter() {
+                if blocks.contains(&block_id) {
+                    ores += 1;
+                    let global =
+                        global_position(&config, &ChunkPos::new(chunk_x, -4, 0), &position);
+                    assert!(global[1] < ORES[0].top + ORE_REACH);
+                }
+            }
+        }
+        assert!(ores > 0);
+    }
+}
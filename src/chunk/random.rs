@@ -0,0 +1,80 @@
+//! Random numbers for world generation, seeded from the world seed and the
+//! position of what is generated, so the same world comes out no matter what
+//! order it is generated in.
+
+use super::Position;
+
+/// A small random number generator (splitmix64).
+#[derive(Clone, Debug)]
+pub struct WorldRandom {
+    state: u64,
+}
+
+impl WorldRandom {
+    /// Create a generator for a feature at a position, `salt` tells apart
+    /// features placed at the same positions, like caves and ores.
+    pub fn new(seed: u64, salt: u64, position: &Position) -> Self {
+        let mut random = Self { state: seed };
+        for value in [
+            salt,
+            position.0 as u64,
+            position.1 as u64,
+            position.2 as u64,
+        ] {
+            random.state ^= value;
+            random.state = random.next_u64();
+        }
+        random
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// Get a number from 0 up to but not including 1.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+
+    /// Get a number from `min` up to but not including `max`.
+    pub fn range(&mut self, min: f64, max: f64) -> f64 {
+        min + self.next_f64() * (max - min)
+    }
+
+    /// Get a whole number from `min` up to but not including `max`.
+    pub fn range_i32(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next_u64() % (max - min) as u64) as i32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn world_random_test() {
+        let sequence = |seed, salt, position: &Position| {
+            let mut random = WorldRandom::new(seed, salt, position);
+            (0..8).map(|_| random.next_u64()).collect::<Vec<u64>>()
+        };
+
+        let first = sequence(1, 2, &(3, -4, 5));
+        assert_eq!(first, sequence(1, 2, &(3, -4, 5)));
+        assert_ne!(first, sequence(2, 2, &(3, -4, 5)));
+        assert_ne!(first, sequence(1, 3, &(3, -4, 5)));
+        assert_ne!(first, sequence(1, 2, &(-4, 3, 5)));
+        assert_ne!(first, sequence(1, 2, &(3, -4, 6)));
+
+        let mut random = WorldRandom::new(1, 2, &(0, 0, 0));
+        for _ in 0..1000 {
+            let value = random.range(-2.0, 3.0);
+            assert!((-2.0..3.0).contains(&value));
+            let value = random.range_i32(-5, 5);
+            assert!((-5..5).contains(&value));
+        }
+    }
+}
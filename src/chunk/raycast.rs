@@ -0,0 +1,143 @@
+//! Voxel raycasting, used to find the block the player is looking at.
+
+use super::Position;
+
+/// The block a ray hit.
+#[derive(Debug, PartialEq)]
+pub struct RaycastHit {
+    /// World space position of the block.
+    pub position: Position,
+    /// Normal of the face the ray entered the block through,
+    /// (0, 0, 0) if the ray started inside the block.
+    pub normal: Position,
+    /// Distance along the ray to the hit.
+    pub distance: f32,
+}
+
+/// Walk the blocks along a ray with the DDA algorithm, returning the first
+/// block `is_solid` returns true for within `max_distance`.
+/// Blocks are unit cubes, ie. block (x, y, z) spans from (x, y, z) to (x + 1, y + 1, z + 1).
+pub fn raycast(
+    origin: glam::Vec3,
+    direction: glam::Vec3,
+    max_distance: f32,
+    is_solid: impl Fn(&Position) -> bool,
+) -> Option<RaycastHit> {
+    let direction = direction.normalize_or_zero();
+    if direction == glam::Vec3::ZERO {
+        return None;
+    }
+
+    let origin = origin.to_array();
+    let direction = direction.to_array();
+
+    let mut block = [0; 3];
+    let mut step = [0; 3];
+    // distance along the ray to the next block boundary on each axis
+    let mut t_max = [f32::INFINITY; 3];
+    // distance along the ray between block boundaries on each axis
+    let mut t_delta = [f32::INFINITY; 3];
+
+    for axis in 0..3 {
+        block[axis] = origin[axis].floor() as i32;
+
+        if direction[axis] > 0.0 {
+            step[axis] = 1;
+            t_delta[axis] = 1.0 / direction[axis];
+            t_max[axis] = (block[axis] as f32 + 1.0 - origin[axis]) * t_delta[axis];
+        } else if direction[axis] < 0.0 {
+            step[axis] = -1;
+            t_delta[axis] = -1.0 / direction[axis];
+            t_max[axis] = (origin[axis] - block[axis] as f32) * t_delta[axis];
+        }
+    }
+
+    let mut normal = [0; 3];
+    let mut distance = 0.0;
+    loop {
+        let position = (block[0], block[1], block[2]);
+        if is_solid(&position) {
+            return Some(RaycastHit {
+                position,
+                normal: (normal[0], normal[1], normal[2]),
+                distance,
+            });
+        }
+
+        // step into the next block along the axis with the closest boundary
+        let mut axis = 0;
+        if t_max[1] < t_max[axis] {
+            axis = 1;
+        }
+        if t_max[2] < t_max[axis] {
+            axis = 2;
+        }
+
+        distance = t_max[axis];
+        if distance > max_distance {
+            return None;
+        }
+
+        block[axis] += step[axis];
+        normal = [0; 3];
+        normal[axis] = -step[axis];
+        t_max[axis] += t_delta[axis];
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn raycast_axis_test() {
+        let solid = |pos: &Position| *pos == (3, 0, 0) || *pos == (-3, 0, 0);
+
+        let hit = raycast(glam::vec3(0.5, 0.5, 0.5), glam::Vec3::X, 10.0, solid).unwrap();
+        assert_eq!(hit.position, (3, 0, 0));
+        assert_eq!(hit.normal, (-1, 0, 0));
+        assert!((hit.distance - 2.5).abs() < 0.0001);
+
+        let hit = raycast(glam::vec3(0.5, 0.5, 0.5), -glam::Vec3::X, 10.0, solid).unwrap();
+        assert_eq!(hit.position, (-3, 0, 0));
+        assert_eq!(hit.normal, (1, 0, 0));
+        assert!((hit.distance - 2.5).abs() < 0.0001);
+
+        assert!(raycast(glam::vec3(0.5, 0.5, 0.5), glam::Vec3::Y, 10.0, solid).is_none());
+    }
+
+    #[test]
+    fn raycast_diagonal_test() {
+        let solid = |pos: &Position| pos.1 < 0;
+
+        let hit = raycast(
+            glam::vec3(0.25, 2.5, 0.3),
+            glam::vec3(1.0, -1.0, 1.0),
+            10.0,
+            solid,
+        )
+        .unwrap();
+        assert_eq!(hit.position.1, -1);
+        assert_eq!(hit.normal, (0, 1, 0));
+        assert!((hit.distance - 2.5 * 3.0_f32.sqrt()).abs() < 0.0001);
+    }
+
+    #[test]
+    fn raycast_max_distance_test() {
+        let solid = |pos: &Position| *pos == (0, 0, 5);
+
+        assert!(raycast(glam::vec3(0.5, 0.5, 0.5), glam::Vec3::Z, 4.0, solid).is_none());
+        assert!(raycast(glam::vec3(0.5, 0.5, 0.5), glam::Vec3::Z, 5.0, solid).is_some());
+        assert!(raycast(glam::vec3(0.5, 0.5, 0.5), glam::Vec3::ZERO, 5.0, solid).is_none());
+    }
+
+    #[test]
+    fn raycast_inside_block_test() {
+        let solid = |pos: &Position| *pos == (-1, -1, -1);
+
+        let hit = raycast(glam::vec3(-0.5, -0.5, -0.5), glam::Vec3::X, 5.0, solid).unwrap();
+        assert_eq!(hit.position, (-1, -1, -1));
+        assert_eq!(hit.normal, (0, 0, 0));
+        assert_eq!(hit.distance, 0.0);
+    }
+}
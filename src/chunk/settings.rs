@@ -0,0 +1,236 @@
+//! Settings a world is generated with, loaded from a settings file or picked
+//! from the built in presets.
+
+use std::{fmt, path::Path, str::FromStr};
+
+use anyhow::{anyhow, bail, Context};
+use serde::Deserialize;
+
+/// How the blocks of the world are generated.
+#[derive(Clone, Copy, Debug, Default, Deserialize, PartialEq, Eq)]
+pub enum GeneratorKind {
+    /// 3d noise terrain shaped by biomes, with caves, ores and structures.
+    #[default]
+    Terrain,
+    /// Flat layers of stone, dirt and grass.
+    Flat,
+    /// Islands floating in the sky, with nothing below them.
+    FloatingIslands,
+    /// Nothing at all.
+    Void,
+}
+
+/// The built in settings worlds can be generated with.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WorldPreset {
+    Default,
+    Superflat,
+    Amplified,
+    FloatingIslands,
+    Void,
+}
+
+impl WorldPreset {
+    pub const ALL: [WorldPreset; 5] = [
+        WorldPreset::Default,
+        WorldPreset::Superflat,
+        WorldPreset::Amplified,
+        WorldPreset::FloatingIslands,
+        WorldPreset::Void,
+    ];
+
+    /// Name of the preset on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            WorldPreset::Default => "default",
+            WorldPreset::Superflat => "superflat",
+            WorldPreset::Amplified => "amplified",
+            WorldPreset::FloatingIslands => "floating_islands",
+            WorldPreset::Void => "void",
+        }
+    }
+}
+
+impl fmt::Display for WorldPreset {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for WorldPreset {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        WorldPreset::ALL
+            .into_iter()
+            .find(|preset| preset.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = WorldPreset::ALL.iter().map(|p| p.name()).collect();
+                anyhow!(
+                    "Unknown world preset {}, the presets are {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The seed and the shape of the terrain of a world. Fields left out of a
+/// settings file are taken from the default preset.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct WorldGenSettings {
+    /// Everything generated is picked from the seed.
+    pub seed: u64,
+    pub generator: GeneratorKind,
+    /// Layers of terrain noise added together, each with finer detail.
+    pub octaves: u32,
+    /// How much faster each octave changes than the one before it.
+    pub lacunarity: f64,
+    /// How much less each octave adds than the one before it.
+    pub persistence: f64,
+    /// How fast the terrain noise changes along x, y and z.
+    pub noise_amplitude: (f64, f64, f64),
+    /// How many times taller the hills of every biome are.
+    pub height_amplitude: f64,
+    /// Height the ground of every biome is raised by, and the height of flat
+    /// worlds.
+    pub ground_level: i32,
+}
+
+impl Default for WorldGenSettings {
+    fn default() -> Self {
+        WorldGenSettings::preset(WorldPreset::Default)
+    }
+}
+
+impl WorldGenSettings {
+    pub fn preset(preset: WorldPreset) -> Self {
+        let default = Self {
+            seed: 123456789,
+            generator: GeneratorKind::Terrain,
+            octaves: 4,
+            lacunarity: 2.0,
+            persistence: 0.5,
+            noise_amplitude: (0.005, 0.005, 0.005),
+            height_amplitude: 1.0,
+            ground_level: 0,
+        };
+
+        match preset {
+            WorldPreset::Default => default,
+            WorldPreset::Superflat => Self {
+                generator: GeneratorKind::Flat,
+                ..default
+            },
+            WorldPreset::Amplified => Self {
+                octaves: 5,
+                height_amplitude: 4.0,
+                ground_level: 16,
+                ..default
+            },
+            WorldPreset::FloatingIslands => Self {
+                generator: GeneratorKind::FloatingIslands,
+                noise_amplitude: (0.01, 0.02, 0.01),
+                ..default
+            },
+            WorldPreset::Void => Self {
+                generator: GeneratorKind::Void,
+                ..default
+            },
+        }
+    }
+
+    /// Check that the settings make a world that can be generated.
+    pub fn validate(&self) -> anyhow::Result<()> {
+        if !(1..=16).contains(&self.octaves) {
+            bail!("There are {} octaves, there can be 1 to 16", self.octaves);
+        }
+        if self.lacunarity <= 0.0 {
+            bail!(
+                "The lacunarity is {}, it has to be above 0",
+                self.lacunarity
+            );
+        }
+        if !(0.0..1.0).contains(&self.persistence) {
+            bail!(
+                "The persistence is {}, it has to be from 0 up to 1",
+                self.persistence
+            );
+        }
+        let (x, y, z) = self.noise_amplitude;
+        if x <= 0.0 || y <= 0.0 || z <= 0.0 {
+            bail!("The noise amplitudes have to be above 0");
+        }
+        if self.height_amplitude <= 0.0 {
+            bail!(
+                "The height amplitude is {}, it has to be above 0",
+                self.height_amplitude
+            );
+        }
+        Ok(())
+    }
+
+    /// Parse the contents of a settings file.
+    pub fn parse(source: &str) -> anyhow::Result<Self> {
+        let settings: Self = ron::from_str(source)?;
+        settings.validate()?;
+        Ok(settings)
+    }
+
+    /// Load the settings from a file on disk.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let source = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read {}", path.display()))?;
+        Self::parse(&source)
+            .with_context(|| format!("Failed to load world settings from {}", path.display()))
+    }
+
+    /// Get the most the terrain noise can be above or below 0, the octaves
+    /// each add noise from -1 to 1 times their amplitude.
+    pub fn noise_range(&self) -> f64 {
+        (0..self.octaves)
+            .map(|octave| 0.5 * self.persistence.powi(octave as i32))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn presets_test() {
+        for preset in WorldPreset::ALL {
+            let settings = WorldGenSettings::preset(preset);
+            settings.validate().unwrap();
+            assert_eq!(preset.name().parse::<WorldPreset>().unwrap(), preset);
+        }
+        assert_eq!(
+            WorldGenSettings::preset(WorldPreset::Superflat).generator,
+            GeneratorKind::Flat
+        );
+        assert!("hilly".parse::<WorldPreset>().is_err());
+
+        // the noise of the default world is the same as it always was
+        let settings = WorldGenSettings::default();
+        assert_eq!(settings.noise_range(), 0.9375);
+    }
+
+    #[test]
+    fn settings_file_test() {
+        // left out fields are taken from the default preset
+        let settings =
+            WorldGenSettings::parse("(seed: 5, generator: FloatingIslands, octaves: 2)").unwrap();
+        assert_eq!(settings.seed, 5);
+        assert_eq!(settings.generator, GeneratorKind::FloatingIslands);
+        assert_eq!(settings.octaves, 2);
+        assert_eq!(settings.persistence, 0.5);
+
+        let error = WorldGenSettings::parse("(octaves: 0)").unwrap_err();
+        assert!(error.to_string().contains("0 octaves"), "{}", error);
+        let error = WorldGenSettings::parse("(persistence: 1.5)").unwrap_err();
+        assert!(error.to_string().contains("persistence"), "{}", error);
+        assert!(WorldGenSettings::parse("(hills: 3)").is_err());
+    }
+}
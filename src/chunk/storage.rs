@@ -0,0 +1,693 @@
+//! Persistent storage of chunk data on the filesystem.
+//!
+//! Chunks are grouped into regions of 16x16x16 chunks, and each region is
+//! stored in its own file. A region file starts with a header containing a
+//! magic number and the layout version, followed by a table with an offset
+//! and length for every chunk slot in the region. Chunk data is written to
+//! space no chunk uses, the chunk's old data included, or appended to the end
+//! of the file, and only then the table entry is updated to point at it, so a
+//! failed write keeps the old data. Files with more unused space than chunk
+//! data are compacted.
+//!
+//! Chunks that can't be read are copied to the `corrupt` folder before they
+//! can be saved over, once for every chunk.
+
+use std::collections::{HashMap, HashSet};
+use std::fs::{File, OpenOptions};
+use std::io::{Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use anyhow::{bail, Context};
+
+use super::encoding::{self, ENCODING_VERSION};
+use super::meshing::should_mesh_block;
+use super::{ChunkConfig, ChunkData, ChunkPos, Position};
+
+/// Number of chunks along each axis of a region.
+pub const REGION_SIZE: i32 = 16;
+
+/// Bytes at the start of every region file.
+const REGION_MAGIC: &[u8; 4] = b"MCRS";
+/// Current layout version of the region files.
+pub const REGION_VERSION: u16 = 1;
+
+const HEADER_SIZE: u64 = 8;
+const TABLE_ENTRY_SIZE: u64 = 8;
+const TABLE_SIZE: u64 = (REGION_SIZE * REGION_SIZE * REGION_SIZE) as u64 * TABLE_ENTRY_SIZE;
+
+/// Region files are only compacted once they have this many unused bytes.
+const MIN_COMPACT_SIZE: u64 = 1 << 20;
+
+/// Format tag of chunks saved as a list of positions and block ids, newer
+/// chunks are saved with the [encoding] module and start with its version.
+const CHUNK_FORMAT_SPARSE: u8 = 1;
+
+/// Reads and writes chunk data to region files in a directory.
+/// File access is serialized so chunks can be loaded and saved from the thread
+/// pool at the same time.
+pub struct RegionStorage {
+    directory: PathBuf,
+    lock: Mutex<()>,
+    /// Chunks queued to be saved, loaded from here until they are written.
+    pending: Mutex<HashMap<ChunkPos, ChunkData>>,
+    /// Chunks that couldn't be read, they are only copied to the `corrupt`
+    /// folder the first time.
+    unreadable: Mutex<HashSet<ChunkPos>>,
+    /// Chunks that couldn't be read or copied, they are never saved over.
+    uncopied: Mutex<HashSet<ChunkPos>>,
+}
+
+impl RegionStorage {
+    /// Create a storage backed by `directory`. The directory is created when
+    /// the first chunk is saved.
+    pub fn new<P: AsRef<Path>>(directory: P) -> Self {
+        Self {
+            directory: directory.as_ref().to_path_buf(),
+            lock: Mutex::new(()),
+            pending: Mutex::new(HashMap::new()),
+            unreadable: Mutex::new(HashSet::new()),
+            uncopied: Mutex::new(HashSet::new()),
+        }
+    }
+
+    pub fn directory(&self) -> &Path {
+        &self.directory
+    }
+
+    /// Load the chunk at `chunk_pos`, returns `None` if it has never been saved.
+    /// Only the inside of the chunk is saved, so the chunk has no generation border.
+    /// A chunk that can't be decoded is copied to the `corrupt` folder the
+    /// first time, so saving the chunk again doesn't lose it.
+    pub fn load(
+        &self,
+        config: &ChunkConfig,
+        chunk_pos: &ChunkPos,
+    ) -> anyhow::Result<Option<ChunkData>> {
+        let _guard = self.lock.lock().unwrap();
+        if let Some(chunk) = self.pending.lock().unwrap().get(chunk_pos) {
+            return Ok(Some(chunk.clone()));
+        }
+
+        let (region, slot) = region_position(chunk_pos);
+        let path = self.region_path(&region);
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let mut file = File::open(path)?;
+        read_header(&mut file)?;
+
+        let Some(bytes) = read_slot(&mut file, slot)? else {
+            return Ok(None);
+        };
+        decode_chunk(config, &bytes).map(Some).inspect_err(|_| {
+            if !self.unreadable.lock().unwrap().insert(*chunk_pos) {
+                return;
+            }
+            match self.quarantine(chunk_pos, &bytes) {
+                Ok(path) => log::warn!(
+                    "Chunk {} can't be read, its data is kept in {}",
+                    chunk_pos,
+                    path.display()
+                ),
+                Err(copy_error) => {
+                    log::error!(
+                        "Chunk {} can't be read or copied, it won't be saved over: {:#}",
+                        chunk_pos,
+                        copy_error
+                    );
+                    self.uncopied.lock().unwrap().insert(*chunk_pos);
+                }
+            }
+        })
+    }
+
+    /// Copy the data of a chunk that can't be read to the `corrupt` folder,
+    /// unless a copy from an earlier game is already there.
+    fn quarantine(&self, chunk_pos: &ChunkPos, bytes: &[u8]) -> anyhow::Result<PathBuf> {
+        let directory = self.directory.join("corrupt");
+        std::fs::create_dir_all(&directory)?;
+        let path = directory.join(format!(
+            "chunk.{}.{}.{}.bin",
+            chunk_pos.x, chunk_pos.y, chunk_pos.z
+        ));
+        if !path.exists() {
+            std::fs::write(&path, bytes)?;
+        }
+        Ok(path)
+    }
+
+    /// Queue the chunk at `chunk_pos` to be saved by [`RegionStorage::flush_chunk`],
+    /// it's loaded from the queue until then.
+    pub fn queue_save(&self, chunk_pos: ChunkPos, chunk: ChunkData) {
+        self.pending.lock().unwrap().insert(chunk_pos, chunk);
+    }
+
+    /// Save the chunk queued at `chunk_pos`, if it's still queued.
+    pub fn flush_chunk(&self, config: &ChunkConfig, chunk_pos: &ChunkPos) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        // taken out of the queue while the files are locked, so it can't be
+        // loaded from the region file before it's written
+        let Some(chunk) = self.pending.lock().unwrap().remove(chunk_pos) else {
+            return Ok(());
+        };
+        self.write(config, chunk_pos, &chunk)
+    }
+
+    /// Save every queued chunk, used before the game exits.
+    pub fn flush(&self, config: &ChunkConfig) -> anyhow::Result<()> {
+        let queued: Vec<ChunkPos> = self.pending.lock().unwrap().keys().copied().collect();
+        for chunk_pos in queued {
+            self.flush_chunk(config, &chunk_pos)?;
+        }
+        Ok(())
+    }
+
+    /// Save the chunk at `chunk_pos`, replacing any previously saved data.
+    pub fn save(
+        &self,
+        config: &ChunkConfig,
+        chunk_pos: &ChunkPos,
+        chunk: &ChunkData,
+    ) -> anyhow::Result<()> {
+        let _guard = self.lock.lock().unwrap();
+        self.pending.lock().unwrap().remove(chunk_pos);
+        self.write(config, chunk_pos, chunk)
+    }
+
+    /// Write the chunk to its region file, the files have to be locked.
+    fn write(
+        &self,
+        config: &ChunkConfig,
+        chunk_pos: &ChunkPos,
+        chunk: &ChunkData,
+    ) -> anyhow::Result<()> {
+        if self.uncopied.lock().unwrap().contains(chunk_pos) {
+            bail!(
+                "Chunk {} couldn't be read or copied, so it isn't saved over",
+                chunk_pos
+            );
+        }
+
+        let (region, slot) = region_position(chunk_pos);
+        let path = self.region_path(&region);
+
+        let mut file = if path.exists() {
+            let mut file = OpenOptions::new().read(true).write(true).open(&path)?;
+            read_header(&mut file)?;
+            file
+        } else {
+            std::fs::create_dir_all(&self.directory)?;
+            let mut file = OpenOptions::new()
+                .read(true)
+                .write(true)
+                .create_new(true)
+                .open(&path)?;
+            write_header(&mut file)?;
+            file
+        };
+
+        // the old data is kept until the table points at the new data
+        let bytes = encoding::encode_chunk(config, chunk);
+        let table = read_table(&mut file)?;
+        let offset = free_offset(&table, bytes.len() as u64);
+        let entry = table_entry(offset, bytes.len())?;
+
+        file.seek(SeekFrom::Start(offset))?;
+        file.write_all(&bytes)?;
+        file.sync_data()?;
+        file.seek(SeekFrom::Start(HEADER_SIZE + slot * TABLE_ENTRY_SIZE))?;
+        file.write_all(&entry)?;
+        file.flush()?;
+
+        let used: u64 = read_table(&mut file)?
+            .iter()
+            .map(|(_, length)| length)
+            .sum();
+        let unused = file.metadata()?.len() - HEADER_SIZE - TABLE_SIZE - used;
+        if unused > used && unused > MIN_COMPACT_SIZE {
+            drop(file);
+            compact(&path).with_context(|| format!("Failed to compact {}", path.display()))?;
+        }
+
+        Ok(())
+    }
+
+    fn region_path(&self, region: &Position) -> PathBuf {
+        self.directory.join(format!(
+            "region.{}.{}.{}.mcrs",
+            region.0, region.1, region.2
+        ))
+    }
+}
+
+/// Get the region a chunk belongs to, and the chunk's slot in that region.
+pub fn region_position(chunk_pos: &ChunkPos) -> (Position, u64) {
+    let region = (
+        chunk_pos.x.div_euclid(REGION_SIZE),
+        chunk_pos.y.div_euclid(REGION_SIZE),
+        chunk_pos.z.div_euclid(REGION_SIZE),
+    );
+    let local = (
+        chunk_pos.x.rem_euclid(REGION_SIZE),
+        chunk_pos.y.rem_euclid(REGION_SIZE),
+        chunk_pos.z.rem_euclid(REGION_SIZE),
+    );
+    let slot = local.0 + REGION_SIZE * (local.1 + REGION_SIZE * local.2);
+
+    (region, slot as u64)
+}
+
+fn write_header(file: &mut File) -> anyhow::Result<()> {
+    file.seek(SeekFrom::Start(0))?;
+    file.write_all(REGION_MAGIC)?;
+    file.write_all(&REGION_VERSION.to_le_bytes())?;
+    file.write_all(&[0; 2])?;
+    file.write_all(&vec![0; TABLE_SIZE as usize])?;
+    Ok(())
+}
+
+fn read_header(file: &mut File) -> anyhow::Result<()> {
+    let mut header = [0; HEADER_SIZE as usize];
+    file.seek(SeekFrom::Start(0))?;
+    file.read_exact(&mut header)?;
+
+    if &header[0..4] != REGION_MAGIC {
+        bail!("Region file has an invalid header.");
+    }
+    let version = u16::from_le_bytes([header[4], header[5]]);
+    if version != REGION_VERSION {
+        bail!(
+            "Region file version {} is not supported, expected {}.",
+            version,
+            REGION_VERSION
+        );
+    }
+
+    Ok(())
+}
+
+/// Read the offset and length of a slot in the table, the offset is 0 for slots
+/// that were never saved.
+fn read_table_entry(file: &mut File, slot: u64) -> anyhow::Result<(u64, u64)> {
+    file.seek(SeekFrom::Start(HEADER_SIZE + slot * TABLE_ENTRY_SIZE))?;
+    let offset = read_u32(file)? as u64;
+    let length = read_u32(file)? as u64;
+    Ok((offset, length))
+}
+
+/// Read the offset and length of every slot of the table.
+fn read_table(file: &mut File) -> anyhow::Result<Vec<(u64, u64)>> {
+    let mut table = vec![0; TABLE_SIZE as usize];
+    file.seek(SeekFrom::Start(HEADER_SIZE))?;
+    file.read_exact(&mut table)?;
+    Ok(table
+        .chunks(TABLE_ENTRY_SIZE as usize)
+        .map(|entry| {
+            let offset = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let length = u32::from_le_bytes(entry[4..8].try_into().unwrap());
+            (offset as u64, length as u64)
+        })
+        .collect())
+}
+
+/// Read the data saved in a slot, `None` if the slot was never saved.
+fn read_slot(file: &mut File, slot: u64) -> anyhow::Result<Option<Vec<u8>>> {
+    let (offset, length) = read_table_entry(file, slot)?;
+    if offset == 0 {
+        return Ok(None);
+    }
+    let mut bytes = vec![0; length as usize];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut bytes)?;
+    Ok(Some(bytes))
+}
+
+/// Get the offset of the first space between the chunks of the table that
+/// fits the length, or of the end of the last chunk.
+fn free_offset(table: &[(u64, u64)], length: u64) -> u64 {
+    let mut used: Vec<(u64, u64)> = table
+        .iter()
+        .filter(|(offset, _)| *offset != 0)
+        .copied()
+        .collect();
+    used.sort_unstable();
+
+    let mut free = HEADER_SIZE + TABLE_SIZE;
+    for (offset, used_length) in used {
+        if offset >= free + length {
+            break;
+        }
+        free = free.max(offset + used_length);
+    }
+    free
+}
+
+/// Get the table entry of data written at the offset, the offset and length
+/// are stored as 32 bit numbers.
+fn table_entry(offset: u64, length: usize) -> anyhow::Result<[u8; TABLE_ENTRY_SIZE as usize]> {
+    let (Ok(offset), Ok(length)) = (u32::try_from(offset), u32::try_from(length)) else {
+        bail!("The region file is full, chunks can't be saved past 4 GiB");
+    };
+    let mut entry = [0; TABLE_ENTRY_SIZE as usize];
+    entry[0..4].copy_from_slice(&offset.to_le_bytes());
+    entry[4..8].copy_from_slice(&length.to_le_bytes());
+    Ok(entry)
+}
+
+/// Write the chunks of a region file one after another to a new file without
+/// unused space, and replace the region file with it.
+fn compact(path: &Path) -> anyhow::Result<()> {
+    let mut file = File::open(path)?;
+    read_header(&mut file)?;
+    let compacted_path = path.with_extension("mcrs.tmp");
+    let mut compacted = OpenOptions::new()
+        .read(true)
+        .write(true)
+        .create(true)
+        .truncate(true)
+        .open(&compacted_path)?;
+    write_header(&mut compacted)?;
+
+    let mut offset = HEADER_SIZE + TABLE_SIZE;
+    for slot in 0..TABLE_SIZE / TABLE_ENTRY_SIZE {
+        let Some(bytes) = read_slot(&mut file, slot)? else {
+            continue;
+        };
+        compacted.seek(SeekFrom::Start(offset))?;
+        compacted.write_all(&bytes)?;
+        compacted.seek(SeekFrom::Start(HEADER_SIZE + slot * TABLE_ENTRY_SIZE))?;
+        compacted.write_all(&table_entry(offset, bytes.len())?)?;
+        offset += bytes.len() as u64;
+    }
+    compacted.sync_all()?;
+    drop(file);
+    std::fs::rename(&compacted_path, path)?;
+    Ok(())
+}
+
+fn read_u32(reader: &mut impl Read) -> anyhow::Result<u32> {
+    let mut bytes = [0; 4];
+    reader.read_exact(&mut bytes)?;
+    Ok(u32::from_le_bytes(bytes))
+}
+
+/// Decode a chunk's data, picking the decoder from the format tag at the start
+/// of the data. The returned chunk has no generation border.
+pub fn decode_chunk(config: &ChunkConfig, bytes: &[u8]) -> anyhow::Result<ChunkData> {
+    match bytes.first() {
+        Some(&CHUNK_FORMAT_SPARSE) => decode_sparse_chunk(config, bytes),
+        Some(&ENCODING_VERSION) => encoding::decode_chunk(config, bytes),
+        Some(format) => bail!("Unknown chunk format {}.", format),
+        None => bail!("Chunk data is empty."),
+    }
+}
+
+/// Decode the list of positions and block ids that chunks were first saved as.
+fn decode_sparse_chunk(config: &ChunkConfig, bytes: &[u8]) -> anyhow::Result<ChunkData> {
+    let mut reader = &bytes[1..];
+
+    let depth = read_u32(&mut reader)? as i32;
+    if depth != config.depth {
+        bail!(
+            "Chunk was saved with depth {}, but the world uses depth {}.",
+            depth,
+            config.depth
+        );
+    }
+
+    let count = read_u32(&mut reader)? as usize;
+    let mut output = ChunkData::new(depth);
+    for _ in 0..count {
+        let mut entry = [0; 10];
+        reader.read_exact(&mut entry)?;
+        let x = i16::from_le_bytes([entry[0], entry[1]]) as i32;
+        let y = i16::from_le_bytes([entry[2], entry[3]]) as i32;
+        let z = i16::from_le_bytes([entry[4], entry[5]]) as i32;
+        let block_id = u32::from_le_bytes([entry[6], entry[7], entry[8], entry[9]]);
+
+        // the border used to be saved too
+        if should_mesh_block(config, &(x, y, z)) {
+            output.set(&(x, y, z), block_id);
+        }
+    }
+
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::generation::{ao_test, generate, load_chunk};
+    use crate::util::test_directory;
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 8, 3)
+    }
+
+    /// The blocks of the chunk that are saved, ie. without the border.
+    fn inside(config: &ChunkConfig, chunk: &ChunkData) -> ChunkData {
+        assert_eq!(chunk.depth(), config.depth);
+        let mut inside = chunk.clone();
+        inside.clear_border();
+        inside
+    }
+
+    /// Encode the chunk the way chunks were first saved.
+    fn encode_sparse_chunk(config: &ChunkConfig, chunk: &ChunkData) -> Vec<u8> {
+        let mut output = vec![CHUNK_FORMAT_SPARSE];
+        output.extend_from_slice(&(config.depth as u32).to_le_bytes());
+        output.extend_from_slice(&(chunk.iter().count() as u32).to_le_bytes());
+        for ((x, y, z), block_id) in chunk.iter() {
+            output.extend_from_slice(&(x as i16).to_le_bytes());
+            output.extend_from_slice(&(y as i16).to_le_bytes());
+            output.extend_from_slice(&(z as i16).to_le_bytes());
+            output.extend_from_slice(&block_id.to_le_bytes());
+        }
+        output
+    }
+
+    #[test]
+    fn region_position_test() {
+        assert_eq!(region_position(&ChunkPos::new(0, 0, 0)), ((0, 0, 0), 0));
+        assert_eq!(region_position(&ChunkPos::new(15, 0, 0)), ((0, 0, 0), 15));
+        assert_eq!(region_position(&ChunkPos::new(0, 1, 0)), ((0, 0, 0), 16));
+        assert_eq!(region_position(&ChunkPos::new(0, 0, 1)), ((0, 0, 0), 256));
+        assert_eq!(region_position(&ChunkPos::new(-1, 16, 0)), ((-1, 1, 0), 15));
+    }
+
+    #[test]
+    fn decode_sparse_chunk_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+
+        let chunk = generate(&config, &ChunkPos::new(0, -1, 0));
+        let decoded = decode_chunk(&config, &encode_sparse_chunk(&config, &chunk))?;
+        assert_eq!(decoded, inside(&config, &chunk));
+
+        let other_config = ChunkConfig::new(10, 16, 3);
+        let bytes = encode_sparse_chunk(&config, &chunk);
+        assert!(decode_chunk(&other_config, &bytes).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn decode_unknown_format_test() {
+        let config = create_mock_config();
+        assert!(decode_chunk(&config, &[]).is_err());
+        assert!(decode_chunk(&config, &[0xFF, 0, 0]).is_err());
+    }
+
+    #[test]
+    fn region_round_trip_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+        let directory = test_directory("region-round-trip");
+        let storage = RegionStorage::new(&directory);
+
+        assert!(storage.load(&config, &ChunkPos::new(0, 0, 0))?.is_none());
+
+        let positions = [
+            ChunkPos::new(0, 0, 0),
+            ChunkPos::new(1, -1, 0),
+            ChunkPos::new(-17, 3, 40),
+        ];
+        for pos in positions.iter() {
+            storage.save(&config, pos, &generate(&config, pos))?;
+        }
+        for pos in positions.iter() {
+            let chunk = generate(&config, pos);
+            assert_eq!(storage.load(&config, pos)?, Some(inside(&config, &chunk)));
+        }
+
+        // a chunk in a saved region that was never written
+        assert!(storage.load(&config, &ChunkPos::new(2, 0, 0))?.is_none());
+
+        // saving again replaces the old data
+        let chunk = ao_test(config.depth);
+        storage.save(&config, &ChunkPos::new(0, 0, 0), &chunk)?;
+        assert_eq!(
+            storage.load(&config, &ChunkPos::new(0, 0, 0))?,
+            Some(inside(&config, &chunk))
+        );
+
+        // the border is generated again when loading
+        let (loaded, generated) = load_chunk(&config, &storage, &ChunkPos::new(1, -1, 0));
+        assert!(!generated);
+        assert_eq!(loaded, generate(&config, &ChunkPos::new(1, -1, 0)));
+
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn region_corrupt_chunk_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+        let directory = test_directory("region-corrupt-chunk");
+        let storage = RegionStorage::new(&directory);
+        let pos = ChunkPos::new(0, 0, 0);
+        storage.save(&config, &pos, &ao_test(config.depth))?;
+
+        // a chunk saved with another depth can't be read
+        let other_config = ChunkConfig::new(10, 16, 3);
+        let original = std::fs::read(storage.region_path(&(0, 0, 0)))?;
+        assert!(storage.load(&other_config, &pos).is_err());
+
+        // it's generated, but not saved over unless it's edited, and its data
+        // is kept in the corrupt folder
+        let (_, generated) = load_chunk(&other_config, &storage, &pos);
+        assert!(!generated);
+        let copy_path = directory.join("corrupt/chunk.0.0.0.bin");
+        let copy = std::fs::read(&copy_path)?;
+        assert_eq!(
+            decode_chunk(&config, &copy)?,
+            inside(&config, &ao_test(config.depth))
+        );
+        assert_eq!(std::fs::read(storage.region_path(&(0, 0, 0)))?, original);
+
+        // it's only copied once, also when the game is started again
+        std::fs::write(&copy_path, b"first copy")?;
+        assert!(storage.load(&other_config, &pos).is_err());
+        assert!(RegionStorage::new(&directory)
+            .load(&other_config, &pos)
+            .is_err());
+        assert_eq!(std::fs::read(&copy_path)?, b"first copy");
+        assert_eq!(std::fs::read_dir(directory.join("corrupt"))?.count(), 1);
+
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn region_queued_save_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+        let directory = test_directory("region-queued-save");
+        let storage = RegionStorage::new(&directory);
+        let pos = ChunkPos::new(3, 0, 0);
+        let chunk = inside(&config, &generate(&config, &pos));
+
+        // loaded from the queue before it's written
+        storage.queue_save(pos, chunk.clone());
+        assert!(!directory.exists());
+        assert_eq!(storage.load(&config, &pos)?, Some(chunk.clone()));
+
+        storage.flush(&config)?;
+        assert!(storage.pending.lock().unwrap().is_empty());
+        assert_eq!(storage.load(&config, &pos)?, Some(chunk));
+        // already written
+        storage.flush_chunk(&config, &pos)?;
+
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn region_reuse_test() -> anyhow::Result<()> {
+        let config = ChunkConfig::new(10, 32, 3);
+        let directory = test_directory("region-reuse");
+        let storage = RegionStorage::new(&directory);
+        let path = storage.region_path(&(0, -1, 0));
+        let positions = [ChunkPos::new(0, -1, 0), ChunkPos::new(1, -1, 0)];
+
+        // saving the same chunks again keeps their old data until the table
+        // points at the new data, after that its space is used again
+        for pos in positions.iter() {
+            storage.save(&config, pos, &generate(&config, pos))?;
+        }
+        let size = std::fs::metadata(&path)?.len();
+        let (_, slot) = region_position(&positions[0]);
+        let old_entry = read_table_entry(&mut File::open(&path)?, slot)?;
+        let old_bytes = std::fs::read(&path)?;
+        storage.save(&config, &positions[0], &generate(&config, &positions[0]))?;
+        let new_entry = read_table_entry(&mut File::open(&path)?, slot)?;
+        assert_ne!(new_entry.0, old_entry.0);
+        let old_data = (old_entry.0 as usize)..(old_entry.0 + old_entry.1) as usize;
+        assert_eq!(std::fs::read(&path)?[old_data.clone()], old_bytes[old_data]);
+        storage.save(&config, &positions[1], &generate(&config, &positions[1]))?;
+        let resaved_size = std::fs::metadata(&path)?.len();
+        assert!(resaved_size > size);
+        for pos in positions.iter() {
+            storage.save(&config, pos, &generate(&config, pos))?;
+        }
+        assert_eq!(std::fs::metadata(&path)?.len(), resaved_size);
+
+        // chunks that don't fit in the space of old copies are appended, and
+        // compacting the file takes out the unused space
+        let mut chunk = generate(&config, &positions[0]);
+        for i in 0..50 {
+            chunk.set(&(i % 32, i / 32, 5), (i % 7 + 1) as u32);
+            storage.save(&config, &positions[0], &chunk)?;
+        }
+        let appended_size = std::fs::metadata(&path)?.len();
+        assert!(appended_size > resaved_size);
+        compact(&path)?;
+        let compacted_size = std::fs::metadata(&path)?.len();
+        let used: u64 = read_table(&mut File::open(&path)?)?
+            .iter()
+            .map(|(_, length)| length)
+            .sum();
+        assert!(compacted_size < appended_size);
+        assert_eq!(compacted_size, HEADER_SIZE + TABLE_SIZE + used);
+        assert_eq!(
+            storage.load(&config, &positions[0])?,
+            Some(inside(&config, &chunk))
+        );
+        assert_eq!(
+            storage.load(&config, &positions[1])?,
+            Some(inside(&config, &generate(&config, &positions[1])))
+        );
+
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[test]
+    fn table_entry_test() {
+        assert_eq!(table_entry(8, 3).unwrap(), [8, 0, 0, 0, 3, 0, 0, 0]);
+        assert!(table_entry(u32::MAX as u64, 3).is_ok());
+        assert!(table_entry(u32::MAX as u64 + 1, 3).is_err());
+    }
+
+    #[test]
+    fn region_invalid_header_test() -> anyhow::Result<()> {
+        let config = create_mock_config();
+        let directory = test_directory("region-invalid-header");
+        let storage = RegionStorage::new(&directory);
+
+        storage.save(&config, &ChunkPos::new(0, 0, 0), &ao_test(config.depth))?;
+
+        // overwrite the version in the header
+        let path = storage.region_path(&(0, 0, 0));
+        let mut bytes = std::fs::read(&path)?;
+        bytes[4] = 0xFF;
+        std::fs::write(&path, bytes)?;
+
+        assert!(storage.load(&config, &ChunkPos::new(0, 0, 0)).is_err());
+        assert!(storage
+            .save(&config, &ChunkPos::new(0, 0, 0), &ao_test(config.depth))
+            .is_err());
+
+        std::fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,397 @@
+//! Structures on top of the ground, like trees, boulders and ruins, made from
+//! templates in the structures file.
+//!
+//! Structures are placed in cells of `STRUCTURE_CELL` columns, seeded from the
+//! cell's position, and a chunk writes the part of every structure that
+//! overlaps it. So a tree crossing a chunk border comes out the same on both
+//! sides, whichever chunk is generated first.
+
+use std::collections::{HashMap, HashSet};
+
+use anyhow::{bail, Context};
+use indexmap::IndexMap;
+use serde::Deserialize;
+
+use super::biome::{terrain_column, Biome};
+use super::block::BlockDictionary;
+use super::generation::{
+    global_position, ground_height, GenerationArea, GenerationPass, SEA_LEVEL,
+};
+use super::random::WorldRandom;
+use super::{ChunkConfig, ChunkData, ChunkPos, Position};
+use crate::engine::resources::load_string;
+
+/// The file the structure templates are loaded from, in the assets folder.
+pub const STRUCTURES_FILE: &str = "structures.ron";
+
+/// Tells the random numbers of structures apart from other features.
+const STRUCTURE_SALT: u64 = 200;
+/// Size of the cells structures are placed in, in columns.
+const STRUCTURE_CELL: i32 = 16;
+
+/// Part of a template as it is written in the structures file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub enum TemplatePart {
+    /// A single block.
+    Block(Position, String),
+    /// Every block between two corners.
+    Fill(Position, Position, String),
+}
+
+/// A structure as it is written in the structures file.
+#[derive(Clone, Debug, Deserialize, PartialEq)]
+pub struct TemplateDefinition {
+    pub name: String,
+    pub biomes: Vec<Biome>,
+    /// Chance of a cell having the structure.
+    pub chance: f64,
+    /// Later parts replace earlier ones, air leaves the world as it is.
+    pub blocks: Vec<TemplatePart>,
+}
+
+/// A structure that can be placed in the world.
+#[derive(Clone, Debug, PartialEq)]
+pub struct StructureTemplate {
+    pub name: String,
+    pub biomes: Vec<Biome>,
+    pub chance: f64,
+    /// Every block of the structure, relative to the block above the ground
+    /// it's placed on.
+    pub blocks: Vec<(Position, u32)>,
+}
+
+impl StructureTemplate {
+    /// Farthest a block of the structure is from its origin, horizontally.
+    pub fn reach(&self) -> i32 {
+        self.blocks
+            .iter()
+            .map(|(offset, _)| offset.0.abs().max(offset.2.abs()))
+            .max()
+            .unwrap_or(0)
+    }
+}
+
+/// Check the definitions and turn them into templates, with the blocks looked
+/// up by their ident.
+pub fn build_structures(
+    definitions: Vec<TemplateDefinition>,
+    dict: &BlockDictionary,
+) -> anyhow::Result<Vec<StructureTemplate>> {
+    let ids: HashMap<&str, u32> = dict
+        .iter()
+        .map(|(id, block)| (block.ident.as_str(), *id))
+        .collect();
+
+    let mut names = HashSet::new();
+    let mut templates = Vec::new();
+    for definition in definitions {
+        let name = &definition.name;
+        if !names.insert(name.clone()) {
+            bail!("Structure {} is defined more than once", name);
+        }
+        if definition.biomes.is_empty() {
+            bail!("Structure {} isn't placed in any biome", name);
+        }
+        if !(0.0..=1.0).contains(&definition.chance) {
+            bail!(
+                "Structure {} has chance {}, it has to be from 0 to 1",
+                name,
+                definition.chance
+            );
+        }
+
+        let mut blocks = IndexMap::new();
+        for part in definition.blocks.iter() {
+            let (from, to, ident) = match part {
+                TemplatePart::Block(position, ident) => (*position, *position, ident),
+                TemplatePart::Fill(from, to, ident) => (*from, *to, ident),
+            };
+            let Some(block_id) = ids.get(ident.as_str()) else {
+                bail!("Structure {} uses unknown block {}", name, ident);
+            };
+
+            for x in from.0.min(to.0)..=from.0.max(to.0) {
+                for y in from.1.min(to.1)..=from.1.max(to.1) {
+                    for z in from.2.min(to.2)..=from.2.max(to.2) {
+                        blocks.insert((x, y, z), *block_id);
+                    }
+                }
+            }
+        }
+
+        templates.push(StructureTemplate {
+            name: definition.name,
+            biomes: definition.biomes,
+            chance: definition.chance,
+            blocks: blocks
+                .into_iter()
+                .filter(|(_, block_id)| *block_id != 0)
+                .collect(),
+        });
+    }
+
+    Ok(templates)
+}
+
+/// Parse the contents of a structures file into templates.
+pub fn parse_structures(
+    source: &str,
+    dict: &BlockDictionary,
+) -> anyhow::Result<Vec<StructureTemplate>> {
+    let definitions: Vec<TemplateDefinition> = ron::from_str(source)?;
+    build_structures(definitions, dict)
+}
+
+/// Load the templates from the structures file in the assets folder.
+pub async fn load_structures(dict: &BlockDictionary) -> anyhow::Result<Vec<StructureTemplate>> {
+    let source = load_string(STRUCTURES_FILE, true).await?;
+    parse_structures(&source, dict)
+        .with_context(|| format!("Failed to load structures from {}", STRUCTURES_FILE))
+}
+
+/// A structure placed in the world.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PlacedStructure {
+    /// Index of the structure's template.
+    pub template: usize,
+    /// World space position of the block above the ground it's placed on.
+    pub origin: Position,
+}
+
+/// Get the structure placed in a cell, if it has one. Cells are world space x
+/// and z divided by `STRUCTURE_CELL`.
+pub fn cell_structure(
+    config: &ChunkConfig,
+    templates: &[StructureTemplate],
+    cell: (i32, i32),
+) -> Option<PlacedStructure> {
+    let mut random = WorldRandom::new(config.settings.seed, STRUCTURE_SALT, &(cell.0, 0, cell.1));
+    let x = cell.0 * STRUCTURE_CELL + random.range_i32(0, STRUCTURE_CELL);
+    let z = cell.1 * STRUCTURE_CELL + random.range_i32(0, STRUCTURE_CELL);
+
+    let column = terrain_column(config, x, z);
+    let template = templates.iter().position(|template| {
+        template.biomes.contains(&column.biome) && random.next_f64() < template.chance
+    })?;
+
+    // nothing is placed under water
+    let ground = ground_height(config, &column, x, z).filter(|y| *y >= SEA_LEVEL)?;
+    Some(PlacedStructure {
+        template,
+        origin: (x, ground + 1, z),
+    })
+}
+
+/// Places structures on top of the ground, they only replace air.
+pub struct StructurePass {
+    templates: Vec<StructureTemplate>,
+    /// Farthest a block of any structure is from its origin, horizontally.
+    reach: i32,
+}
+
+impl StructurePass {
+    pub fn new(templates: Vec<StructureTemplate>) -> Self {
+        let reach = templates
+            .iter()
+            .map(StructureTemplate::reach)
+            .max()
+            .unwrap_or(0);
+        Self { templates, reach }
+    }
+}
+
+impl GenerationPass for StructurePass {
+    fn name(&self) -> &'static str {
+        "structures"
+    }
+
+    fn generate(
+        &self,
+        config: &ChunkConfig,
+        pos: &ChunkPos,
+        area: GenerationArea,
+        output: &mut ChunkData,
+    ) {
+        let cells = |chunk: i32| {
+            let min = chunk * config.depth - 1 - self.reach;
+            let max = chunk * config.depth + config.depth + self.reach;
+            min.div_euclid(STRUCTURE_CELL)..=max.div_euclid(STRUCTURE_CELL)
+        };
+
+        // every chunk goes through the cells in the same order, so where two
+        // structures overlap the same one is placed first in every chunk
+        let origin = global_position(config, pos, &(0, 0, 0));
+        let range = -1..=config.depth;
+        for cell_x in cells(pos.x) {
+            for cell_z in cells(pos.z) {
+                let Some(placed) = cell_structure(config, &self.templates, (cell_x, cell_z)) else {
+                    continue;
+                };
+
+                for (offset, block_id) in self.templates[placed.template].blocks.iter() {
+                    let position = (
+                        placed.origin.0 + offset.0 - origin[0],
+                        placed.origin.1 + offset.1 - origin[1],
+                        placed.origin.2 + offset.2 - origin[2],
+                    );
+                    let inside = range.contains(&position.0)
+                        && range.contains(&position.1)
+                        && range.contains(&position.2);
+                    if inside && area.contains(config, &position) && output.get(&position) == 0 {
+                        output.set(&position, *block_id);
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::generation::{generate, get_terrain_at};
+    use crate::chunk::{chunk_position, local_position};
+
+    fn create_mock_config() -> ChunkConfig {
+        ChunkConfig::new(10, 16, 3)
+    }
+
+    fn templates(config: &ChunkConfig) -> Vec<StructureTemplate> {
+        parse_structures(include_str!("../../assets/structures.ron"), &config.dict).unwrap()
+    }
+
+    #[test]
+    fn structures_file_test() {
+        let config = create_mock_config();
+        let templates = templates(&config);
+        let names: Vec<&str> = templates.iter().map(|t| t.name.as_str()).collect();
+        assert_eq!(names, ["tree", "boulder", "ruin"]);
+
+        // the trunk replaces the leaves, and the cut out corners are left out
+        let tree = &templates[0];
+        let block = |position: Position| {
+            tree.blocks
+                .iter()
+                .find(|(offset, _)| *offset == position)
+                .map(|(_, block_id)| config.dict[block_id].ident.as_str())
+        };
+        assert_eq!(block((0, 0, 0)), Some("log"));
+        assert_eq!(block((0, 4, 0)), Some("log"));
+        assert_eq!(block((1, 4, 0)), Some("leaves"));
+        assert_eq!(block((2, 4, 2)), None);
+        assert_eq!(tree.reach(), 2);
+        assert!(templates
+            .iter()
+            .all(|t| t.blocks.iter().all(|(_, id)| *id != 0)));
+    }
+
+    #[test]
+    fn structures_error_test() {
+        let config = create_mock_config();
+        let parse = |source: &str| {
+            parse_structures(source, &config.dict)
+                .unwrap_err()
+                .to_string()
+        };
+
+        let error = parse(
+            r#"[(name: "a", biomes: [Plains], chance: 0.5, blocks: [Block((0, 0, 0), "nothing")])]"#,
+        );
+        assert!(error.contains("unknown block nothing"), "{}", error);
+        let error = parse(r#"[(name: "a", biomes: [Plains], chance: 2.0, blocks: [])]"#);
+        assert!(error.contains("chance 2"), "{}", error);
+        let error = parse(r#"[(name: "a", biomes: [], chance: 0.5, blocks: [])]"#);
+        assert!(error.contains("any biome"), "{}", error);
+        let error = parse(
+            r#"[(name: "a", biomes: [Desert], chance: 0.5, blocks: []), (name: "a", biomes: [Desert], chance: 0.5, blocks: [])]"#,
+        );
+        assert!(error.contains("more than once"), "{}", error);
+    }
+
+    #[test]
+    fn cell_structure_test() {
+        let config = create_mock_config();
+        let templates = templates(&config);
+
+        let mut placed = Vec::new();
+        for x in -20..20 {
+            for z in -20..20 {
+                if let Some(structure) = cell_structure(&config, &templates, (x, z)) {
+                    assert_eq!(cell_structure(&config, &templates, (x, z)), Some(structure));
+                    placed.push(structure);
+                }
+            }
+        }
+        assert!(placed.iter().any(|structure| structure.template == 0));
+
+        // on top of the ground, in a biome the structure is placed in
+        for structure in placed {
+            let (x, y, z) = structure.origin;
+            assert!(get_terrain_at(&config, [x, y - 1, z]) < 0.0);
+            assert!(get_terrain_at(&config, [x, y, z]) >= 0.0);
+            assert!(y > SEA_LEVEL);
+            let biome = terrain_column(&config, x, z).biome;
+            assert!(templates[structure.template].biomes.contains(&biome));
+        }
+    }
+
+    #[test]
+    fn structure_order_test() {
+        let config = create_mock_config();
+        let templates = templates(&config);
+
+        // find a tree crossing a chunk border
+        let (tree, chunks) = (-20..20)
+            .flat_map(|x| (-20..20).map(move |z| (x, z)))
+            .filter_map(|cell| cell_structure(&config, &templates, cell))
+            .filter(|structure| structure.template == 0)
+            .find_map(|structure| {
+                let mut chunks: Vec<ChunkPos> = templates[0]
+                    .blocks
+                    .iter()
+                    .map(|(offset, _)| {
+                        let (x, y, z) = structure.origin;
+                        chunk_position(&config, &(x + offset.0, y + offset.1, z + offset.2))
+                    })
+                    .collect();
+                chunks.sort();
+                chunks.dedup();
+                (chunks.len() > 1).then_some((structure, chunks))
+            })
+            .unwrap();
+
+        // generate the chunks it's in forwards and backwards
+        let forwards: Vec<ChunkData> = chunks.iter().map(|pos| generate(&config, pos)).collect();
+        let mut backwards: Vec<ChunkData> = chunks
+            .iter()
+            .rev()
+            .map(|pos| generate(&config, pos))
+            .collect();
+        backwards.reverse();
+        assert_eq!(forwards, backwards);
+
+        // the trunk is in the world, in whichever chunk, and every chunk's
+        // border matches the chunks around it
+        let block_at = |position: &Position| {
+            let index = chunks
+                .iter()
+                .position(|pos| *pos == chunk_position(&config, position))
+                .unwrap();
+            forwards[index].get(&local_position(&config, position))
+        };
+        let log = templates[0].blocks.last().unwrap().1;
+        for y in 0..6 {
+            let (x, origin_y, z) = tree.origin;
+            assert_eq!(block_at(&(x, origin_y + y, z)), log);
+        }
+        for (pos, chunk) in chunks.iter().zip(forwards.iter()) {
+            for position in GenerationArea::Border.positions(&config) {
+                let global = global_position(&config, pos, &position);
+                let global = (global[0], global[1], global[2]);
+                if chunks.contains(&chunk_position(&config, &global)) {
+                    assert_eq!(chunk.get(&position), block_at(&global));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,408 @@
+//! Command line options of `mcrs-bin` and `mcrs-worldgen`.
+
+use std::path::PathBuf;
+
+use anyhow::bail;
+use clap::{Args, Parser};
+use winit::dpi::PhysicalSize;
+
+use crate::chunk::settings::{WorldGenSettings, WorldPreset};
+use crate::chunk::CHUNK_DEPTH;
+use crate::engine::render::frame_render_pass::{RenderScale, UpscaleFilter};
+use crate::worldgen::RegionOptions;
+
+/// How the game is started, picked on the command line or left as the
+/// defaults.
+#[derive(Clone, Debug, PartialEq)]
+pub struct GameOptions {
+    pub settings: WorldGenSettings,
+    /// Chunks loaded around the player in every direction.
+    pub load_radius: u32,
+    /// Size of the window, picked by the platform when `None`.
+    pub window_size: Option<PhysicalSize<u32>>,
+    /// How much smaller the world is rendered than the window, and how it's
+    /// scaled up to fill it.
+    pub render_scale: RenderScale,
+    /// Folder the chunks of the world are saved to.
+    pub world_dir: PathBuf,
+    pub fullscreen: bool,
+    /// Waits for the display to show each frame before drawing the next.
+    pub vsync: bool,
+    /// Draws with wgpu's software adapter, for machines without a GPU.
+    pub fallback_adapter: bool,
+    /// Draw one frame without a window and save it to this image file,
+    /// instead of playing.
+    pub screenshot: Option<PathBuf>,
+    /// Folder screenshots and captured frames are saved to.
+    pub capture_dir: PathBuf,
+    /// Save the screen every this many frames, never when 0.
+    pub capture_every: u32,
+    /// Most detailed logs shown, `None` leaves it to the `RUST_LOG`
+    /// environment variable.
+    pub log_level: Option<log::LevelFilter>,
+}
+
+impl Default for GameOptions {
+    fn default() -> Self {
+        Self {
+            settings: WorldGenSettings::default(),
+            load_radius: 4,
+            window_size: None,
+            render_scale: RenderScale {
+                downscale_factor: 4.0,
+                filter: UpscaleFilter::Nearest,
+            },
+            world_dir: PathBuf::from("world"),
+            fullscreen: false,
+            vsync: false,
+            fallback_adapter: false,
+            screenshot: None,
+            capture_dir: PathBuf::from("screenshots"),
+            capture_every: 0,
+            log_level: None,
+        }
+    }
+}
+
+/// Parse a window size written as `<width>x<height>`.
+fn parse_window_size(value: &str) -> anyhow::Result<PhysicalSize<u32>> {
+    let Some((width, height)) = value.split_once('x') else {
+        bail!("The window size has to be written as <width>x<height>, eg. 1280x720");
+    };
+    let size = PhysicalSize::new(width.trim().parse()?, height.trim().parse()?);
+    if size.width == 0 || size.height == 0 {
+        bail!("The window can't be 0 pixels wide or tall");
+    }
+    Ok(size)
+}
+
+/// Parse how many times smaller the world is rendered than the window.
+fn parse_downscale(value: &str) -> anyhow::Result<f32> {
+    let factor: f32 = value.trim().parse()?;
+    if !(1.0..=RenderScale::MAX_DOWNSCALE_FACTOR).contains(&factor) {
+        bail!(
+            "The downscale factor has to be from 1 to {}",
+            RenderScale::MAX_DOWNSCALE_FACTOR
+        );
+    }
+    Ok(factor)
+}
+
+/// Options picking the settings a world is generated with, shared by the
+/// game and the world generation tool.
+#[derive(Debug, Args)]
+pub struct WorldGenArgs {
+    /// Seed the world is generated from, replaces the seed of the preset or
+    /// settings file.
+    #[arg(long)]
+    seed: Option<u64>,
+
+    /// Built in world settings: default, superflat, amplified,
+    /// floating_islands or void.
+    #[arg(long, value_name = "NAME")]
+    preset: Option<WorldPreset>,
+
+    /// File to load the world settings from.
+    #[arg(long, value_name = "FILE", conflicts_with = "preset")]
+    world_gen: Option<PathBuf>,
+}
+
+impl WorldGenArgs {
+    /// Load the settings file or pick the preset, with the seed replaced.
+    pub fn settings(self) -> anyhow::Result<WorldGenSettings> {
+        let mut settings = match (self.preset, self.world_gen) {
+            (_, Some(path)) => WorldGenSettings::load(&path)?,
+            (Some(preset), None) => WorldGenSettings::preset(preset),
+            (None, None) => WorldGenSettings::default(),
+        };
+        if let Some(seed) = self.seed {
+            settings.seed = seed;
+        }
+        Ok(settings)
+    }
+}
+
+/// A voxel game, explore an infinite world and break and place blocks.
+#[derive(Debug, Parser)]
+#[command(name = "mcrs-bin", version)]
+pub struct Cli {
+    #[command(flatten)]
+    world: WorldGenArgs,
+
+    /// Chunks loaded around the player in every direction.
+    #[arg(long, default_value_t = 4, value_parser = clap::value_parser!(u32).range(1..=32))]
+    render_distance: u32,
+
+    /// Size of the window, eg. 1280x720.
+    #[arg(long, value_name = "WIDTHxHEIGHT", value_parser = parse_window_size)]
+    window_size: Option<PhysicalSize<u32>>,
+
+    /// How many times smaller the world is rendered than the window, eg. 2.5,
+    /// or 1 to render it at the size of the window.
+    #[arg(long, value_name = "FACTOR", default_value_t = 4.0, value_parser = parse_downscale)]
+    downscale: f32,
+
+    /// How the world is scaled up to the window: nearest, bilinear or sharp,
+    /// which keeps pixels sharp at fractional downscales.
+    #[arg(long, value_name = "FILTER", default_value_t = UpscaleFilter::Nearest)]
+    upscale_filter: UpscaleFilter,
+
+    /// Folder the chunks of the world are saved to.
+    #[arg(long, value_name = "DIR", default_value = "world")]
+    world_dir: PathBuf,
+
+    /// Fill the screen with the window.
+    #[arg(long)]
+    fullscreen: bool,
+
+    /// Wait for the display to show each frame before drawing the next.
+    #[arg(long)]
+    vsync: bool,
+
+    /// Draw with the software graphics adapter, for machines without a GPU.
+    #[arg(long)]
+    fallback_adapter: bool,
+
+    /// Draw the world without a window once the chunks around the player are
+    /// loaded, save the frame to this PNG file and exit.
+    #[arg(long, value_name = "FILE")]
+    screenshot: Option<PathBuf>,
+
+    /// Folder screenshots and captured frames are saved to.
+    #[arg(long, value_name = "DIR", default_value = "screenshots")]
+    capture_dir: PathBuf,
+
+    /// Save the screen every N frames to numbered files in the capture
+    /// folder, eg. for timelapses.
+    #[arg(long, value_name = "N", default_value_t = 0)]
+    capture_every: u32,
+
+    /// Most detailed logs shown: off, error, warn, info, debug or trace.
+    #[arg(long, value_name = "LEVEL")]
+    log_level: Option<log::LevelFilter>,
+}
+
+impl Cli {
+    /// Check the options that depend on each other or on the file system,
+    /// and load the world settings.
+    pub fn options(self) -> anyhow::Result<GameOptions> {
+        if self.world_dir.exists() && !self.world_dir.is_dir() {
+            bail!(
+                "The world folder {} is a file, not a folder",
+                self.world_dir.display()
+            );
+        }
+        if self.capture_dir.exists() && !self.capture_dir.is_dir() {
+            bail!(
+                "The capture folder {} is a file, not a folder",
+                self.capture_dir.display()
+            );
+        }
+
+        Ok(GameOptions {
+            settings: self.world.settings()?,
+            load_radius: self.render_distance,
+            window_size: self.window_size,
+            render_scale: RenderScale {
+                downscale_factor: self.downscale,
+                filter: self.upscale_filter,
+            },
+            world_dir: self.world_dir,
+            fullscreen: self.fullscreen,
+            vsync: self.vsync,
+            fallback_adapter: self.fallback_adapter,
+            screenshot: self.screenshot,
+            capture_dir: self.capture_dir,
+            capture_every: self.capture_every,
+            log_level: self.log_level,
+        })
+    }
+}
+
+/// Parse a chunk position written as `<x>,<y>,<z>`.
+fn parse_chunk_position(value: &str) -> anyhow::Result<(i32, i32, i32)> {
+    let parts: Vec<&str> = value.split(',').map(str::trim).collect();
+    let [x, y, z] = parts[..] else {
+        bail!("The chunk position has to be written as <x>,<y>,<z>, eg. 0,-1,0");
+    };
+    Ok((x.parse()?, y.parse()?, z.parse()?))
+}
+
+/// Generate a region of chunks without a window, and report how long every
+/// stage took.
+#[derive(Debug, Parser)]
+#[command(name = "mcrs-worldgen", version)]
+pub struct WorldGenCli {
+    #[command(flatten)]
+    world: WorldGenArgs,
+
+    /// Chunk position of a corner of the region.
+    #[arg(long, value_name = "X,Y,Z", default_value = "-2,-2,-2", value_parser = parse_chunk_position, allow_hyphen_values = true)]
+    from: (i32, i32, i32),
+
+    /// Chunk position of the opposite corner of the region, it's generated
+    /// too.
+    #[arg(long, value_name = "X,Y,Z", default_value = "1,1,1", value_parser = parse_chunk_position, allow_hyphen_values = true)]
+    to: (i32, i32, i32),
+
+    /// Folder to save the chunks to as region files, the game opens it with
+    /// `--world-dir` and the same world settings.
+    #[arg(long, value_name = "DIR")]
+    output: Option<PathBuf>,
+}
+
+impl WorldGenCli {
+    /// Check the options and load the world settings.
+    pub fn options(self) -> anyhow::Result<RegionOptions> {
+        if let Some(output) = self.output.as_ref().filter(|output| output.exists()) {
+            if !output.is_dir() {
+                bail!("The output {} is a file, not a folder", output.display());
+            }
+        }
+
+        Ok(RegionOptions {
+            settings: self.world.settings()?,
+            depth: CHUNK_DEPTH,
+            from: self.from,
+            to: self.to,
+            output: self.output,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::settings::GeneratorKind;
+    use crate::util::test_directory;
+
+    use super::*;
+
+    fn options(args: &[&str]) -> anyhow::Result<GameOptions> {
+        let args = std::iter::once("mcrs-bin").chain(args.iter().copied());
+        Cli::try_parse_from(args)?.options()
+    }
+
+    #[test]
+    fn cli_test() {
+        assert_eq!(options(&[]).unwrap(), GameOptions::default());
+
+        let options = options(&[
+            "--seed",
+            "42",
+            "--preset",
+            "amplified",
+            "--render-distance",
+            "3",
+            "--window-size",
+            "1280x720",
+            "--downscale",
+            "2.5",
+            "--upscale-filter",
+            "sharp",
+            "--world-dir",
+            "other",
+            "--fullscreen",
+            "--vsync",
+            "--fallback-adapter",
+            "--screenshot",
+            "shot.png",
+            "--capture-dir",
+            "captures",
+            "--capture-every",
+            "30",
+            "--log-level",
+            "debug",
+        ])
+        .unwrap();
+        assert_eq!(options.settings.seed, 42);
+        assert_eq!(options.settings.height_amplitude, 4.0);
+        assert_eq!(options.load_radius, 3);
+        assert_eq!(options.window_size, Some(PhysicalSize::new(1280, 720)));
+        assert_eq!(options.render_scale.downscale_factor, 2.5);
+        assert_eq!(options.render_scale.filter, UpscaleFilter::Sharp);
+        assert_eq!(options.world_dir, PathBuf::from("other"));
+        assert!(options.fullscreen && options.vsync && options.fallback_adapter);
+        assert_eq!(options.screenshot, Some(PathBuf::from("shot.png")));
+        assert_eq!(options.capture_dir, PathBuf::from("captures"));
+        assert_eq!(options.capture_every, 30);
+        assert_eq!(options.log_level, Some(log::LevelFilter::Debug));
+    }
+
+    #[test]
+    fn cli_world_gen_test() {
+        let dir = test_directory("cli-world-gen");
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("world_gen.ron");
+        std::fs::write(&path, "(generator: Void)").unwrap();
+        let settings = options(&["--world-gen", path.to_str().unwrap(), "--seed", "7"])
+            .unwrap()
+            .settings;
+        assert_eq!(settings.generator, GeneratorKind::Void);
+        assert_eq!(settings.seed, 7);
+
+        // a preset and a settings file can't both be picked
+        let error = options(&["--world-gen", path.to_str().unwrap(), "--preset", "void"]);
+        assert!(error.is_err());
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn cli_validation_test() {
+        for args in [
+            &["--seed", "tall"][..],
+            &["--preset", "hilly"],
+            &["--render-distance", "0"],
+            &["--render-distance", "33"],
+            &["--window-size", "1280"],
+            &["--window-size", "0x720"],
+            &["--downscale", "0"],
+            &["--downscale", "0.5"],
+            &["--downscale", "17"],
+            &["--upscale-filter", "blurry"],
+            &["--capture-every", "-1"],
+            &["--log-level", "loud"],
+            &["--world-gen", "no_such_file.ron"],
+            &["--world-dir", "Cargo.toml"],
+            &["--capture-dir", "Cargo.toml"],
+            &["--fast"],
+        ] {
+            assert!(options(args).is_err(), "{:?}", args);
+        }
+    }
+
+    #[test]
+    fn worldgen_cli_test() {
+        let options = |args: &[&str]| {
+            let args = std::iter::once("mcrs-worldgen").chain(args.iter().copied());
+            WorldGenCli::try_parse_from(args)?.options()
+        };
+
+        let defaults = options(&[]).unwrap();
+        assert_eq!(defaults.from, (-2, -2, -2));
+        assert_eq!(defaults.to, (1, 1, 1));
+        assert_eq!(defaults.depth, CHUNK_DEPTH);
+        assert_eq!(defaults.output, None);
+
+        let region = options(&[
+            "--from",
+            "-1,-3,0",
+            "--to",
+            "2,0,1",
+            "--preset",
+            "superflat",
+        ])
+        .unwrap();
+        assert_eq!(region.from, (-1, -3, 0));
+        assert_eq!(region.to, (2, 0, 1));
+        assert_eq!(region.settings.generator, GeneratorKind::Flat);
+
+        for args in [
+            &["--from", "1,2"][..],
+            &["--to", "a,b,c"],
+            &["--depth", "16"],
+            &["--output", "Cargo.toml"],
+        ] {
+            assert!(options(args).is_err(), "{:?}", args);
+        }
+    }
+}
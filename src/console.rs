@@ -0,0 +1,121 @@
+//! Commands typed into the terminal the game was started from. Lines are read
+//! on a thread of their own and run on the next tick.
+
+use std::io::BufRead;
+use std::str::FromStr;
+use std::sync::mpsc::{channel, Receiver};
+
+use anyhow::{anyhow, bail};
+
+use crate::engine::input::Input;
+use crate::engine::render::capture::CaptureSource;
+use crate::world::{Event, GameData};
+use crate::world_renderer::WorldRenderer;
+
+/// Usage of every command, shown when a line isn't one.
+const USAGE: &str = "screenshot, screenshot world, capture <every n frames>";
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Command {
+    /// Take a screenshot of the next frame.
+    Screenshot(CaptureSource),
+    /// Capture the screen every this many frames, never when 0.
+    CaptureEvery(u32),
+}
+
+impl FromStr for Command {
+    type Err = anyhow::Error;
+
+    fn from_str(line: &str) -> anyhow::Result<Self> {
+        let words: Vec<&str> = line.split_whitespace().collect();
+        let command = match words[..] {
+            ["screenshot"] => Command::Screenshot(CaptureSource::Screen),
+            ["screenshot", "world"] => Command::Screenshot(CaptureSource::World),
+            ["capture", every] => Command::CaptureEvery(
+                every
+                    .parse()
+                    .map_err(|_| anyhow!("Can't capture every {} frames", every))?,
+            ),
+            _ => bail!(
+                "Unknown command {:?}, the commands are {}",
+                line.trim(),
+                USAGE
+            ),
+        };
+        Ok(command)
+    }
+}
+
+/// The lines typed into the terminal, read in the background.
+pub struct Console {
+    lines: Receiver<String>,
+}
+
+impl Console {
+    /// Start reading lines from the terminal, until it's closed.
+    pub fn spawn() -> Self {
+        let (sender, lines) = channel();
+        std::thread::spawn(move || {
+            for line in std::io::stdin().lock().lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+        Self { lines }
+    }
+}
+
+/// System running the commands typed into the console since the last tick.
+pub fn run_console_commands(
+    _renderer: &mut WorldRenderer,
+    _input: &mut Input,
+    data: &mut GameData,
+    _queue: &mut Vec<Event>,
+    _delta: f64,
+) {
+    let Some(console) = &data.console else {
+        return;
+    };
+    let lines: Vec<String> = console.lines.try_iter().collect();
+    for line in lines.iter().filter(|line| !line.trim().is_empty()) {
+        match line.parse() {
+            Ok(Command::Screenshot(source)) => data.capture.requested = Some(source),
+            Ok(Command::CaptureEvery(every)) => data.capture.every = every,
+            Err(e) => log::error!("{:#}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn command_test() {
+        assert_eq!(
+            "screenshot".parse::<Command>().unwrap(),
+            Command::Screenshot(CaptureSource::Screen)
+        );
+        assert_eq!(
+            " screenshot  world ".parse::<Command>().unwrap(),
+            Command::Screenshot(CaptureSource::World)
+        );
+        assert_eq!(
+            "capture 30".parse::<Command>().unwrap(),
+            Command::CaptureEvery(30)
+        );
+        assert_eq!(
+            "capture 0".parse::<Command>().unwrap(),
+            Command::CaptureEvery(0)
+        );
+
+        let error = "capture often".parse::<Command>().unwrap_err().to_string();
+        assert!(error.contains("often"), "{}", error);
+        let error = "screenshots".parse::<Command>().unwrap_err().to_string();
+        assert!(error.contains("the commands are"), "{}", error);
+    }
+}
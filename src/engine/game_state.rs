@@ -1,7 +1,6 @@
 use std::collections::HashMap;
 
 use crate::engine::input::Input;
-use crate::window_state_mut;
 use winit::dpi::PhysicalSize;
 
 use super::render::renderer::Renderer;
@@ -47,12 +46,9 @@ where
         }
     }
 
-    /// Updates the window state surface and calls the resize method on the renderer.
+    /// Calls the resize method on the renderer, which owns the surface.
     pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
-        // must set window_state to new size since
-        // renderer reads from it
-        window_state_mut().resize(new_size);
-        self.renderer.resize();
+        self.renderer.resize(new_size);
     }
 
     /// Add a new system for the corresponding event.
@@ -124,7 +120,7 @@ mod tests {
             Ok(())
         }
         fn handle_event(&mut self, _event: &winit::event::Event<()>) {}
-        fn resize(&mut self) {}
+        fn resize(&mut self, _new_size: PhysicalSize<u32>) {}
     }
 
     fn mock_system_trigger_event(
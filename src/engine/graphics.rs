@@ -0,0 +1,110 @@
+//! The GPU device and queue everything is drawn with. The renderer owns them,
+//! and they are passed to whatever creates or writes GPU resources.
+
+use anyhow::{anyhow, Context};
+use wgpu::{
+    Adapter, Backends, Device, DeviceDescriptor, Features, Instance, InstanceDescriptor, Limits,
+    PowerPreference, Queue, RequestAdapterOptions, Surface, SurfaceConfiguration, TextureFormat,
+    TextureUsages,
+};
+
+/// Format of the textures drawn to without a window.
+pub const OFFSCREEN_FORMAT: TextureFormat = TextureFormat::Rgba8UnormSrgb;
+
+/// Everything needed to create and write GPU resources, and the format and
+/// size of the frames they are drawn to.
+pub struct GraphicsContext {
+    pub device: Device,
+    pub queue: Queue,
+    /// Format and size of the frames, kept the size of the window.
+    pub config: SurfaceConfiguration,
+}
+
+impl GraphicsContext {
+    /// Create a context without a window, that draws frames of the size to
+    /// textures.
+    /// `force_fallback_adapter` draws with wgpu's software adapter, so it works
+    /// on machines without a GPU.
+    pub async fn headless(
+        width: u32,
+        height: u32,
+        force_fallback_adapter: bool,
+    ) -> anyhow::Result<Self> {
+        let instance = create_instance();
+        let (_, device, queue) = request_device(&instance, None, force_fallback_adapter).await?;
+        Ok(Self {
+            device,
+            queue,
+            config: SurfaceConfiguration {
+                usage: TextureUsages::RENDER_ATTACHMENT | TextureUsages::COPY_SRC,
+                format: OFFSCREEN_FORMAT,
+                width,
+                height,
+                present_mode: wgpu::PresentMode::Fifo,
+                alpha_mode: wgpu::CompositeAlphaMode::Opaque,
+                view_formats: vec![],
+            },
+        })
+    }
+
+    /// Get the width of the frames divided by their height.
+    pub fn aspect_ratio(&self) -> f32 {
+        self.config.width as f32 / self.config.height as f32
+    }
+
+    /// Create a context for tests on the software adapter, the tests fail on
+    /// machines without one, eg. without Mesa's llvmpipe.
+    #[cfg(test)]
+    pub fn for_tests(width: u32, height: u32) -> Self {
+        pollster::block_on(Self::headless(width, height, true))
+            .expect("The rendering tests need a software graphics adapter")
+    }
+}
+
+/// Create the handle to the GPU, for every backend.
+pub fn create_instance() -> Instance {
+    Instance::new(InstanceDescriptor {
+        backends: Backends::all(),
+        dx12_shader_compiler: Default::default(),
+    })
+}
+
+/// Find an adapter that can draw to the surface, or to textures when there is
+/// no surface, and request the device and queue the game draws with.
+/// `force_fallback_adapter` only accepts wgpu's software adapter.
+pub async fn request_device(
+    instance: &Instance,
+    surface: Option<&Surface>,
+    force_fallback_adapter: bool,
+) -> anyhow::Result<(Adapter, Device, Queue)> {
+    log::info!("Looking for an adapter, force_fallback_adapter: {force_fallback_adapter}");
+    let adapter = instance
+        .request_adapter(&RequestAdapterOptions {
+            power_preference: PowerPreference::default(),
+            compatible_surface: surface,
+            force_fallback_adapter,
+        })
+        .await
+        .ok_or_else(|| anyhow!("No graphics adapter found"))?;
+    log::info!("Drawing with {:?}", adapter.get_info());
+
+    // WebGL and software adapters don't support all of wgpu's features and
+    // limits, so only the ones the adapter has are asked for
+    let limits = if cfg!(target_arch = "wasm32") {
+        Limits::downlevel_webgl2_defaults()
+    } else {
+        Limits::default()
+    };
+    let (device, queue) = adapter
+        .request_device(
+            &DeviceDescriptor {
+                features: adapter.features() & Features::POLYGON_MODE_LINE,
+                limits: limits.using_resolution(adapter.limits()),
+                label: None,
+            },
+            None, // Trace path
+        )
+        .await
+        .context("Failed to get a device from the graphics adapter")?;
+    Ok((adapter, device, queue))
+}
@@ -4,8 +4,6 @@ use std::collections::HashMap;
 
 use winit::event::{ElementState, KeyboardInput, MouseButton, VirtualKeyCode, WindowEvent};
 
-use crate::window_state;
-
 /// Struct used to track how long a key or mouse button has been pressed. Also
 /// stores mouse movement delta and whether or not the window is focused.
 pub struct Input {
@@ -92,13 +90,6 @@ impl Input {
                     },
                 ..
             } => {
-                if v == &VirtualKeyCode::Escape {
-                    let window = &window_state().window;
-                    window
-                        .set_cursor_grab(winit::window::CursorGrabMode::None)
-                        .unwrap_or_default();
-                    window.set_cursor_visible(true);
-                }
                 self.keys.entry(*v).or_insert(instant::now());
             }
             WindowEvent::KeyboardInput {
@@ -155,6 +146,27 @@ mod tests {
         assert!(input.get_key(VirtualKeyCode::A) == 0.0);
     }
 
+    /// Input doesn't need a window, even for the keys the game uses to free
+    /// the cursor.
+    #[test]
+    fn escape_without_window_test() {
+        let mut input = Input::new();
+
+        let escape_event = WindowEvent::KeyboardInput {
+            device_id: unsafe { DeviceId::dummy() },
+            input: KeyboardInput {
+                state: ElementState::Pressed,
+                scancode: 0x01,
+                virtual_keycode: Some(VirtualKeyCode::Escape),
+                modifiers: ModifiersState::default(),
+            },
+            is_synthetic: false,
+        };
+
+        input.handle(&escape_event);
+        assert!(input.get_key(VirtualKeyCode::Escape) > 0.0);
+    }
+
     #[test]
     fn mouse_press_event_test() {
         let mut input = Input::new();
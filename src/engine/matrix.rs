@@ -1,9 +1,7 @@
 //! Used to simplify sending Mat4s to the shader during rendering.
 
 use glam::Mat4;
-use wgpu::Buffer;
-
-use crate::window_state;
+use wgpu::{Buffer, Device, Queue};
 
 use super::render::uniform::{Uniform, UniformLayout};
 
@@ -17,9 +15,8 @@ pub struct Matrix {
 impl Matrix {
     /// Create a new engine matrix from a [glam Matrix](glam::Mat4). Creates and fills
     /// a new device buffer.
-    pub fn new(matrix: Mat4) -> Self {
+    pub fn new(device: &Device, matrix: Mat4) -> Self {
         use wgpu::util::DeviceExt;
-        let device = &window_state().device;
         Matrix {
             data: matrix,
             buffer: device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
@@ -41,8 +38,7 @@ impl Matrix {
     }
 
     /// Updates the matrix buffer using the matrix member.
-    pub fn update_buffer(&mut self) {
-        let queue = &window_state().queue;
+    pub fn update_buffer(&mut self, queue: &Queue) {
         queue.write_buffer(
             &self.buffer,
             0,
@@ -52,9 +48,7 @@ impl Matrix {
 
     /// Commonly used for creating a render group. Returns a uniform layout specifying how
     /// the matrix will be used in the shader.
-    pub fn create_layout(location: u32) -> UniformLayout {
-        let device = &window_state().device;
-
+    pub fn create_layout(device: &Device, location: u32) -> UniformLayout {
         UniformLayout {
             layout: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[wgpu::BindGroupLayoutEntry {
@@ -76,8 +70,7 @@ impl Matrix {
     /// Consume the Matrix to create a [Uniform](super::uniform::Uniform). The Uniform takes ownership of
     /// this Matrix struct for future updates.
     /// `location` is the bind group index to be used in the shader.
-    pub fn uniform(self, layout: &UniformLayout) -> Uniform {
-        let device = &window_state().device;
+    pub fn uniform(self, device: &Device, layout: &UniformLayout) -> Uniform {
         let bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &layout.layout,
             entries: &[wgpu::BindGroupEntry {
@@ -1,4 +1,5 @@
 pub mod game_state;
+pub mod graphics;
 pub mod input;
 pub mod matrix;
 pub mod resources;
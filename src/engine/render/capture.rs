@@ -0,0 +1,236 @@
+//! Saving frames as PNG files, screenshots and captures of every few frames.
+//! Frames are read back without waiting for the GPU, and saved in the
+//! background once they are.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+use image::RgbaImage;
+
+use crate::engine::graphics::GraphicsContext;
+use crate::engine::render::render_target::TextureReadback;
+
+/// What a capture reads back.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CaptureSource {
+    /// The frame shown on the screen, with the debug menu.
+    Screen,
+    /// The world before it's scaled up to the screen, at the smaller size it's
+    /// drawn at.
+    World,
+}
+
+/// What to capture, changed by the keybindings and the debug menu.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CaptureSettings {
+    /// Folder the images are saved to.
+    pub dir: PathBuf,
+    /// Capture the screen every this many frames to numbered files, never
+    /// when 0.
+    pub every: u32,
+    /// A screenshot to take of the next frame.
+    pub requested: Option<CaptureSource>,
+}
+
+impl CaptureSettings {
+    pub fn new(dir: PathBuf, every: u32) -> Self {
+        Self {
+            dir,
+            every,
+            requested: None,
+        }
+    }
+}
+
+/// The captures being read back, and the numbers of the files they are saved
+/// to.
+pub struct FrameCapture {
+    frames: u64,
+    /// Folder the numbers count up in, it's scanned for the numbers already
+    /// taken once, before the first capture saved to it.
+    numbers_dir: Option<PathBuf>,
+    screenshot_number: u32,
+    sequence_number: u32,
+    pending: Vec<(TextureReadback, PathBuf)>,
+}
+
+impl FrameCapture {
+    pub fn new() -> Self {
+        Self {
+            frames: 0,
+            numbers_dir: None,
+            screenshot_number: 0,
+            sequence_number: 0,
+            pending: Vec::new(),
+        }
+    }
+
+    /// Start the numbers after the last files in the folder, the first time
+    /// something is captured to it.
+    fn scan_numbers(&mut self, dir: &Path) {
+        if self.numbers_dir.as_deref() == Some(dir) {
+            return;
+        }
+        self.screenshot_number = next_free_number(dir, "screenshot");
+        self.sequence_number = next_free_number(dir, "frame");
+        self.numbers_dir = Some(dir.to_path_buf());
+    }
+
+    /// Count a frame, and get what to capture of it and the file to save it
+    /// to, if anything.
+    pub fn next_capture(
+        &mut self,
+        settings: &mut CaptureSettings,
+    ) -> Option<(CaptureSource, PathBuf)> {
+        self.frames += 1;
+        if let Some(source) = settings.requested.take() {
+            self.scan_numbers(&settings.dir);
+            let path = numbered_path(&settings.dir, "screenshot", &mut self.screenshot_number);
+            return Some((source, path));
+        }
+        if settings.every > 0 && self.frames.is_multiple_of(settings.every as u64) {
+            self.scan_numbers(&settings.dir);
+            let path = numbered_path(&settings.dir, "frame", &mut self.sequence_number);
+            return Some((CaptureSource::Screen, path));
+        }
+        None
+    }
+
+    /// Start reading back the texture, it's saved to the file once it has
+    /// been read. Has to be called after the frame is drawn to the texture,
+    /// and before it's presented.
+    pub fn start(&mut self, gfx: &GraphicsContext, texture: &wgpu::Texture, path: PathBuf) {
+        match TextureReadback::new(gfx, texture) {
+            Ok(readback) => self.pending.push((readback, path)),
+            Err(e) => log::error!("Failed to capture {}: {:#}", path.display(), e),
+        }
+    }
+
+    /// Save the captures that have been read back since the last call, on the
+    /// thread pool. Polls the device without waiting for it.
+    pub fn save_finished(&mut self, gfx: &GraphicsContext) {
+        if self.pending.is_empty() {
+            return;
+        }
+        gfx.device.poll(wgpu::Maintain::Poll);
+        self.pending.retain(|(readback, path)| {
+            let Some(image) = readback.try_image() else {
+                return true;
+            };
+            let path = path.clone();
+            rayon::spawn(move || match save_image(image, &path) {
+                Ok(()) => log::info!("Saved {}", path.display()),
+                Err(e) => log::error!("Failed to save {}: {:#}", path.display(), e),
+            });
+            false
+        });
+    }
+}
+
+/// Get the file `<prefix>-<number>.png` in the folder, and count the number
+/// up.
+fn numbered_path(dir: &Path, prefix: &str, number: &mut u32) -> PathBuf {
+    let path = dir.join(format!("{}-{:05}.png", prefix, number));
+    *number += 1;
+    path
+}
+
+/// Get the number after the highest numbered file `<prefix>-<number>.png` in
+/// the folder, 0 if there are none or the folder doesn't exist.
+fn next_free_number(dir: &Path, prefix: &str) -> u32 {
+    let Ok(entries) = std::fs::read_dir(dir) else {
+        return 0;
+    };
+    entries
+        .flatten()
+        .filter_map(|entry| {
+            let name = entry.file_name().into_string().ok()?;
+            let number = name.strip_prefix(prefix)?.strip_prefix('-')?;
+            number.strip_suffix(".png")?.parse::<u32>().ok()
+        })
+        .max()
+        .map_or(0, |number| number.saturating_add(1))
+}
+
+fn save_image(image: anyhow::Result<RgbaImage>, path: &Path) -> anyhow::Result<()> {
+    let image = image?;
+    if let Some(dir) = path.parent() {
+        std::fs::create_dir_all(dir)
+            .with_context(|| format!("Failed to create the folder {}", dir.display()))?;
+    }
+    image.save(path)?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+
+    use super::*;
+    use crate::engine::render::render_target::{OffscreenTarget, RenderTarget};
+    use crate::util::test_directory;
+
+    #[test]
+    fn next_capture_test() {
+        let dir = test_directory("next-capture");
+        std::fs::create_dir_all(&dir).unwrap();
+        std::fs::write(dir.join("screenshot-00004.png"), []).unwrap();
+        std::fs::write(dir.join("screenshot-notes.png"), []).unwrap();
+        let mut settings = CaptureSettings::new(dir.clone(), 3);
+        let mut capture = FrameCapture::new();
+
+        // every third frame is captured, to numbered files
+        let captures: Vec<_> = (0..7)
+            .map(|_| capture.next_capture(&mut settings))
+            .collect();
+        assert_eq!(captures.iter().flatten().count(), 2);
+        assert_eq!(
+            captures[2],
+            Some((CaptureSource::Screen, dir.join("frame-00000.png")))
+        );
+        assert_eq!(
+            captures[5],
+            Some((CaptureSource::Screen, dir.join("frame-00001.png")))
+        );
+
+        // a screenshot is taken once, and doesn't overwrite older ones
+        settings.every = 0;
+        settings.requested = Some(CaptureSource::World);
+        assert_eq!(
+            capture.next_capture(&mut settings),
+            Some((CaptureSource::World, dir.join("screenshot-00005.png")))
+        );
+        assert_eq!(settings.requested, None);
+        assert_eq!(capture.next_capture(&mut settings), None);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn capture_saved_test() {
+        let gfx = GraphicsContext::for_tests(40, 30);
+        let dir = test_directory("capture-saved");
+        let target = RenderTarget::Offscreen(OffscreenTarget::new(&gfx));
+        let frame = target.frame().unwrap();
+
+        let mut settings = CaptureSettings::new(dir.clone(), 0);
+        settings.requested = Some(CaptureSource::Screen);
+        let mut capture = FrameCapture::new();
+        let (_, path) = capture.next_capture(&mut settings).unwrap();
+        capture.start(&gfx, target.frame_texture(&frame), path.clone());
+        frame.present();
+
+        // saved in the background, after a few frames
+        let start = Instant::now();
+        while !path.exists() || image::open(&path).is_err() {
+            assert!(start.elapsed() < Duration::from_secs(30), "Never saved");
+            capture.save_finished(&gfx);
+            std::thread::sleep(Duration::from_millis(10));
+        }
+        assert!(capture.pending.is_empty());
+        assert_eq!(
+            image::open(&path).unwrap().to_rgba8().dimensions(),
+            (40, 30)
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,11 +1,13 @@
 #![allow(dead_code)]
 
-use crate::{
-    engine::{
-        render::{render_group::RenderGroup, render_object::RenderObject, uniform::Uniform},
-        texture::Texture,
-    },
-    window_state,
+use std::{fmt, str::FromStr};
+
+use anyhow::anyhow;
+
+use crate::engine::{
+    graphics::GraphicsContext,
+    render::{render_group::RenderGroup, render_object::RenderObject, uniform::Uniform},
+    texture::Texture,
 };
 
 use super::{
@@ -14,9 +16,95 @@ use super::{
     uniform::UniformData,
 };
 
+/// How the world drawn to the render texture is scaled up to the screen.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum UpscaleFilter {
+    /// Every texel is drawn as a block of pixels.
+    #[default]
+    Nearest,
+    /// Texels are blended with their neighbours, blurring them.
+    Bilinear,
+    /// Texels are drawn as blocks, blended only where two of them meet, so
+    /// they are all as wide at fractional scales.
+    Sharp,
+}
+
+impl UpscaleFilter {
+    pub const ALL: [UpscaleFilter; 3] = [
+        UpscaleFilter::Nearest,
+        UpscaleFilter::Bilinear,
+        UpscaleFilter::Sharp,
+    ];
+
+    /// Name of the filter on the command line.
+    pub fn name(&self) -> &'static str {
+        match self {
+            UpscaleFilter::Nearest => "nearest",
+            UpscaleFilter::Bilinear => "bilinear",
+            UpscaleFilter::Sharp => "sharp",
+        }
+    }
+
+    /// Entry point of the frame shader that scales with the filter.
+    fn fragment_entry(&self) -> &'static str {
+        match self {
+            UpscaleFilter::Nearest => "fs_main",
+            UpscaleFilter::Bilinear => "fs_bilinear",
+            UpscaleFilter::Sharp => "fs_sharp",
+        }
+    }
+}
+
+impl fmt::Display for UpscaleFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for UpscaleFilter {
+    type Err = anyhow::Error;
+
+    fn from_str(name: &str) -> anyhow::Result<Self> {
+        UpscaleFilter::ALL
+            .into_iter()
+            .find(|filter| filter.name() == name)
+            .ok_or_else(|| {
+                let names: Vec<&str> = UpscaleFilter::ALL.iter().map(|f| f.name()).collect();
+                anyhow!(
+                    "Unknown upscale filter {}, the filters are {}",
+                    name,
+                    names.join(", ")
+                )
+            })
+    }
+}
+
+/// The size the world is drawn at, and how it's scaled up to the screen.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct RenderScale {
+    /// How many times smaller the world is drawn than the screen, 1 draws it
+    /// at the size of the screen.
+    pub downscale_factor: f32,
+    pub filter: UpscaleFilter,
+}
+
+impl RenderScale {
+    pub const MAX_DOWNSCALE_FACTOR: f32 = 16.0;
+
+    /// Get the size the world is drawn at for a screen of the size, at least
+    /// a pixel.
+    pub fn scaled_size(&self, width: u32, height: u32) -> (u32, u32) {
+        let factor = self.downscale_factor.clamp(1.0, Self::MAX_DOWNSCALE_FACTOR);
+        let scale = |size: u32| ((size as f32 / factor).round() as u32).max(1);
+        (scale(width), scale(height))
+    }
+}
+
 pub struct FrameRenderPass {
-    group: RenderGroup,
+    /// A render group for each upscale filter.
+    groups: [RenderGroup; 3],
     frame: RenderObject,
+    scale: RenderScale,
     pub render_texture: Uniform,
     pub clear_color: wgpu::Color,
 }
@@ -44,20 +132,19 @@ impl FrameVertex {
 }
 
 impl FrameRenderPass {
-    pub fn new(downscale_factor: u32, shader_source: &str) -> Self {
-        let config = &window_state().config;
-        let layout = Texture::create_layout(0);
+    pub fn new(gfx: &GraphicsContext, scale: RenderScale, shader_source: &str) -> Self {
+        let device = &gfx.device;
         Self {
-            render_texture: Texture::create_render_texture(
-                config.width / downscale_factor,
-                config.height / downscale_factor,
-            )
-            .uniform(&layout),
-            group: RenderGroupBuilder::new()
-                .shader(shader_source)
-                .vertex_format(FrameVertex::description())
-                .with("frame-buffer", layout)
-                .build(false),
+            render_texture: Self::create_render_texture(gfx, scale),
+            groups: UpscaleFilter::ALL.map(|filter| {
+                RenderGroupBuilder::new()
+                    .shader(shader_source)
+                    .fragment_entry(filter.fragment_entry())
+                    .vertex_format(FrameVertex::description())
+                    .with("frame-buffer", Texture::create_layout(device, 0))
+                    .build(gfx, false)
+            }),
+            scale,
             clear_color: wgpu::Color {
                 r: 0.1,
                 g: 0.2,
@@ -65,6 +152,7 @@ impl FrameRenderPass {
                 a: 1.0,
             },
             frame: RenderObject::new(
+                device,
                 "",
                 bytemuck::cast_slice(
                     vec![
@@ -89,19 +177,50 @@ impl FrameRenderPass {
         None
     }
 
-    pub fn resize(&mut self, downscale_factor: u32) {
-        let config = &window_state().config;
-        self.render_texture = Texture::create_render_texture(
-            config.width / downscale_factor,
-            config.height / downscale_factor,
-        )
-        .uniform(&Texture::create_layout(0));
+    pub fn get_render_texture(&self) -> Option<&wgpu::Texture> {
+        if let UniformData::Texture(texture) = &self.render_texture.data {
+            return Some(&texture.texture);
+        }
+        None
+    }
+
+    /// Create the texture the world is drawn to, for the size of the
+    /// context's frames.
+    fn create_render_texture(gfx: &GraphicsContext, scale: RenderScale) -> Uniform {
+        let (width, height) = scale.scaled_size(gfx.config.width, gfx.config.height);
+        Texture::create_render_texture(gfx, width, height)
+            .uniform(&gfx.device, &Texture::create_layout(&gfx.device, 0))
+    }
+
+    /// Get the size of the render texture.
+    pub fn render_size(&self, gfx: &GraphicsContext) -> (u32, u32) {
+        self.scale.scaled_size(gfx.config.width, gfx.config.height)
+    }
+
+    pub fn scale(&self) -> RenderScale {
+        self.scale
+    }
+
+    /// Change the scale, the render texture is only created again when its
+    /// size changes.
+    pub fn set_scale(&mut self, gfx: &GraphicsContext, scale: RenderScale) {
+        let resized = scale.downscale_factor != self.scale.downscale_factor;
+        self.scale = scale;
+        if resized {
+            self.resize(gfx);
+        }
+    }
+
+    /// Create the render texture again for the size of the context's frames.
+    pub fn resize(&mut self, gfx: &GraphicsContext) {
+        self.render_texture = Self::create_render_texture(gfx, self.scale);
     }
 }
 
 impl<T> RenderPass<T> for FrameRenderPass {
     fn render(
         &mut self,
+        gfx: &GraphicsContext,
         _game_state: &mut T,
         views: RenderPassViews,
         _delta: f64,
@@ -111,8 +230,7 @@ impl<T> RenderPass<T> for FrameRenderPass {
             .expect("No color attachment specified on Object Render Pass...");
 
         let mut encoder: wgpu::CommandEncoder =
-            window_state()
-                .device
+            gfx.device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some("Frame Render Pass"),
                 });
@@ -134,7 +252,7 @@ impl<T> RenderPass<T> for FrameRenderPass {
             // encode the render commands
             // loop over all render objects
 
-            render_pass.set_pipeline(&self.group.pipeline);
+            render_pass.set_pipeline(&self.groups[self.scale.filter as usize].pipeline);
             render_pass.set_bind_group(
                 self.render_texture.location,
                 &self.render_texture.bind_group,
@@ -151,9 +269,7 @@ impl<T> RenderPass<T> for FrameRenderPass {
             // draw
             render_pass.draw(0..6, 0..1);
         }
-        window_state()
-            .queue
-            .submit(std::iter::once(encoder.finish()));
+        gfx.queue.submit(std::iter::once(encoder.finish()));
         Ok(())
     }
 }
@@ -1,9 +1,9 @@
 //! Implementation for ImGui.
 use imgui::{FontSource, Ui};
 use imgui_wgpu::{Renderer, RendererConfig};
-use wgpu::TextureFormat;
+use winit::window::Window;
 
-use crate::window_state;
+use crate::engine::graphics::GraphicsContext;
 
 use super::render_pass::{RenderPass, RenderPassViews};
 
@@ -16,20 +16,22 @@ pub struct ImguiRenderPass<T> {
 }
 
 impl<T> ImguiRenderPass<T> {
-    pub fn new() -> Self {
-        let window = &window_state().window;
-        let device = &window_state().device;
-        let queue = &window_state().queue;
-        let hidpi_factor = &window.scale_factor();
+    /// Set up imgui drawing to the context's frames, without a window it
+    /// draws at a scale of 1 and gets no input.
+    pub fn new(gfx: &GraphicsContext, window: Option<&Window>) -> Self {
+        let GraphicsContext { device, queue, .. } = gfx;
+        let hidpi_factor = &window.map_or(1.0, |window| window.scale_factor());
 
         // Set up dear imgui
         let mut imgui = imgui::Context::create();
         let mut platform = imgui_winit_support::WinitPlatform::init(&mut imgui);
-        platform.attach_window(
-            imgui.io_mut(),
-            &window,
-            imgui_winit_support::HiDpiMode::Default,
-        );
+        if let Some(window) = window {
+            platform.attach_window(
+                imgui.io_mut(),
+                window,
+                imgui_winit_support::HiDpiMode::Default,
+            );
+        }
         imgui.set_ini_filename(None);
 
         let font_size = (13.0 * hidpi_factor) as f32;
@@ -45,11 +47,11 @@ impl<T> ImguiRenderPass<T> {
         }]);
 
         let renderer_config = RendererConfig {
-            texture_format: TextureFormat::Bgra8UnormSrgb,
+            texture_format: gfx.config.format,
             ..Default::default()
         };
 
-        let renderer = Renderer::new(&mut imgui, &device, &queue, renderer_config);
+        let renderer = Renderer::new(&mut imgui, device, queue, renderer_config);
 
         Self {
             context: imgui,
@@ -58,40 +60,50 @@ impl<T> ImguiRenderPass<T> {
             windows: vec![],
         }
     }
+
+    /// Update the size of the screen and the mouse cursor from the window, or
+    /// the size from the context's frames without one. Called before each frame
+    /// is rendered.
+    pub fn prepare_frame(&mut self, gfx: &GraphicsContext, window: Option<&Window>) {
+        match window {
+            Some(window) => self
+                .platform
+                .prepare_frame(self.context.io_mut(), window)
+                .expect("Failed to prepare frame"),
+            None => {
+                let io = self.context.io_mut();
+                io.display_size = [gfx.config.width as f32, gfx.config.height as f32];
+                io.display_framebuffer_scale = [1.0, 1.0];
+            }
+        }
+    }
 }
 
 impl<T> RenderPass<T> for ImguiRenderPass<T> {
     fn render(
         &mut self,
+        gfx: &GraphicsContext,
         game_data: &mut T,
         views: RenderPassViews,
         delta: f64,
     ) -> Result<(), wgpu::SurfaceError> {
-        let window = &window_state().window;
-        let device = &window_state().device;
-        let queue = &window_state().queue;
+        let GraphicsContext { device, queue, .. } = gfx;
         let view = views
             .color
             .expect("No color attachment specified on Imgui Render Pass...");
 
         let mut encoder: wgpu::CommandEncoder =
-            window_state()
-                .device
-                .create_command_encoder(&wgpu::CommandEncoderDescriptor {
-                    label: Some("ImGui Render Pass"),
-                });
+            device.create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("ImGui Render Pass"),
+            });
 
         let imgui = &mut self.context;
-        let platform = &mut self.platform;
         let renderer = &mut self.renderer;
 
         imgui
             .io_mut()
             .update_delta_time(std::time::Duration::from_millis(delta as u64));
 
-        platform
-            .prepare_frame(imgui.io_mut(), &window)
-            .expect("Failed to prepare frame");
         let ui = imgui.frame();
 
         self.windows.drain(..).for_each(|f| {
@@ -112,12 +124,10 @@ impl<T> RenderPass<T> for ImguiRenderPass<T> {
             });
 
             renderer
-                .render(imgui.render(), &queue, &device, &mut rpass)
+                .render(imgui.render(), queue, device, &mut rpass)
                 .expect("Rendering failed");
         }
-        window_state()
-            .queue
-            .submit(std::iter::once(encoder.finish()));
+        queue.submit(std::iter::once(encoder.finish()));
 
         Ok(())
     }
@@ -1,8 +1,10 @@
+pub mod capture;
 pub mod frame_render_pass;
 pub mod imgui_render_pass;
 pub mod object_render_pass;
 pub mod render_group;
 pub mod render_object;
 pub mod render_pass;
+pub mod render_target;
 pub mod renderer;
 pub mod uniform;
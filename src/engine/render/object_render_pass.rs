@@ -2,9 +2,9 @@
 
 use std::{collections::HashMap, marker::PhantomData};
 
-use crate::{
-    engine::render::{render_group::RenderGroup, render_object::RenderObject, uniform::Uniform},
-    window_state,
+use crate::engine::{
+    graphics::GraphicsContext,
+    render::{render_group::RenderGroup, render_object::RenderObject, uniform::Uniform},
 };
 
 use super::render_pass::{RenderPass, RenderPassViews};
@@ -37,6 +37,7 @@ impl<T> ObjectRenderPass<T> {
 impl<T> RenderPass<T> for ObjectRenderPass<T> {
     fn render(
         &mut self,
+        gfx: &GraphicsContext,
         _game_state: &mut T,
         views: RenderPassViews,
         _delta: f64,
@@ -49,8 +50,7 @@ impl<T> RenderPass<T> for ObjectRenderPass<T> {
             .expect("No depth attachment specified on Object Render Pass...");
 
         let mut encoder: wgpu::CommandEncoder =
-            window_state()
-                .device
+            gfx.device
                 .create_command_encoder(&wgpu::CommandEncoderDescriptor {
                     label: Some("Object Render Pass"),
                 });
@@ -117,9 +117,7 @@ impl<T> RenderPass<T> for ObjectRenderPass<T> {
                 render_pass.draw_indexed(0..num_indices, 0, 0..1);
             }
         }
-        window_state()
-            .queue
-            .submit(std::iter::once(encoder.finish()));
+        gfx.queue.submit(std::iter::once(encoder.finish()));
         Ok(())
     }
 }
@@ -1,12 +1,12 @@
 //! RenderGroups are used to hold specific render pipelines and their associated uniforms.
 
+use crate::engine::graphics::GraphicsContext;
 use crate::engine::render::uniform::UniformLayout;
 
-use crate::window_state;
-
 use wgpu::{
-    BindGroupLayout, PipelineLayoutDescriptor, RenderPipeline, RenderPipelineDescriptor,
-    ShaderModule, ShaderModuleDescriptor, ShaderSource, TextureFormat, VertexBufferLayout,
+    BindGroupLayout, BlendState, PipelineLayoutDescriptor, RenderPipeline,
+    RenderPipelineDescriptor, ShaderModuleDescriptor, ShaderSource, TextureFormat,
+    VertexBufferLayout,
 };
 
 /// Used to build a RenderGroup.
@@ -16,7 +16,10 @@ pub struct RenderGroupBuilder<'a> {
     vertex_format: Option<VertexBufferLayout<'a>>,
     uniforms: Vec<UniformLayout>,
     uniform_names: Vec<String>,
-    shader: Option<ShaderModule>,
+    shader: Option<&'a str>,
+    fragment_entry: &'a str,
+    blend: BlendState,
+    depth_write: bool,
 }
 
 impl<'a> RenderGroupBuilder<'a> {
@@ -27,19 +30,34 @@ impl<'a> RenderGroupBuilder<'a> {
             uniforms: Vec::new(),
             uniform_names: Vec::new(),
             shader: None,
+            fragment_entry: "fs_main",
+            blend: BlendState::REPLACE,
+            depth_write: true,
         }
     }
 
-    /// Set the shader for the render group. Takes in the shader source as a `&str`.
-    pub fn shader(mut self, source: &str) -> Self {
-        let shader = window_state()
-            .device
-            .create_shader_module(ShaderModuleDescriptor {
-                label: Some("Shader"),
-                source: ShaderSource::Wgsl(source.into()),
-            });
+    /// Set the shader for the render group. Takes in the shader source as a `&str`,
+    /// it's compiled when the group is built.
+    pub fn shader(mut self, source: &'a str) -> Self {
+        self.shader = Some(source);
+        self
+    }
 
-        self.shader = Some(shader);
+    /// Set the entry point of the fragment shader, `fs_main` by default.
+    pub fn fragment_entry(mut self, entry: &'a str) -> Self {
+        self.fragment_entry = entry;
+        self
+    }
+
+    /// Set how fragments are blended with the target, they replace it by default.
+    pub fn blend(mut self, blend: BlendState) -> Self {
+        self.blend = blend;
+        self
+    }
+
+    /// Set if fragments write to the depth buffer when depth is enabled, true by default.
+    pub fn depth_write(mut self, depth_write: bool) -> Self {
+        self.depth_write = depth_write;
         self
     }
 
@@ -56,11 +74,16 @@ impl<'a> RenderGroupBuilder<'a> {
         self
     }
 
-    /// Consume the builder and return a built render group. Will panic if no vertex format or shader set.
-    pub fn build(self, depth: bool) -> RenderGroup {
+    /// Consume the builder and return a render group built on the context's device, drawing
+    /// to frames of the context's format. Will panic if no vertex format or shader set.
+    pub fn build(self, gfx: &GraphicsContext, depth: bool) -> RenderGroup {
         let layouts: Vec<&BindGroupLayout> = self.uniforms.iter().map(|x| &x.layout).collect();
 
-        let shader = self.shader.expect("No shader set for RenderGroup.");
+        let source = self.shader.expect("No shader set for RenderGroup.");
+        let shader = gfx.device.create_shader_module(ShaderModuleDescriptor {
+            label: Some("Shader"),
+            source: ShaderSource::Wgsl(source.into()),
+        });
 
         let vertex_format = self
             .vertex_format
@@ -70,23 +93,22 @@ impl<'a> RenderGroupBuilder<'a> {
         if depth {
             depth_stencil = Some(wgpu::DepthStencilState {
                 format: TextureFormat::Depth32Float,
-                depth_write_enabled: true,
+                depth_write_enabled: self.depth_write,
                 depth_compare: wgpu::CompareFunction::Less, // 1.
                 stencil: wgpu::StencilState::default(),     // 2.
                 bias: wgpu::DepthBiasState::default(),
             });
         }
 
-        let render_pipeline_layout =
-            window_state()
-                .device
-                .create_pipeline_layout(&PipelineLayoutDescriptor {
-                    label: Some("Render Pipeline Layout"),
-                    bind_group_layouts: &layouts,
-                    push_constant_ranges: &[],
-                });
+        let render_pipeline_layout = gfx
+            .device
+            .create_pipeline_layout(&PipelineLayoutDescriptor {
+                label: Some("Render Pipeline Layout"),
+                bind_group_layouts: &layouts,
+                push_constant_ranges: &[],
+            });
 
-        let pipeline = window_state()
+        let pipeline = gfx
             .device
             .create_render_pipeline(&RenderPipelineDescriptor {
                 label: Some("Render Pipeline"),
@@ -99,11 +121,11 @@ impl<'a> RenderGroupBuilder<'a> {
                 fragment: Some(wgpu::FragmentState {
                     // 3.
                     module: &shader,
-                    entry_point: "fs_main",
+                    entry_point: self.fragment_entry,
                     targets: &[Some(wgpu::ColorTargetState {
                         // 4.
-                        format: window_state().config.format,
-                        blend: Some(wgpu::BlendState::REPLACE),
+                        format: gfx.config.format,
+                        blend: Some(self.blend),
                         write_mask: wgpu::ColorWrites::ALL,
                     })],
                 }),
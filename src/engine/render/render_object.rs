@@ -1,10 +1,9 @@
 //! Used to store a game object's rendering information.
 
 use std::collections::HashMap;
-use wgpu::Buffer;
+use wgpu::{Buffer, Device};
 
 use crate::engine::render::uniform::Uniform;
-use crate::window_state;
 
 /// Stores the id of its associated [render group](super::render_group::RenderGroup), a vertex and index buffer,
 /// and this render object's associated uniforms.
@@ -19,9 +18,9 @@ pub struct RenderObject {
 
 impl RenderObject {
     /// Create a new render object with bind group id, vertex and index buffer.
-    pub fn new(bind_group: &str, vertices: &[u8], indices: &[u8]) -> Self {
+    /// The indices can be written again later, eg. to draw in another order.
+    pub fn new(device: &Device, bind_group: &str, vertices: &[u8], indices: &[u8]) -> Self {
         use wgpu::util::DeviceExt;
-        let device = &window_state().device;
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
             contents: vertices, // bytemuck::cast_slice(VERTICES),
@@ -30,7 +29,7 @@ impl RenderObject {
         let index_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Index Buffer"),
             contents: indices, //  bytemuck::cast_slice(INDICES),
-            usage: wgpu::BufferUsages::INDEX,
+            usage: wgpu::BufferUsages::INDEX | wgpu::BufferUsages::COPY_DST,
         });
         RenderObject {
             render_group: bind_group.to_owned(),
@@ -43,9 +42,8 @@ impl RenderObject {
 
     /// Update the render object's buffers. Writes the buffers using the WGPU device.
     #[allow(dead_code)]
-    pub fn set_buffers(&mut self, vertices: &[u8], indices: &[u8]) {
+    pub fn set_buffers(&mut self, device: &Device, vertices: &[u8], indices: &[u8]) {
         use wgpu::util::DeviceExt;
-        let device = &window_state().device;
 
         let vertex_buffer = device.create_buffer_init(&wgpu::util::BufferInitDescriptor {
             label: Some("Vertex Buffer"),
@@ -1,11 +1,14 @@
 //! Abstract trait for a rendering pass.
 
+use crate::engine::graphics::GraphicsContext;
+
 /// We pass in the textures we want to render to and also a mutable reference to the game state.
 /// We do this so that the rendering can change variables, ie. for gui.
 pub trait RenderPass<T> {
-    /// Execute the render pass.
+    /// Execute the render pass, the commands are submitted to the context's queue.
     fn render(
         &mut self,
+        gfx: &GraphicsContext,
         game_data: &mut T,
         views: RenderPassViews,
         delta: f64,
@@ -0,0 +1,324 @@
+//! What the renderer draws to, the window's surface or a texture that can be
+//! read back as an image.
+
+use std::sync::mpsc::{Receiver, TryRecvError};
+
+use anyhow::{anyhow, bail, Context};
+use image::RgbaImage;
+use wgpu::{SurfaceError, SurfaceTexture, TextureFormat, TextureView};
+use winit::dpi::PhysicalSize;
+use winit::window::Window;
+
+use crate::engine::graphics::GraphicsContext;
+use crate::window::WindowState;
+
+pub enum RenderTarget {
+    /// Frames are presented to the window.
+    Window(WindowState),
+    /// Frames are drawn to a texture, without a window.
+    Offscreen(OffscreenTarget),
+}
+
+/// A texture the size and format of the context's frames.
+pub struct OffscreenTarget {
+    pub texture: wgpu::Texture,
+}
+
+impl OffscreenTarget {
+    pub fn new(gfx: &GraphicsContext) -> Self {
+        let texture = gfx.device.create_texture(&wgpu::TextureDescriptor {
+            label: Some("Offscreen Target"),
+            size: wgpu::Extent3d {
+                width: gfx.config.width,
+                height: gfx.config.height,
+                depth_or_array_layers: 1,
+            },
+            mip_level_count: 1,
+            sample_count: 1,
+            dimension: wgpu::TextureDimension::D2,
+            format: gfx.config.format,
+            usage: wgpu::TextureUsages::RENDER_ATTACHMENT | wgpu::TextureUsages::COPY_SRC,
+            view_formats: &[],
+        });
+        Self { texture }
+    }
+}
+
+/// A frame being drawn to the target, the surface's frame is shown once it's
+/// presented.
+pub struct TargetFrame {
+    pub view: TextureView,
+    surface_texture: Option<SurfaceTexture>,
+}
+
+impl TargetFrame {
+    pub fn present(self) {
+        if let Some(surface_texture) = self.surface_texture {
+            surface_texture.present();
+        }
+    }
+}
+
+impl RenderTarget {
+    /// Get the texture the next frame is drawn to.
+    pub fn frame(&self) -> Result<TargetFrame, SurfaceError> {
+        let descriptor = wgpu::TextureViewDescriptor::default();
+        Ok(match self {
+            RenderTarget::Window(window) => {
+                let output = window.surface.get_current_texture()?;
+                TargetFrame {
+                    view: output.texture.create_view(&descriptor),
+                    surface_texture: Some(output),
+                }
+            }
+            RenderTarget::Offscreen(target) => TargetFrame {
+                view: target.texture.create_view(&descriptor),
+                surface_texture: None,
+            },
+        })
+    }
+
+    /// Resize the target and the frames of the graphics context.
+    pub fn resize(&mut self, gfx: &mut GraphicsContext, new_size: PhysicalSize<u32>) {
+        match self {
+            RenderTarget::Window(window) => window.resize(gfx, new_size),
+            RenderTarget::Offscreen(target) => {
+                if new_size.width > 0 && new_size.height > 0 {
+                    gfx.config.width = new_size.width;
+                    gfx.config.height = new_size.height;
+                    *target = OffscreenTarget::new(gfx);
+                }
+            }
+        }
+    }
+
+    /// Get the texture of a frame of the target.
+    pub fn frame_texture<'a>(&'a self, frame: &'a TargetFrame) -> &'a wgpu::Texture {
+        match (self, &frame.surface_texture) {
+            (_, Some(surface_texture)) => &surface_texture.texture,
+            (RenderTarget::Offscreen(target), None) => &target.texture,
+            (RenderTarget::Window(_), None) => unreachable!("Window frames have a surface texture"),
+        }
+    }
+
+    pub fn window(&self) -> Option<&Window> {
+        match self {
+            RenderTarget::Window(window) => Some(&window.window),
+            RenderTarget::Offscreen(_) => None,
+        }
+    }
+
+    /// Read the last frame drawn to an offscreen target, the window's frames
+    /// are gone once they are presented.
+    pub fn read_image(&self, gfx: &GraphicsContext) -> anyhow::Result<RgbaImage> {
+        match self {
+            RenderTarget::Window(_) => bail!("Frames drawn to the window can't be read back"),
+            RenderTarget::Offscreen(target) => read_texture(gfx, &target.texture),
+        }
+    }
+}
+
+/// Copy a texture from the GPU into an image, waiting for the copy to finish.
+/// The texture has to have 8 bit RGBA or BGRA texels, and be made with the
+/// `COPY_SRC` usage.
+pub fn read_texture(gfx: &GraphicsContext, texture: &wgpu::Texture) -> anyhow::Result<RgbaImage> {
+    let readback = TextureReadback::new(gfx, texture)?;
+    gfx.device.poll(wgpu::Maintain::Wait);
+    readback
+        .try_image()
+        .context("The readback buffer wasn't mapped")?
+}
+
+/// A copy of a texture being read back from the GPU. The buffer it's copied to
+/// is mapped in the background, while the device is polled, so the frame
+/// doesn't wait for it.
+pub struct TextureReadback {
+    buffer: wgpu::Buffer,
+    width: u32,
+    height: u32,
+    padded_row_size: u32,
+    bgra: bool,
+    mapped: Receiver<Result<(), wgpu::BufferAsyncError>>,
+}
+
+impl TextureReadback {
+    /// Copy the texture to a buffer after the work already submitted to the
+    /// queue, and start mapping it. The texture has to have 8 bit RGBA or BGRA
+    /// texels, and be made with the `COPY_SRC` usage.
+    pub fn new(gfx: &GraphicsContext, texture: &wgpu::Texture) -> anyhow::Result<Self> {
+        let format = texture.format();
+        let bgra = match format {
+            TextureFormat::Rgba8Unorm | TextureFormat::Rgba8UnormSrgb => false,
+            TextureFormat::Bgra8Unorm | TextureFormat::Bgra8UnormSrgb => true,
+            _ => bail!("Textures with the format {:?} can't be read back", format),
+        };
+        if !texture.usage().contains(wgpu::TextureUsages::COPY_SRC) {
+            bail!("The texture wasn't made to be copied from, so it can't be read back");
+        }
+
+        // rows of a copy have to be aligned to 256 bytes
+        let (width, height) = (texture.width(), texture.height());
+        let padded_row_size = (width * 4).next_multiple_of(wgpu::COPY_BYTES_PER_ROW_ALIGNMENT);
+        let buffer = gfx.device.create_buffer(&wgpu::BufferDescriptor {
+            label: Some("Readback Buffer"),
+            size: (padded_row_size * height) as wgpu::BufferAddress,
+            usage: wgpu::BufferUsages::COPY_DST | wgpu::BufferUsages::MAP_READ,
+            mapped_at_creation: false,
+        });
+
+        let mut encoder = gfx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor {
+                label: Some("Readback"),
+            });
+        encoder.copy_texture_to_buffer(
+            texture.as_image_copy(),
+            wgpu::ImageCopyBuffer {
+                buffer: &buffer,
+                layout: wgpu::ImageDataLayout {
+                    offset: 0,
+                    bytes_per_row: Some(padded_row_size),
+                    rows_per_image: Some(height),
+                },
+            },
+            texture.size(),
+        );
+        gfx.queue.submit(std::iter::once(encoder.finish()));
+
+        let (sender, mapped) = std::sync::mpsc::channel();
+        buffer
+            .slice(..)
+            .map_async(wgpu::MapMode::Read, move |result| {
+                let _ = sender.send(result);
+            });
+        Ok(Self {
+            buffer,
+            width,
+            height,
+            padded_row_size,
+            bgra,
+            mapped,
+        })
+    }
+
+    /// Get the image once the buffer is mapped, `None` while it's still being
+    /// mapped. The mapping only finishes while the device is polled.
+    pub fn try_image(&self) -> Option<anyhow::Result<RgbaImage>> {
+        match self.mapped.try_recv() {
+            Ok(result) => Some(
+                result
+                    .context("Failed to map the readback buffer")
+                    .and_then(|_| self.image()),
+            ),
+            Err(TryRecvError::Empty) => None,
+            Err(TryRecvError::Disconnected) => Some(Err(anyhow!(
+                "The readback buffer was dropped before it was mapped"
+            ))),
+        }
+    }
+
+    /// Copy the rows out of the mapped buffer, without their padding.
+    fn image(&self) -> anyhow::Result<RgbaImage> {
+        let row_size = (self.width * 4) as usize;
+        let mut pixels = Vec::with_capacity(row_size * self.height as usize);
+        for row in self
+            .buffer
+            .slice(..)
+            .get_mapped_range()
+            .chunks(self.padded_row_size as usize)
+        {
+            pixels.extend_from_slice(&row[..row_size]);
+        }
+        self.buffer.unmap();
+        if self.bgra {
+            for texel in pixels.chunks_mut(4) {
+                texel.swap(0, 2);
+            }
+        }
+
+        RgbaImage::from_raw(self.width, self.height, pixels)
+            .context("The texture didn't fill the image")
+    }
+}
+
+/// Compare an image to the golden image `tests/golden/<name>.png`, allowing
+/// small differences between adapters. The golden image is only written when
+/// `MCRS_BLESS` is set, a missing one fails.
+#[cfg(test)]
+pub fn assert_golden(name: &str, image: &RgbaImage) {
+    let path = std::path::Path::new(env!("CARGO_MANIFEST_DIR"))
+        .join("tests/golden")
+        .join(format!("{}.png", name));
+    if std::env::var_os("MCRS_BLESS").is_some() {
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        image.save(&path).unwrap();
+        return;
+    }
+    assert!(
+        path.exists(),
+        "There is no golden image {}, run the test with MCRS_BLESS=1 to draw it",
+        path.display()
+    );
+
+    let golden = image::open(&path).unwrap().to_rgba8();
+    let different = if golden.dimensions() == image.dimensions() {
+        golden
+            .pixels()
+            .zip(image.pixels())
+            .filter(|(a, b)| a.0.iter().zip(b.0).any(|(a, b)| a.abs_diff(b) > 16))
+            .count()
+    } else {
+        (image.width() * image.height()) as usize
+    };
+    // up to 1% of the pixels can be off, eg. on the edges of triangles
+    if different * 100 > image.width() as usize * image.height() as usize {
+        let actual = std::env::temp_dir().join(format!("{}.png", name));
+        image.save(&actual).unwrap();
+        panic!(
+            "{} pixels differ from {}, the image drawn is saved to {}",
+            different,
+            path.display(),
+            actual.display()
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn offscreen_read_test() {
+        // rows 200 bytes long are padded when copied
+        let mut gfx = GraphicsContext::for_tests(50, 20);
+        let mut target = RenderTarget::Offscreen(OffscreenTarget::new(&gfx));
+        assert!(target.window().is_none());
+
+        let frame = target.frame().unwrap();
+        let mut encoder = gfx
+            .device
+            .create_command_encoder(&wgpu::CommandEncoderDescriptor { label: None });
+        encoder.begin_render_pass(&wgpu::RenderPassDescriptor {
+            label: None,
+            color_attachments: &[Some(wgpu::RenderPassColorAttachment {
+                view: &frame.view,
+                resolve_target: None,
+                ops: wgpu::Operations {
+                    load: wgpu::LoadOp::Clear(wgpu::Color::RED),
+                    store: true,
+                },
+            })],
+            depth_stencil_attachment: None,
+        });
+        gfx.queue.submit(std::iter::once(encoder.finish()));
+        frame.present();
+
+        let image = target.read_image(&gfx).unwrap();
+        assert_eq!(image.dimensions(), (50, 20));
+        assert!(image.pixels().all(|pixel| pixel.0 == [255, 0, 0, 255]));
+
+        target.resize(&mut gfx, PhysicalSize::new(30, 10));
+        assert_eq!(target.read_image(&gfx).unwrap().dimensions(), (30, 10));
+        assert_eq!((gfx.config.width, gfx.config.height), (30, 10));
+    }
+}
@@ -1,9 +1,11 @@
+use winit::dpi::PhysicalSize;
 use winit::event::Event;
 
 pub trait Renderer<D> {
     fn render(&mut self, game_data: &mut D, delta: f64) -> Result<(), wgpu::SurfaceError>;
 
-    fn resize(&mut self);
+    /// Resize whatever is drawn to, to the new size of the window.
+    fn resize(&mut self, new_size: PhysicalSize<u32>);
 
     fn handle_event(&mut self, event: &Event<()>);
 }
@@ -3,12 +3,10 @@
 use anyhow::*;
 use image::GenericImageView;
 
-use crate::{
-    engine::{
-        render::uniform::{Uniform, UniformData, UniformLayout},
-        resources::load_binary,
-    },
-    window_state,
+use crate::engine::{
+    graphics::GraphicsContext,
+    render::uniform::{Uniform, UniformData, UniformLayout},
+    resources::load_binary,
 };
 
 /// For a texture we need the [texture](wgpu::Texture), [view](wgpu::TextureView), and [sampler](wgpu::Sampler)
@@ -23,15 +21,18 @@ pub struct Texture {
 
 impl Texture {
     /// Create a Texture struct from image bytes.
-    pub fn from_bytes(bytes: &[u8], label: &str) -> Result<Self> {
+    pub fn from_bytes(gfx: &GraphicsContext, bytes: &[u8], label: &str) -> Result<Self> {
         let img = image::load_from_memory(bytes)?;
-        Self::from_image(&img, Some(label))
+        Self::from_image(gfx, &img, Some(label))
     }
 
     /// Create a texture from [loaded image](image::DynamicImage).
-    pub fn from_image(img: &image::DynamicImage, label: Option<&str>) -> Result<Self> {
-        let device = &window_state().device;
-        let queue = &window_state().queue;
+    pub fn from_image(
+        gfx: &GraphicsContext,
+        img: &image::DynamicImage,
+        label: Option<&str>,
+    ) -> Result<Self> {
+        let GraphicsContext { device, queue, .. } = gfx;
 
         let rgba = img.to_rgba8();
         let dimensions = img.dimensions();
@@ -86,8 +87,11 @@ impl Texture {
         })
     }
 
-    pub fn create_render_texture(width: u32, height: u32) -> Self {
-        let device = &window_state().device;
+    /// Create a texture to draw to in the format of the context's frames, that
+    /// can be sampled and read back. It's sampled with bilinear filtering,
+    /// shaders load its texels to draw it without.
+    pub fn create_render_texture(gfx: &GraphicsContext, width: u32, height: u32) -> Self {
+        let device = &gfx.device;
         let label = "Downscaled";
 
         let size = wgpu::Extent3d {
@@ -102,9 +106,10 @@ impl Texture {
             mip_level_count: 1,
             sample_count: 1,
             dimension: wgpu::TextureDimension::D2,
-            format: wgpu::TextureFormat::Bgra8UnormSrgb,
+            format: gfx.config.format,
             usage: wgpu::TextureUsages::RENDER_ATTACHMENT // 3.
-                        | wgpu::TextureUsages::TEXTURE_BINDING,
+                        | wgpu::TextureUsages::TEXTURE_BINDING
+                        | wgpu::TextureUsages::COPY_SRC,
             view_formats: &[],
         };
         let texture = device.create_texture(&desc);
@@ -115,8 +120,8 @@ impl Texture {
             address_mode_u: wgpu::AddressMode::ClampToEdge,
             address_mode_v: wgpu::AddressMode::ClampToEdge,
             address_mode_w: wgpu::AddressMode::ClampToEdge,
-            mag_filter: wgpu::FilterMode::Nearest,
-            min_filter: wgpu::FilterMode::Nearest,
+            mag_filter: wgpu::FilterMode::Linear,
+            min_filter: wgpu::FilterMode::Linear,
             mipmap_filter: wgpu::FilterMode::Nearest,
             compare: None, // 5.
             lod_min_clamp: 0.0,
@@ -182,8 +187,7 @@ impl Texture {
     }
 
     /// Create a uniform layout for the texture.
-    pub fn create_layout(location: u32) -> UniformLayout {
-        let device = &window_state().device;
+    pub fn create_layout(device: &wgpu::Device, location: u32) -> UniformLayout {
         UniformLayout {
             layout: device.create_bind_group_layout(&wgpu::BindGroupLayoutDescriptor {
                 entries: &[
@@ -211,9 +215,7 @@ impl Texture {
     }
 
     /// Consume the Texture struct and return a [Uniform](Uniform).
-    pub fn uniform(self, layout: &UniformLayout) -> Uniform {
-        let device = &window_state().device;
-
+    pub fn uniform(self, device: &wgpu::Device, layout: &UniformLayout) -> Uniform {
         let texture_bind_group = device.create_bind_group(&wgpu::BindGroupDescriptor {
             layout: &layout.layout,
             entries: &[
@@ -237,11 +239,11 @@ impl Texture {
     }
 
     /// Create a Texture from the specified file. Will only check in the `assets` folder.
-    pub async fn load(src: &str) -> Self {
+    pub async fn load(gfx: &GraphicsContext, src: &str) -> Self {
         let texture_bytes = load_binary(src, true)
             .await
             .expect(&format!("Error loading binary file: {}", src));
-        Texture::from_bytes(texture_bytes.as_slice(), src)
+        Texture::from_bytes(gfx, texture_bytes.as_slice(), src)
             .expect(&format!("Error loading image from bytes: {}", src))
     }
 }
@@ -1,7 +1,13 @@
-use winit::window::Window;
+use std::path::Path;
+
+use anyhow::{bail, Context};
+use winit::dpi::PhysicalSize;
+use winit::window::Fullscreen;
 use winit::{event::*, event_loop::EventLoop, window::WindowBuilder};
 
 mod chunk;
+pub mod cli;
+mod console;
 mod engine;
 mod physics;
 mod player;
@@ -9,63 +15,64 @@ mod util;
 mod window;
 mod world;
 mod world_renderer;
+pub mod worldgen;
+use crate::engine::graphics::GraphicsContext;
+use crate::engine::render::render_target::{OffscreenTarget, RenderTarget};
 use crate::engine::render::renderer::Renderer;
 use crate::window::WindowState;
 
+pub use crate::chunk::settings::{GeneratorKind, WorldGenSettings, WorldPreset};
+pub use crate::cli::GameOptions;
+
 #[cfg(target_arch = "wasm32")]
 use wasm_bindgen::prelude::*;
 
 // use crate::engine::test_render_initialization;
 
-/// We create a static mutable variable for the window state.
-/// The state doesn't really change too often.\
-///
-/// We figure out what this is very early on in runtime.
-/// We maay later change this to use OnceCell.
-static mut WINDOW_STATE: Option<WindowState> = None;
-
-// these functions aren't the most sound,
-// they're just more for ergonomics
-async fn init_window_state(window: Window) {
-    unsafe {
-        WINDOW_STATE = Some(WindowState::new(window).await);
-    }
-}
-pub fn window_state<'a>() -> &'a WindowState {
-    unsafe {
-        WINDOW_STATE
-            .as_ref()
-            .expect("Window state is uninitialized.")
-    }
-}
-pub fn window_state_mut<'a>() -> &'a mut WindowState {
-    unsafe {
-        WINDOW_STATE
-            .as_mut()
-            .expect("Window state is uninitialized.")
-    }
-}
-
 #[cfg_attr(target_arch = "wasm32", wasm_bindgen(start))]
 pub async fn run() {
+    run_with_options(GameOptions::default()).await
+}
+
+/// Open the window and play with the options picked on the command line.
+pub async fn run_with_options(options: GameOptions) {
     // set logging
     cfg_if::cfg_if! {
         if #[cfg(target_arch = "wasm32")] {
             std::panic::set_hook(Box::new(console_error_panic_hook::hook));
             console_log::init_with_level(log::Level::Warn).expect("Couldn't initialize logger");
         } else {
-            env_logger::init();
+            let mut logger = env_logger::Builder::from_default_env();
+            if let Some(level) = options.log_level {
+                logger.filter_level(level);
+            }
+            logger.init();
         }
     }
 
     // init window
     let event_loop = EventLoop::new();
-    let window = WindowBuilder::new().build(&event_loop).unwrap();
+    let mut window_builder = WindowBuilder::new();
+    if let Some(size) = options.window_size {
+        window_builder = window_builder.with_inner_size(size);
+    }
+    if options.fullscreen {
+        window_builder = window_builder.with_fullscreen(Some(Fullscreen::Borderless(None)));
+    }
+    let window = window_builder.build(&event_loop).unwrap();
     window.set_title("MCRS");
-    init_window_state(window).await;
+    // the renderer owns the window and the graphics context from here on
+    let (window_state, gfx) = WindowState::new(window, options.vsync, options.fallback_adapter)
+        .await
+        .expect("Failed to set up the graphics device");
 
     // init game logic
-    let mut game_state = world::init().await;
+    let mut game_state = world::init(&options, gfx, RenderTarget::Window(window_state)).await;
+    let window_id = game_state.renderer.target.window().unwrap().id();
+    #[cfg(not(target_arch = "wasm32"))]
+    {
+        game_state.data.console = Some(console::Console::spawn());
+    }
 
     // let state = state::GameState::<(), ()>::new(renderer, ());
 
@@ -74,6 +81,7 @@ pub async fn run() {
         // Winit prevents sizing with CSS, so we have to set
         // the size manually when on web.
         use winit::dpi::PhysicalSize;
+        let window = game_state.renderer.target.window().unwrap();
         window.set_inner_size(PhysicalSize::new(450, 400));
 
         use winit::platform::web::WindowExtWebSys;
@@ -91,9 +99,9 @@ pub async fn run() {
     event_loop.run(move |event, _, control_flow| {
         match event {
             Event::WindowEvent {
-                window_id,
+                window_id: id,
                 ref event,
-            } if window_id == window_state().window().id() => {
+            } if id == window_id => {
                 // calc frame delta
 
                 game_state.input.handle(event);
@@ -101,7 +109,10 @@ pub async fn run() {
                 if true {
                     // !window_state.input(event) {
                     match event {
-                        WindowEvent::CloseRequested => control_flow.set_exit(),
+                        WindowEvent::CloseRequested => {
+                            chunk::loading::save_loaded_chunks(&mut game_state.data);
+                            control_flow.set_exit();
+                        }
                         WindowEvent::Resized(physical_size) => {
                             game_state.resize(*physical_size);
                             game_state.queue_event(world::Event::Resized);
@@ -120,7 +131,7 @@ pub async fn run() {
             } => {
                 game_state.input.mouse_delta(delta);
             }
-            Event::RedrawRequested(window_id) if window_id == window_state().window().id() => {
+            Event::RedrawRequested(id) if id == window_id => {
                 game_state.delta_end();
                 game_state.delta_start();
 
@@ -138,7 +149,7 @@ pub async fn run() {
                 match res {
                     Ok(_) => {}
                     Err(wgpu::SurfaceError::Lost) => {
-                        let size = window_state().size;
+                        let size = game_state.renderer.target.window().unwrap().inner_size();
                         game_state.resize(size);
                         game_state.queue_event(world::Event::Resized);
                     }
@@ -148,7 +159,9 @@ pub async fn run() {
                 */
             }
             Event::MainEventsCleared => {
-                window_state().window().request_redraw();
+                if let Some(window) = game_state.renderer.target.window() {
+                    window.request_redraw();
+                }
             }
             _ => {}
         };
@@ -158,6 +171,58 @@ pub async fn run() {
     })
 }
 
+/// Time each frame of a headless run is stepped by, in milliseconds.
+const HEADLESS_FRAME_TIME: f64 = 1000.0 / 60.0;
+/// Longest a headless run waits for the world to load.
+const HEADLESS_TIMEOUT: std::time::Duration = std::time::Duration::from_secs(600);
+
+/// Draw the world with the options without a window, once the chunks around
+/// the player are loaded and meshed, and save the frame to an image file.
+/// The frame is the size of the window, 1280x720 if it isn't picked.
+pub async fn run_headless(options: GameOptions, path: &Path) -> anyhow::Result<()> {
+    let _ = env_logger::Builder::from_default_env()
+        .filter_level(options.log_level.unwrap_or(log::LevelFilter::Warn))
+        .try_init();
+
+    let size = options.window_size.unwrap_or(PhysicalSize::new(1280, 720));
+    let gfx = GraphicsContext::headless(size.width, size.height, options.fallback_adapter).await?;
+    let target = RenderTarget::Offscreen(OffscreenTarget::new(&gfx));
+    let mut game_state = world::init(&options, gfx, target).await;
+    game_state.delta = HEADLESS_FRAME_TIME;
+
+    // step the game at 60 frames a second until nothing is left to load or
+    // mesh, the thread pool loads the chunks in the meantime
+    let start = std::time::Instant::now();
+    loop {
+        game_state.process_events();
+        game_state.queue_event(world::Event::Tick);
+
+        let data = &game_state.data;
+        let loaded = !data.loaded_chunks.is_empty()
+            && data.loading.is_empty()
+            && data.dirty_chunks.is_empty()
+            && data.done_loading.lock(0).unwrap().is_empty();
+        if loaded {
+            break;
+        }
+        if start.elapsed() > HEADLESS_TIMEOUT {
+            bail!("The world didn't load in {:?}", HEADLESS_TIMEOUT);
+        }
+        std::thread::sleep(std::time::Duration::from_secs_f64(
+            HEADLESS_FRAME_TIME / 1000.0,
+        ));
+    }
+
+    let renderer = &mut game_state.renderer;
+    renderer.render(&mut game_state.data, HEADLESS_FRAME_TIME)?;
+    let image = renderer.target.read_image(&renderer.gfx)?;
+    image
+        .save(path)
+        .with_context(|| format!("Failed to save the frame to {}", path.display()))?;
+    log::info!("Saved the frame to {}", path.display());
+    Ok(())
+}
+
 pub fn add(left: usize, right: usize) -> usize {
     left + right
 }
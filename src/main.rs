@@ -1,5 +1,20 @@
-use mcrs::run;
+use clap::Parser;
+use mcrs::{cli::Cli, run_headless, run_with_options};
 
 fn main() {
-    pollster::block_on(run());
+    let options = match Cli::parse().options() {
+        Ok(options) => options,
+        Err(e) => {
+            eprintln!("error: {:#}", e);
+            std::process::exit(2);
+        }
+    };
+    if let Some(path) = options.screenshot.clone() {
+        if let Err(e) = pollster::block_on(run_headless(options, &path)) {
+            eprintln!("error: {:#}", e);
+            std::process::exit(1);
+        }
+        return;
+    }
+    pollster::block_on(run_with_options(options));
 }
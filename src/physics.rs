@@ -5,6 +5,7 @@ use rapier3d::prelude::*;
 use std::collections::HashMap;
 
 use crate::{
+    chunk::ChunkPos,
     engine::input::Input,
     world::{Event, GameData},
     world_renderer::WorldRenderer,
@@ -16,6 +17,8 @@ pub struct PhysicsEngine {
 
     colliders_handles: HashMap<String, ColliderHandle>,
     rigidbody_handles: HashMap<String, RigidBodyHandle>,
+    chunk_collider_handles: HashMap<ChunkPos, ColliderHandle>,
+    chunk_fluid_handles: HashMap<ChunkPos, ColliderHandle>,
 
     pub gravity: Vector<Real>,
 
@@ -38,6 +41,8 @@ impl PhysicsEngine {
 
             rigidbody_handles: HashMap::new(),
             colliders_handles: HashMap::new(),
+            chunk_collider_handles: HashMap::new(),
+            chunk_fluid_handles: HashMap::new(),
 
             gravity: vector![0.0, -9.81, 0.0],
 
@@ -87,6 +92,43 @@ impl PhysicsEngine {
         }
     }
 
+    /// Insert the collider of a chunk, replacing the chunk's old collider.
+    pub fn insert_chunk_collider(&mut self, chunk_pos: ChunkPos, collider: Collider) {
+        self.remove_chunk_collider(&chunk_pos);
+        let handle = self.collider_set.insert(collider);
+        self.chunk_collider_handles.insert(chunk_pos, handle);
+    }
+
+    /// Insert the sensor collider of a chunk's fluids, replacing the old one.
+    /// Chunks without fluids have no fluid collider.
+    pub fn insert_chunk_fluid_collider(&mut self, chunk_pos: ChunkPos, collider: Option<Collider>) {
+        if let Some(handle) = self.chunk_fluid_handles.remove(&chunk_pos) {
+            self.collider_set.remove(
+                handle,
+                &mut self.island_manager,
+                &mut self.rigidbody_set,
+                false,
+            );
+        }
+        if let Some(collider) = collider {
+            let handle = self.collider_set.insert(collider);
+            self.chunk_fluid_handles.insert(chunk_pos, handle);
+        }
+    }
+
+    /// Remove the collider and fluid collider of a chunk.
+    pub fn remove_chunk_collider(&mut self, chunk_pos: &ChunkPos) {
+        if let Some(handle) = self.chunk_collider_handles.remove(chunk_pos) {
+            self.collider_set.remove(
+                handle,
+                &mut self.island_manager,
+                &mut self.rigidbody_set,
+                false,
+            );
+        }
+        self.insert_chunk_fluid_collider(*chunk_pos, None);
+    }
+
     pub fn insert_rigid_body(&mut self, id: String, rigidbody: RigidBody) {
         let handle = self.rigidbody_set.insert(rigidbody);
         self.rigidbody_handles.insert(id, handle);
@@ -151,6 +193,17 @@ impl PhysicsEngine {
 
         false
     }
+
+    /// Check if the collider is inside of a sensor, like the fluids of a chunk.
+    pub fn is_intersecting(&self, id: &str) -> bool {
+        match self.colliders_handles.get(id) {
+            Some(handle) => self
+                .narrow_phase
+                .intersections_with(*handle)
+                .any(|(_, _, intersecting)| intersecting),
+            None => false,
+        }
+    }
 }
 
 pub fn simulate_physics(
@@ -1,17 +1,24 @@
 use rapier3d::prelude::*;
 
 use crate::{
-    chunk::{chunk_id, chunk_position, player_to_position, Position},
+    chunk::{
+        chunk_position, editing::edit_block, get_block, player_to_position, raycast::raycast,
+        ChunkPos, Position,
+    },
     engine::{
         input::Input,
         render::uniform::{Uniform, UniformData},
     },
     physics::PhysicsEngine,
-    window_state,
     world::{Event, GameData},
     world_renderer::WorldRenderer,
 };
 
+/// How much of the gravity pulls on the player while they are in a fluid.
+const FLUID_GRAVITY_SCALE: f32 = 0.2;
+/// Fastest the player sinks in a fluid.
+const FLUID_SINK_SPEED: f32 = 2.0;
+
 pub struct Player {
     pub yaw: f32,
     pub pitch: f32,
@@ -20,7 +27,11 @@ pub struct Player {
     pub max_jump: f32,
     sensitivity: f32,
     pub is_flying: bool,
-    last_chunk: Position,
+    /// How far away blocks can be broken or placed.
+    pub reach: f32,
+    /// The block id placed with right click.
+    pub selected_block: u32,
+    last_chunk: ChunkPos,
 }
 
 impl Player {
@@ -33,7 +44,9 @@ impl Player {
             max_jump: 1.25,
             sensitivity: 0.2,
             is_flying: true,
-            last_chunk: (0, 0, 0),
+            reach: 6.0,
+            selected_block: 2,
+            last_chunk: ChunkPos::new(0, 0, 0),
         }
     }
 }
@@ -119,10 +132,10 @@ pub fn simulate_player(
         .get_rigid_body("player".to_string())
         .unwrap()
         .translation();
-    let current_chunk = chunk_id(&chunk_position(
+    let current_chunk = chunk_position(
         &data.chunk_config,
         &(pos.x as i32, pos.y as i32, pos.z as i32),
-    ));
+    );
     if data.focused && !data.loaded_chunks.get(&current_chunk).is_none() {
         data.physics_engine.step(delta);
     }
@@ -149,10 +162,21 @@ pub fn player_input(
         .linvel();
     let mut output_vel = vector!(input_vel.x, physics_vel.y, input_vel.z);
 
+    // fluids don't collide with the player, they swim through them instead
+    let swimming = !data.player.is_flying && data.physics_engine.is_intersecting("player");
+
     if on_ground(&data.physics_engine) {
         output_vel.y += input_vel.y;
     }
 
+    if swimming {
+        output_vel.y = output_vel.y.max(-FLUID_SINK_SPEED);
+        // swim up with jump and down with crouch, at half the speed
+        if input_vel.y != 0.0 {
+            output_vel.y = input_vel.y * 0.5;
+        }
+    }
+
     if data.player.is_flying {
         output_vel.x = input_vel.x;
         output_vel.y = input_vel.y;
@@ -164,6 +188,7 @@ pub fn player_input(
         .get_mut_rigid_body("player".to_string())
         .unwrap();
     player.set_linvel(output_vel, true);
+    player.set_gravity_scale(if swimming { FLUID_GRAVITY_SCALE } else { 1.0 }, true);
 
     // change look direction
     if input.movement.0 != 0.0 || input.movement.1 != 0.0 {
@@ -206,6 +231,104 @@ pub fn player_changed_chunk(
     }
 }
 
+/// Get the world position of the camera.
+pub fn camera_position(physics_engine: &PhysicsEngine) -> glam::Vec3 {
+    // we use center of mass because then we clip less into walls
+    let p_t = physics_engine
+        .get_rigid_body("player".to_string())
+        .unwrap()
+        .center_of_mass(); //.translation();
+                           // then we translate the camera to where we want
+    glam::vec3(p_t.x + 0.5, p_t.y + 1.25, p_t.z + 0.5)
+}
+
+/// Get the direction the camera is looking in from the player's yaw and pitch.
+fn camera_facing(player: &Player) -> glam::Vec3 {
+    let (yaw_sin, yaw_cos) = player.yaw.sin_cos();
+    let (pitch_sin, pitch_cos) = player.pitch.sin_cos();
+    glam::vec3(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize()
+}
+
+/// Check if a block at the world position would overlap the player's collider.
+fn overlaps_player(physics_engine: &PhysicsEngine, position: &Position) -> bool {
+    let p_t = physics_engine
+        .get_rigid_body("player".to_string())
+        .unwrap()
+        .translation();
+    // blocks are drawn half a block offset from their colliders
+    let (x, y, z) = (p_t.x + 0.5, p_t.y + 0.5, p_t.z + 0.5);
+    let (radius, half_height) = (0.25, 0.75);
+
+    (position.0 as f32) < x + radius
+        && (position.0 + 1) as f32 > x - radius
+        && (position.1 as f32) < y + half_height
+        && (position.1 + 1) as f32 > y - half_height
+        && (position.2 as f32) < z + radius
+        && (position.2 + 1) as f32 > z - radius
+}
+
+/// System for breaking and placing blocks, left click breaks the block the
+/// player is looking at and right click places one against the face looked at.
+pub fn edit_world(
+    _renderer: &mut WorldRenderer,
+    input: &mut Input,
+    data: &mut GameData,
+    _queue: &mut Vec<Event>,
+    delta: f64,
+) {
+    if !data.focused {
+        return;
+    }
+
+    let left = input.get_click(winit::event::MouseButton::Left);
+    let right = input.get_click(winit::event::MouseButton::Right);
+    let breaking = left <= delta && left > 0.0;
+    let placing = right <= delta && right > 0.0;
+    if !breaking && !placing {
+        return;
+    }
+
+    let config = &data.chunk_config;
+    let loaded_chunks = &data.loaded_chunks;
+    // the ray goes through fluids, so blocks can be placed in them
+    let hit = raycast(
+        camera_position(&data.physics_engine),
+        camera_facing(&data.player),
+        data.player.reach,
+        |pos| {
+            let block_id = get_block(config, loaded_chunks, pos);
+            block_id != 0
+                && config
+                    .dict
+                    .get(&block_id)
+                    .is_none_or(|block| block.fluid.is_none())
+        },
+    );
+    let hit = match hit {
+        Some(hit) => hit,
+        None => return,
+    };
+
+    if breaking {
+        edit_block(data, &hit.position, 0);
+        return;
+    }
+
+    // the ray started inside of a block, there is no face to place against
+    if hit.normal == (0, 0, 0) {
+        return;
+    }
+    let position = (
+        hit.position.0 + hit.normal.0,
+        hit.position.1 + hit.normal.1,
+        hit.position.2 + hit.normal.2,
+    );
+    if overlaps_player(&data.physics_engine, &position) {
+        return;
+    }
+    edit_block(data, &position, data.player.selected_block);
+}
+
 /// Update the player camera with look position and world position.
 pub fn update_camera(
     renderer: &mut WorldRenderer,
@@ -221,24 +344,13 @@ pub fn update_camera(
     {
         let mat = m.matrix_mut();
 
-        // we use center of mass because then we clip less into walls
-        let p_t = data
-            .physics_engine
-            .get_mut_rigid_body("player".to_string())
-            .unwrap()
-            .center_of_mass(); //.translation();
-                               // then we translate the camera to where we want
-        let (x, y, z) = (p_t.x + 0.5, p_t.y + 1.25, p_t.z + 0.5);
-        let position = glam::vec3(x, y, z);
+        let position = camera_position(&data.physics_engine);
         let up = glam::vec3(0.0, 1.0, 0.0);
-
-        let (yaw_sin, yaw_cos) = data.player.yaw.sin_cos();
-        let (pitch_sin, pitch_cos) = data.player.pitch.sin_cos();
-        let facing = glam::vec3(pitch_cos * yaw_cos, pitch_sin, pitch_cos * yaw_sin).normalize();
+        let facing = camera_facing(&data.player);
 
         let look = glam::Mat4::look_to_rh(position, facing, up);
         *mat = look;
-        m.update_buffer();
+        m.update_buffer(&renderer.gfx.queue);
     }
 }
 
@@ -255,21 +367,20 @@ pub fn update_perspective(
         ..
     }) = renderer.chunk_render_pass.uniforms.get_mut("projection")
     {
-        let config = &window_state().config;
         let mat = m.matrix_mut();
         *mat = glam::Mat4::perspective_rh_gl(
             data.player.fov,
-            config.width as f32 / config.height as f32,
+            renderer.gfx.aspect_ratio(),
             0.1,
             1000.0,
         );
-        m.update_buffer();
+        m.update_buffer(&renderer.gfx.queue);
     }
 }
 
 /// System for tracking if the user is actively using the window.
 pub fn focus_window(
-    _renderer: &mut WorldRenderer,
+    renderer: &mut WorldRenderer,
     input: &mut Input,
     data: &mut GameData,
     _queue: &mut Vec<Event>,
@@ -281,11 +392,12 @@ pub fn focus_window(
         if click > 0.0 {
             data.focused = true;
 
-            let window = &window_state().window;
-            window
-                .set_cursor_grab(winit::window::CursorGrabMode::Confined)
-                .unwrap();
-            window.set_cursor_visible(false);
+            if let Some(window) = renderer.target.window() {
+                window
+                    .set_cursor_grab(winit::window::CursorGrabMode::Confined)
+                    .unwrap();
+                window.set_cursor_visible(false);
+            }
         }
     }
 
@@ -295,11 +407,12 @@ pub fn focus_window(
         if esc > 0.0 {
             data.focused = false;
 
-            let window = &window_state().window;
-            window
-                .set_cursor_grab(winit::window::CursorGrabMode::None)
-                .unwrap();
-            window.set_cursor_visible(true);
+            if let Some(window) = renderer.target.window() {
+                window
+                    .set_cursor_grab(winit::window::CursorGrabMode::None)
+                    .unwrap();
+                window.set_cursor_visible(true);
+            }
         }
     }
 }
@@ -1 +1,11 @@
 pub mod vec_set;
+
+/// Get a folder in the temporary folder for a test, named after the test and
+/// the process so tests running at the same time never share one. Anything
+/// left in it by an earlier run is removed, the folder itself isn't created.
+#[cfg(test)]
+pub fn test_directory(name: &str) -> std::path::PathBuf {
+    let path = std::env::temp_dir().join(format!("mcrs-{}-{}", name, std::process::id()));
+    let _ = std::fs::remove_dir_all(&path);
+    path
+}
@@ -1,64 +1,34 @@
-use wgpu::{Device, Queue, Surface, SurfaceConfiguration};
+use wgpu::{Surface, SurfaceConfiguration};
 use winit::dpi::PhysicalSize;
 use winit::window::Window;
 
+use crate::engine::graphics::{create_instance, request_device, GraphicsContext};
+
+/// The window and the surface frames are presented to.
 pub struct WindowState {
     pub surface: Surface,
-    pub device: Device,
-    pub queue: Queue,
-    pub config: SurfaceConfiguration,
     pub size: PhysicalSize<u32>,
     pub window: Window,
 }
 
 impl WindowState {
-    pub async fn new(window: Window) -> Self {
-        use wgpu::{
-            Backends, DeviceDescriptor, Features, Instance, InstanceDescriptor, Limits,
-            PowerPreference, RequestAdapterOptions, TextureUsages,
-        };
+    /// Create the surface for the window and the device that draws to it,
+    /// `vsync` waits for the display to show each frame before presenting the
+    /// next, and `force_fallback_adapter` draws with wgpu's software adapter.
+    pub async fn new(
+        window: Window,
+        vsync: bool,
+        force_fallback_adapter: bool,
+    ) -> anyhow::Result<(Self, GraphicsContext)> {
+        use wgpu::{PresentMode, TextureUsages};
 
         let size = window.inner_size();
 
         // instance is handle to gpu
-        // backends is basically architecture target
-        let instance = Instance::new(InstanceDescriptor {
-            backends: Backends::all(),
-            dx12_shader_compiler: Default::default(),
-        });
-
-        let surface = unsafe { instance.create_surface(&window) }.unwrap();
-
-        // this may fail if no adapter is found with the preferences
-        // you can
-        log::warn!("Looking for an adapter, may fail if none exists with set adaptor options.");
-        let adapter = instance
-            .request_adapter(&RequestAdapterOptions {
-                power_preference: PowerPreference::default(),
-                compatible_surface: Some(&surface),
-                force_fallback_adapter: false,
-            })
-            .await
-            .unwrap();
-
-        log::warn!("Setting features, may fail if host does not have features...");
-        let (device, queue) = adapter
-            .request_device(
-                &DeviceDescriptor {
-                    features: Features::POLYGON_MODE_LINE,
-                    // WebGL doesn't support all of wgpu's features, so if
-                    // we're building for the web we'll have to disable some.
-                    limits: if cfg!(target_arch = "wasm32") {
-                        Limits::downlevel_webgl2_defaults()
-                    } else {
-                        Limits::default()
-                    },
-                    label: None,
-                },
-                None, // Trace path
-            )
-            .await
-            .unwrap();
+        let instance = create_instance();
+        let surface = unsafe { instance.create_surface(&window) }?;
+        let (adapter, device, queue) =
+            request_device(&instance, Some(&surface), force_fallback_adapter).await?;
 
         let surface_caps = surface.get_capabilities(&adapter);
         // Shader code in this tutorial assumes an sRGB surface texture. Using a different
@@ -71,37 +41,49 @@ impl WindowState {
             .find(|f| f.is_srgb())
             .unwrap_or(surface_caps.formats[0]);
         log::info!("{:#?}", &surface_caps.alpha_modes);
+        // every surface supports fifo, without vsync the surface's preferred
+        // mode is used
+        let present_mode = if vsync {
+            PresentMode::Fifo
+        } else {
+            surface_caps.present_modes[0]
+        };
         let config = SurfaceConfiguration {
-            usage: TextureUsages::RENDER_ATTACHMENT,
+            // frames can only be read back for screenshots if the surface
+            // lets them be copied
+            usage: TextureUsages::RENDER_ATTACHMENT
+                | (surface_caps.usages & TextureUsages::COPY_SRC),
             format: surface_format,
             width: size.width,
             height: size.height,
-            present_mode: surface_caps.present_modes[0],
+            present_mode,
             alpha_mode: surface_caps.alpha_modes[0],
             view_formats: vec![],
         };
         surface.configure(&device, &config);
 
-        Self {
-            window,
-            device,
-            queue,
-            surface,
-            config,
-            size,
-        }
-    }
-
-    pub fn window(&self) -> &Window {
-        &self.window
+        Ok((
+            Self {
+                window,
+                surface,
+                size,
+            },
+            GraphicsContext {
+                device,
+                queue,
+                config,
+            },
+        ))
     }
 
-    pub fn resize(&mut self, new_size: PhysicalSize<u32>) {
+    /// Resize the surface and the frames of the graphics context to the new
+    /// size of the window.
+    pub fn resize(&mut self, gfx: &mut GraphicsContext, new_size: PhysicalSize<u32>) {
         if new_size.width > 0 && new_size.height > 0 {
             self.size = new_size;
-            self.config.width = new_size.width;
-            self.config.height = new_size.height;
-            self.surface.configure(&self.device, &self.config);
+            gfx.config.width = new_size.width;
+            gfx.config.height = new_size.height;
+            self.surface.configure(&gfx.device, &gfx.config);
         }
     }
 }
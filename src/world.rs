@@ -1,23 +1,34 @@
 use priomutex::Mutex;
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 use std::sync::Arc;
 
-use crate::chunk::block::{Block, BlockDictionary};
-use crate::chunk::cube_model::cube_model;
-use crate::chunk::culling::VisibilityGraph;
+use crate::chunk::biome::biome_at;
+use crate::chunk::block::{load_block_dictionary, RenderLayer};
+use crate::chunk::chunk_renderer::render_group_name;
 use crate::chunk::culling::VisibilityGraphStorage;
-use crate::chunk::loading::check_done_load_world;
-use crate::chunk::loading::load_world;
+use crate::chunk::fluids::{flow_fluids, FluidSimulation};
+use crate::chunk::lighting::LightStorage;
+use crate::chunk::loading::{check_done_load_world, rebuild_dirty_chunks, reload_blocks};
+use crate::chunk::loading::{load_world, DoneLoading};
 use crate::chunk::meshing;
+use crate::chunk::player_to_position;
+use crate::chunk::storage::RegionStorage;
+use crate::chunk::structures::load_structures;
 use crate::chunk::ChunkConfig;
-use crate::chunk::ChunkData;
+use crate::chunk::ChunkPos;
 use crate::chunk::ChunkStorage;
-use crate::chunk::Position;
+use crate::chunk::CHUNK_DEPTH;
+use crate::chunk::LOD;
+use crate::cli::GameOptions;
+use crate::console::{run_console_commands, Console};
 use crate::engine::game_state::GameState;
+use crate::engine::graphics::GraphicsContext;
 use crate::engine::input::Input;
 use crate::engine::matrix::Matrix;
+use crate::engine::render::capture::{CaptureSettings, CaptureSource};
+use crate::engine::render::frame_render_pass::{RenderScale, UpscaleFilter};
 use crate::engine::render::render_group::RenderGroupBuilder;
-use crate::engine::render::render_object::RenderObject;
+use crate::engine::render::render_target::RenderTarget;
 use crate::engine::resources::load_string;
 use crate::engine::texture;
 
@@ -25,12 +36,11 @@ use crate::physics::PhysicsEngine;
 use crate::player::create_player;
 use crate::player::player_changed_chunk;
 use crate::player::simulate_player;
-use crate::player::{focus_window, player_input, update_camera, update_perspective, Player};
-use crate::window_state;
-use crate::world_renderer::toggle_debug_menu;
+use crate::player::{
+    edit_world, focus_window, player_input, update_camera, update_perspective, Player,
+};
 use crate::world_renderer::WorldRenderer;
-
-use rapier3d::prelude::*;
+use crate::world_renderer::{take_screenshot, toggle_debug_menu};
 
 #[derive(PartialEq, Eq, Hash)]
 pub enum Event {
@@ -46,20 +56,37 @@ pub enum Event {
 pub struct GameData {
     // component data
     pub show_debug_menu: bool,
+    // the size the world is drawn at, changed from the debug menu
+    pub render_scale: RenderScale,
+    // the downscale factor on the debug menu's slider, applied to the render
+    // scale once the slider is let go so the textures aren't made every frame
+    pub downscale_edit: f32,
 
     // chunks
     pub loaded_chunks: ChunkStorage,
     pub visibility_graphs: VisibilityGraphStorage,
 
-    pub loading: HashSet<String>,
-    pub done_loading: Arc<
-        Mutex<
-            indexmap::IndexMap<
-                String,
-                (Position, ChunkData, VisibilityGraph, RenderObject, Collider),
-            >,
-        >,
-    >,
+    pub loading: HashSet<ChunkPos>,
+    pub done_loading: DoneLoading,
+    // loaded chunks whose blocks changed and need to be rebuilt
+    pub dirty_chunks: indexmap::IndexSet<ChunkPos>,
+    // the level of detail each chunk was last meshed at
+    pub chunk_lods: HashMap<ChunkPos, LOD>,
+    // the sky and block light of the loaded chunks
+    pub light: LightStorage,
+    // loaded chunks whose light was thrown away, to be lit again on the
+    // thread pool
+    pub unlit_chunks: indexmap::IndexSet<ChunkPos>,
+    // fluid blocks waiting to flow
+    pub fluids: FluidSimulation,
+
+    // persistence
+    pub region_storage: Arc<RegionStorage>,
+    pub unsaved_chunks: HashSet<ChunkPos>,
+    // screenshots and captures of every few frames
+    pub capture: CaptureSettings,
+    // commands typed into the terminal, only read when the game has a window
+    pub console: Option<Console>,
 
     // physics
     pub physics_engine: PhysicsEngine,
@@ -79,15 +106,22 @@ pub struct GameData {
     pub focused: bool,
 }
 
-use libnoise::prelude::*;
-
-pub async fn init() -> GameState<GameData, WorldRenderer, Event> {
-    let seed = 123456789;
+/// Set up the game, drawn with the graphics context to the window or an
+/// offscreen target.
+pub async fn init(
+    options: &GameOptions,
+    gfx: GraphicsContext,
+    target: RenderTarget,
+) -> GameState<GameData, WorldRenderer, Event> {
+    let dict = load_block_dictionary(0.0625).await.unwrap();
+    let structures = load_structures(&dict).await.unwrap();
     let frame_shader_source = load_string("frame.wgsl", true).await.unwrap();
     let mut game_state = GameState::new(
-        WorldRenderer::new(&frame_shader_source),
+        WorldRenderer::new(gfx, target, &frame_shader_source, options.render_scale),
         GameData {
             show_debug_menu: false,
+            render_scale: options.render_scale,
+            downscale_edit: options.render_scale.downscale_factor,
 
             // chunk_data: HashMap::new(),
             loaded_chunks: ChunkStorage::new(),
@@ -95,6 +129,16 @@ pub async fn init() -> GameState<GameData, WorldRenderer, Event> {
 
             loading: HashSet::new(),
             done_loading: Arc::new(Mutex::new(indexmap::IndexMap::new())),
+            dirty_chunks: indexmap::IndexSet::new(),
+            chunk_lods: HashMap::new(),
+            light: LightStorage::new(),
+            unlit_chunks: indexmap::IndexSet::new(),
+            fluids: FluidSimulation::new(),
+
+            region_storage: Arc::new(RegionStorage::new(&options.world_dir)),
+            unsaved_chunks: HashSet::new(),
+            capture: CaptureSettings::new(options.capture_dir.clone(), options.capture_every),
+            console: None,
 
             physics_engine: PhysicsEngine::new(),
 
@@ -104,42 +148,15 @@ pub async fn init() -> GameState<GameData, WorldRenderer, Event> {
                 .unwrap(),
 
             chunk_config: Arc::new(ChunkConfig {
-                noise: Source::simplex(seed), // apply a closure to the noise Source::worley(123), //Arc.fbm(3, 0.013, 2.0, 0.5); // ::new(Worley::new(0)), // |[x, y, z]| f64::sin(x) + f64::sin(y) + f64::sin(z),
-                noise_amplitude: (0.005, 0.005, 0.005),
-                depth: 32,
-                load_radius: 4,
-
-                uv_size: 0.0625,
-                dict: BlockDictionary::from([
-                    (0, Block::default()),
-                    (
-                        1,
-                        Block {
-                            model: cube_model,
-                            transparent: false,
-                            ident: "grass".to_owned(),
-                            uv: [0.0, 0.0],
-                        },
-                    ),
-                    (
-                        2,
-                        Block {
-                            model: cube_model,
-                            transparent: false,
-                            ident: "stone".to_owned(),
-                            uv: [0.0625, 0.0],
-                        },
-                    ),
-                    (
-                        3,
-                        Block {
-                            model: cube_model,
-                            transparent: false,
-                            ident: "dirt".to_owned(),
-                            uv: [0.125, 0.0],
-                        },
-                    ),
-                ]),
+                meshing: meshing::MeshingMode::Greedy,
+                ..ChunkConfig::from_settings(
+                    options.settings.clone(),
+                    dict,
+                    structures,
+                    CHUNK_DEPTH,
+                    options.load_radius,
+                )
+                .unwrap()
             }),
 
             player: Player::new(),
@@ -157,41 +174,53 @@ pub async fn init() -> GameState<GameData, WorldRenderer, Event> {
     let shader_source = load_string("chunk.wgsl", true)
         .await
         .expect("error loading shader... :(");
-    game_state.renderer.chunk_render_pass.render_groups.insert(
-        "chunk_render_group".to_string(),
-        RenderGroupBuilder::new()
-            .with("projection", Matrix::create_layout(0))
-            .with("view", Matrix::create_layout(1))
-            .with("model", Matrix::create_layout(2))
-            .with("texture_atlas", texture::Texture::create_layout(3))
-            .vertex_format(meshing::Vertex::description())
-            .shader(&shader_source)
-            .build(true),
-    );
+    let renderer = &mut game_state.renderer;
+    let device = &renderer.gfx.device;
+    for layer in RenderLayer::ALL {
+        // translucent faces are blended over what is behind them, and don't
+        // hide the faces behind them that are drawn after them
+        let (fragment_entry, blend, depth_write) = match layer {
+            RenderLayer::Opaque => ("fs_main", wgpu::BlendState::REPLACE, true),
+            RenderLayer::Cutout => ("fs_cutout", wgpu::BlendState::REPLACE, true),
+            RenderLayer::Translucent => ("fs_translucent", wgpu::BlendState::ALPHA_BLENDING, false),
+        };
+        renderer.chunk_render_pass.render_groups.insert(
+            render_group_name(layer).to_string(),
+            RenderGroupBuilder::new()
+                .with("projection", Matrix::create_layout(device, 0))
+                .with("view", Matrix::create_layout(device, 1))
+                .with("model", Matrix::create_layout(device, 2))
+                .with("texture_atlas", texture::Texture::create_layout(device, 3))
+                .vertex_format(meshing::Vertex::description())
+                .shader(&shader_source)
+                .fragment_entry(fragment_entry)
+                .blend(blend)
+                .depth_write(depth_write)
+                .build(&renderer.gfx, true),
+        );
+    }
 
-    let texture_uniform = texture::Texture::load("texture_atlas.png").await;
-    game_state.renderer.chunk_render_pass.uniforms.insert(
+    let texture_uniform = texture::Texture::load(&renderer.gfx, "texture_atlas.png").await;
+    renderer.chunk_render_pass.uniforms.insert(
         "texture_atlas".to_string(),
-        texture_uniform.uniform(&texture::Texture::create_layout(3)),
+        texture_uniform.uniform(device, &texture::Texture::create_layout(device, 3)),
     );
 
-    let config = &window_state().config;
     let projection = glam::Mat4::perspective_rh_gl(
         game_state.data.player.fov,
-        config.width as f32 / config.height as f32,
+        renderer.gfx.aspect_ratio(),
         0.1,
         1000.0,
     );
-    let proj = Matrix::new(projection).uniform(&Matrix::create_layout(0));
-    game_state
-        .renderer
+    let proj = Matrix::new(device, projection).uniform(device, &Matrix::create_layout(device, 0));
+    renderer
         .chunk_render_pass
         .uniforms
         .insert("projection".to_string(), proj);
 
-    let camera = Matrix::new(glam::Mat4::IDENTITY).uniform(&Matrix::create_layout(1));
-    game_state
-        .renderer
+    let camera = Matrix::new(device, glam::Mat4::IDENTITY)
+        .uniform(device, &Matrix::create_layout(device, 1));
+    renderer
         .chunk_render_pass
         .uniforms
         .insert("view".to_string(), camera);
@@ -209,16 +238,23 @@ pub async fn init() -> GameState<GameData, WorldRenderer, Event> {
 
     game_state.add_system(Event::Init, load_world);
     game_state.add_system(Event::Tick, player_input);
+    // before focus_window, so the click that focuses doesn't break a block
+    game_state.add_system(Event::Tick, edit_world);
     game_state.add_system(Event::Tick, debug);
     // game_state.add_system(Event::Tick, visibility_cull);
     game_state.add_system(Event::Tick, focus_window);
     game_state.add_system(Event::Tick, toggle_debug_menu);
+    game_state.add_system(Event::Tick, take_screenshot);
+    game_state.add_system(Event::Tick, run_console_commands);
     // game_state.add_system(Event::Tick, cursor_lock);
     game_state.add_system(Event::Tick, simulate_player);
     game_state.add_system(Event::PlayerMoved, update_camera);
     game_state.add_system(Event::PlayerMoved, player_changed_chunk);
 
     game_state.add_system(Event::PlayerChunkChanged, load_world);
+    game_state.add_system(Event::Tick, reload_blocks);
+    game_state.add_system(Event::Tick, flow_fluids);
+    game_state.add_system(Event::Tick, rebuild_dirty_chunks);
     game_state.add_system(Event::Tick, check_done_load_world);
     game_state.add_system(Event::Resized, update_perspective);
     // game_state.add_system(Event::Tick, mesh_chunks);
@@ -255,6 +291,11 @@ fn debug(
                             .unwrap()
                             .translation();
                         ui.text(format!("Player position: {}, {}, {}", pos.x, pos.y, pos.z));
+                        let position = player_to_position(&(pos.x, pos.y, pos.z));
+                        ui.text(format!(
+                            "Biome: {:?}",
+                            biome_at(&game_data.chunk_config, &position)
+                        ));
                         ui.text(format!(
                             "Is player colliding: {}",
                             game_data.physics_engine.is_colliding("player")
@@ -299,6 +340,45 @@ fn debug(
                             game_data.loading.len()
                         ));
                     });
+                ui.window("Capture")
+                    .size([400.0, 100.0], imgui::Condition::FirstUseEver)
+                    .position([0.0, 300.0], imgui::Condition::FirstUseEver)
+                    .build(|| {
+                        let capture = &mut game_data.capture;
+                        if ui.button("Screenshot") {
+                            capture.requested = Some(CaptureSource::Screen);
+                        }
+                        ui.same_line();
+                        if ui.button("World screenshot") {
+                            capture.requested = Some(CaptureSource::World);
+                        }
+                        ui.slider("Capture every N frames", 0, 600, &mut capture.every);
+                        ui.text(format!("Saved to {}", capture.dir.display()));
+                    });
+                ui.window("Render Scale")
+                    .size([400.0, 100.0], imgui::Condition::FirstUseEver)
+                    .position([0.0, 400.0], imgui::Condition::FirstUseEver)
+                    .build(|| {
+                        let scale = &mut game_data.render_scale;
+                        let edit = &mut game_data.downscale_edit;
+                        ui.slider(
+                            "Downscale factor",
+                            1.0,
+                            RenderScale::MAX_DOWNSCALE_FACTOR,
+                            edit,
+                        );
+                        if ui.is_item_deactivated_after_edit() {
+                            scale.downscale_factor = *edit;
+                        }
+                        if ui.button("Native resolution") {
+                            scale.downscale_factor = 1.0;
+                            *edit = 1.0;
+                        }
+                        for filter in UpscaleFilter::ALL {
+                            ui.same_line();
+                            ui.radio_button(filter.name(), &mut scale.filter, filter);
+                        }
+                    });
             },
         ));
     }
@@ -1,57 +1,83 @@
+use winit::dpi::PhysicalSize;
 use winit::event::{Event, VirtualKeyCode};
 
 use crate::chunk::chunk_renderer::ChunkRenderPass;
+use crate::engine::graphics::GraphicsContext;
 use crate::engine::input::Input;
-use crate::engine::render::frame_render_pass::FrameRenderPass;
+use crate::engine::render::capture::{CaptureSource, FrameCapture};
+use crate::engine::render::frame_render_pass::{FrameRenderPass, RenderScale};
 use crate::engine::render::imgui_render_pass::ImguiRenderPass;
 use crate::engine::render::render_pass::{RenderPass, RenderPassViews};
+use crate::engine::render::render_target::RenderTarget;
 use crate::engine::render::renderer::Renderer;
 use crate::engine::texture::Texture;
-use crate::window_state;
 use crate::world::GameData;
 
 pub struct WorldRenderer {
+    pub gfx: GraphicsContext,
+    pub target: RenderTarget,
+
     pub chunk_render_pass: ChunkRenderPass,
     pub imgui_render_pass: ImguiRenderPass<GameData>,
     pub frame_render_pass: FrameRenderPass,
 
-    downscale_factor: u32,
+    pub capture: FrameCapture,
 
     depth_texture: Texture,
 }
 
 impl WorldRenderer {
-    pub fn new(frame_source: &str) -> Self {
-        let device = &window_state().device;
-        let config = &window_state().config;
-        let downscale_factor = 4;
+    /// Create the renderer drawing to the target, the world is drawn smaller
+    /// than the target and scaled up to fill it, as picked by the scale.
+    pub fn new(
+        gfx: GraphicsContext,
+        target: RenderTarget,
+        frame_source: &str,
+        scale: RenderScale,
+    ) -> Self {
+        let frame_render_pass = FrameRenderPass::new(&gfx, scale, frame_source);
         Self {
             chunk_render_pass: ChunkRenderPass::new(),
-            imgui_render_pass: ImguiRenderPass::new(),
-            frame_render_pass: FrameRenderPass::new(downscale_factor, frame_source),
-            downscale_factor,
-            depth_texture: Texture::create_depth_texture(
-                device,
-                config,
-                "depth_texture",
-                config.width / downscale_factor,
-                config.height / downscale_factor,
-            ),
+            imgui_render_pass: ImguiRenderPass::new(&gfx, target.window()),
+            depth_texture: Self::create_depth_texture(&gfx, &frame_render_pass),
+            frame_render_pass,
+            capture: FrameCapture::new(),
+            gfx,
+            target,
+        }
+    }
+
+    /// Create the depth texture the size of the frame pass's render texture.
+    fn create_depth_texture(gfx: &GraphicsContext, frame_render_pass: &FrameRenderPass) -> Texture {
+        let (width, height) = frame_render_pass.render_size(gfx);
+        Texture::create_depth_texture(&gfx.device, &gfx.config, "depth_texture", width, height)
+    }
+
+    /// Change the size the world is drawn at and how it's scaled up, the
+    /// depth and render textures are created again for the new size.
+    pub fn set_render_scale(&mut self, scale: RenderScale) {
+        let resized = scale.downscale_factor != self.frame_render_pass.scale().downscale_factor;
+        self.frame_render_pass.set_scale(&self.gfx, scale);
+        if resized {
+            self.depth_texture = Self::create_depth_texture(&self.gfx, &self.frame_render_pass);
         }
     }
 }
 
 impl Renderer<GameData> for WorldRenderer {
     fn render(&mut self, game_data: &mut GameData, delta: f64) -> Result<(), wgpu::SurfaceError> {
-        let surface = &window_state().surface;
-        let output = surface.get_current_texture()?;
-        let view = output
-            .texture
-            .create_view(&wgpu::TextureViewDescriptor::default());
+        // the scale is changed from the debug menu
+        if game_data.render_scale != self.frame_render_pass.scale() {
+            self.set_render_scale(game_data.render_scale);
+        }
+
+        let frame = self.target.frame()?;
+        let view = &frame.view;
 
         let downscaled_view = self.frame_render_pass.get_render_texture_view().unwrap();
 
         let _ = self.chunk_render_pass.render(
+            &self.gfx,
             game_data,
             RenderPassViews {
                 color: Some(&downscaled_view),
@@ -61,53 +87,56 @@ impl Renderer<GameData> for WorldRenderer {
         )?;
 
         let _ = self.frame_render_pass.render(
+            &self.gfx,
             game_data,
             RenderPassViews {
-                color: Some(&view),
+                color: Some(view),
                 depth: None,
             },
             delta,
         )?;
 
         if game_data.show_debug_menu {
+            self.imgui_render_pass
+                .prepare_frame(&self.gfx, self.target.window());
             let _ = self.imgui_render_pass.render(
+                &self.gfx,
                 game_data,
                 RenderPassViews {
-                    color: Some(&view),
+                    color: Some(view),
                     depth: None,
                 },
                 delta,
             )?;
         }
 
-        // submit the command buffer
-        // window_state()
-        //     .queue
-        //    .submit(std::iter::once(encoder.finish()));
+        if let Some((source, path)) = self.capture.next_capture(&mut game_data.capture) {
+            let texture = match source {
+                CaptureSource::Screen => self.target.frame_texture(&frame),
+                CaptureSource::World => self.frame_render_pass.get_render_texture().unwrap(),
+            };
+            self.capture.start(&self.gfx, texture, path);
+        }
 
-        output.present();
+        frame.present();
+        self.capture.save_finished(&self.gfx);
         Ok(())
     }
 
-    fn resize(&mut self) {
-        let device = &window_state().device;
-        let config = &window_state().config;
-        self.frame_render_pass.resize(self.downscale_factor);
-        self.depth_texture = Texture::create_depth_texture(
-            &device,
-            &config,
-            "depth_texture",
-            config.width / self.downscale_factor,
-            config.height / self.downscale_factor,
-        );
+    fn resize(&mut self, new_size: PhysicalSize<u32>) {
+        self.target.resize(&mut self.gfx, new_size);
+        self.frame_render_pass.resize(&self.gfx);
+        self.depth_texture = Self::create_depth_texture(&self.gfx, &self.frame_render_pass);
     }
 
     fn handle_event(&mut self, event: &Event<()>) {
-        self.imgui_render_pass.platform.handle_event(
-            self.imgui_render_pass.context.io_mut(),
-            &window_state().window,
-            &event,
-        );
+        if let Some(window) = self.target.window() {
+            self.imgui_render_pass.platform.handle_event(
+                self.imgui_render_pass.context.io_mut(),
+                window,
+                event,
+            );
+        }
     }
 }
 
@@ -124,3 +153,163 @@ pub fn toggle_debug_menu(
         data.show_debug_menu = !data.show_debug_menu;
     }
 }
+
+/// System taking a screenshot of the next frame when F2 is pressed, or of the
+/// world before it's scaled up to the screen when F3 is pressed.
+pub fn take_screenshot(
+    _renderer: &mut WorldRenderer,
+    input: &mut Input,
+    data: &mut GameData,
+    _queue: &mut Vec<super::world::Event>,
+    delta: f64,
+) {
+    let pressed = |key| {
+        let key = input.get_key(key);
+        key <= delta && key > 0.0
+    };
+
+    if pressed(VirtualKeyCode::F2) {
+        data.capture.requested = Some(CaptureSource::Screen);
+    } else if pressed(VirtualKeyCode::F3) {
+        data.capture.requested = Some(CaptureSource::World);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::chunk::culling::VisibilityGraph;
+    use crate::chunk::lighting::LightData;
+    use crate::chunk::loading::insert_chunk_mesh;
+    use crate::chunk::meshing::mesh_chunk;
+    use crate::chunk::{ChunkData, ChunkPos, LOD};
+    use crate::cli::GameOptions;
+    use crate::engine::render::frame_render_pass::UpscaleFilter;
+    use crate::engine::render::render_target::{assert_golden, read_texture, OffscreenTarget};
+    use crate::engine::render::uniform::{Uniform, UniformData};
+    use crate::util::test_directory;
+    use crate::world;
+
+    use super::*;
+
+    /// A floor of grass with a stone pillar, a glass wall and a pool of water
+    /// on it, with a block of every render layer.
+    fn scene_chunk(depth: i32) -> ChunkData {
+        let mut chunk = ChunkData::new(depth);
+        for x in 0..24 {
+            for z in 0..24 {
+                for y in 0..5 {
+                    let block_id = match y {
+                        0 | 1 => 2,
+                        2 | 3 => 3,
+                        _ => 1,
+                    };
+                    chunk.set(&(x, y, z), block_id);
+                }
+            }
+        }
+        for y in 5..10 {
+            chunk.set(&(3, y, 8), 2);
+        }
+        for x in 10..15 {
+            for y in 5..7 {
+                chunk.set(&(x, y, 4), 5);
+            }
+        }
+        for x in 12..18 {
+            for z in 12..18 {
+                chunk.set(&(x, 4, z), 7);
+            }
+        }
+        chunk.set(&(4, 5, 12), 4);
+        chunk
+    }
+
+    #[test]
+    fn render_passes_golden_test() {
+        let gfx = GraphicsContext::for_tests(160, 120);
+        let options = GameOptions {
+            load_radius: 1,
+            render_scale: RenderScale {
+                downscale_factor: 2.0,
+                filter: UpscaleFilter::Nearest,
+            },
+            world_dir: test_directory("render-passes-golden"),
+            ..GameOptions::default()
+        };
+        let target = RenderTarget::Offscreen(OffscreenTarget::new(&gfx));
+        let mut game_state = pollster::block_on(world::init(&options, gfx, target));
+        let renderer = &mut game_state.renderer;
+        let data = &mut game_state.data;
+
+        // only the chunk of the scene is loaded, the world isn't generated
+        let config = data.chunk_config.clone();
+        let chunk_pos = ChunkPos::new(0, 0, 0);
+        let chunk = scene_chunk(config.depth);
+        let light = LightData::filled(config.depth, 15, 0);
+        let mesh = mesh_chunk(&chunk, &light, &config, LOD::MAX);
+        insert_chunk_mesh(renderer, &config, chunk_pos, &mesh);
+        data.visibility_graphs
+            .insert(chunk_pos, VisibilityGraph::from_chunk(&config, &chunk));
+        data.loaded_chunks.insert(chunk_pos, chunk);
+
+        // looking down at the scene from above a corner of the chunk
+        if let Some(Uniform {
+            data: UniformData::Matrix(view),
+            ..
+        }) = renderer.chunk_render_pass.uniforms.get_mut("view")
+        {
+            *view.matrix_mut() = glam::Mat4::look_at_rh(
+                glam::vec3(-4.0, 14.0, -4.0),
+                glam::vec3(14.0, 4.0, 14.0),
+                glam::Vec3::Y,
+            );
+            view.update_buffer(&renderer.gfx.queue);
+        }
+
+        renderer.render(data, 16.0).unwrap();
+        let UniformData::Texture(texture) = &renderer.frame_render_pass.render_texture.data else {
+            panic!("The frame pass has no render texture");
+        };
+        let chunks = read_texture(&renderer.gfx, &texture.texture).unwrap();
+        assert_eq!(chunks.dimensions(), (80, 60));
+        assert_golden("chunk_pass", &chunks);
+        assert_golden(
+            "frame_pass",
+            &renderer.target.read_image(&renderer.gfx).unwrap(),
+        );
+
+        // scaled up from a fractional scale with the other filters
+        for filter in [UpscaleFilter::Bilinear, UpscaleFilter::Sharp] {
+            data.render_scale = RenderScale {
+                downscale_factor: 2.5,
+                filter,
+            };
+            renderer.render(data, 16.0).unwrap();
+            assert_eq!(
+                renderer.frame_render_pass.render_size(&renderer.gfx),
+                (64, 48)
+            );
+            assert_golden(
+                &format!("frame_pass_{}", filter),
+                &renderer.target.read_image(&renderer.gfx).unwrap(),
+            );
+        }
+        data.render_scale = options.render_scale;
+
+        data.show_debug_menu = true;
+        renderer.imgui_render_pass.windows.push(Box::new(
+            |ui: &mut imgui::Ui, _: &mut GameData| {
+                ui.window("Golden")
+                    .position([4.0, 4.0], imgui::Condition::Always)
+                    .size([120.0, 48.0], imgui::Condition::Always)
+                    .build(|| ui.text("mcrs"));
+            },
+        ));
+        renderer.render(data, 16.0).unwrap();
+        assert_golden(
+            "imgui_pass",
+            &renderer.target.read_image(&renderer.gfx).unwrap(),
+        );
+        let _ = std::fs::remove_dir_all(&options.world_dir);
+    }
+}
@@ -0,0 +1,265 @@
+//! Generating a region of chunks without a window or GPU, timing every stage,
+//! so world generation can be benchmarked and tested on its own.
+
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, Instant};
+
+use indexmap::IndexMap;
+
+use crate::chunk::block::load_block_dictionary;
+use crate::chunk::collision::calculate_colliders;
+use crate::chunk::culling::VisibilityGraph;
+use crate::chunk::generation::GenerationArea;
+use crate::chunk::lighting::LightData;
+use crate::chunk::meshing::{mesh_chunk, MeshingMode};
+use crate::chunk::settings::WorldGenSettings;
+use crate::chunk::storage::RegionStorage;
+use crate::chunk::structures::load_structures;
+use crate::chunk::{ChunkConfig, ChunkData, ChunkPos, Position, LOD};
+
+/// What to generate and where to write it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RegionOptions {
+    pub settings: WorldGenSettings,
+    /// Size of a chunk in blocks, the game's chunk depth for regions it opens.
+    pub depth: i32,
+    /// Chunk positions of the corners of the region, both are generated.
+    pub from: Position,
+    pub to: Position,
+    /// Folder the chunks are saved to as region files, like a world folder.
+    pub output: Option<PathBuf>,
+}
+
+impl RegionOptions {
+    /// Get the position of every chunk of the region, in order.
+    pub fn chunks(&self) -> Vec<Position> {
+        let range = |axis: fn(&Position) -> i32| {
+            axis(&self.from).min(axis(&self.to))..=axis(&self.from).max(axis(&self.to))
+        };
+        let mut chunks = Vec::new();
+        for x in range(|p| p.0) {
+            for y in range(|p| p.1) {
+                for z in range(|p| p.2) {
+                    chunks.push((x, y, z));
+                }
+            }
+        }
+        chunks
+    }
+}
+
+/// Time spent in each stage of generating the region, in the order the
+/// stages first ran.
+#[derive(Clone, Debug, Default)]
+pub struct StageTimings {
+    stages: IndexMap<&'static str, Duration>,
+}
+
+impl StageTimings {
+    /// Run a stage and add the time it took.
+    pub fn time<T>(&mut self, stage: &'static str, run: impl FnOnce() -> T) -> T {
+        let start = Instant::now();
+        let result = run();
+        *self.stages.entry(stage).or_default() += start.elapsed();
+        result
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'static str, Duration)> + '_ {
+        self.stages.iter().map(|(stage, time)| (*stage, *time))
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages.values().sum()
+    }
+}
+
+/// What came out of generating a region.
+#[derive(Clone, Debug)]
+pub struct RegionReport {
+    pub chunks: usize,
+    /// Blocks that aren't air, inside the chunks.
+    pub blocks: usize,
+    /// Hash of every block of the region, the same for the same settings, so
+    /// runs can be compared.
+    pub checksum: u64,
+    pub timings: StageTimings,
+}
+
+impl fmt::Display for RegionReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "{} chunks, {} blocks, checksum {:016x}",
+            self.chunks, self.blocks, self.checksum
+        )?;
+        writeln!(
+            f,
+            "{:<18} {:>12} {:>12} {:>6}",
+            "stage", "total ms", "chunk ms", "%"
+        )?;
+
+        let total = self.timings.total().as_secs_f64();
+        let chunks = self.chunks.max(1) as f64;
+        for (stage, time) in self.timings.iter() {
+            let time = time.as_secs_f64();
+            writeln!(
+                f,
+                "{:<18} {:>12.3} {:>12.3} {:>6.1}",
+                stage,
+                time * 1000.0,
+                time * 1000.0 / chunks,
+                time / total.max(f64::EPSILON) * 100.0
+            )?;
+        }
+        write!(
+            f,
+            "{:<18} {:>12.3} {:>12.3}",
+            "total",
+            total * 1000.0,
+            total * 1000.0 / chunks
+        )
+    }
+}
+
+impl RegionReport {
+    /// Count the blocks inside a generated chunk and hash them into the
+    /// checksum, with 64 bit FNV-1a.
+    fn add_chunk(&mut self, config: &ChunkConfig, chunk: &ChunkData) {
+        self.chunks += 1;
+        for x in 0..config.depth {
+            for y in 0..config.depth {
+                for z in 0..config.depth {
+                    let block_id = chunk.get(&(x, y, z));
+                    if block_id != 0 {
+                        self.blocks += 1;
+                    }
+                    for byte in block_id.to_le_bytes() {
+                        self.checksum ^= byte as u64;
+                        self.checksum = self.checksum.wrapping_mul(0x0000_0100_0000_01b3);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Generate every chunk of the region with the config, and build what the
+/// game builds for each chunk, its mesh, colliders and visibility graph.
+pub fn generate_region_with(
+    config: &ChunkConfig,
+    options: &RegionOptions,
+) -> anyhow::Result<RegionReport> {
+    let storage = options.output.as_ref().map(RegionStorage::new);
+    let light = LightData::filled(config.depth, 15, 0);
+
+    let mut report = RegionReport {
+        chunks: 0,
+        blocks: 0,
+        checksum: 0xcbf2_9ce4_8422_2325,
+        timings: StageTimings::default(),
+    };
+    for position in options.chunks() {
+        let pos = ChunkPos::from(position);
+        let timings = &mut report.timings;
+
+        let mut chunk = ChunkData::new(config.depth);
+        for pass in config.passes.iter() {
+            timings.time(pass.name(), || {
+                pass.generate(config, &pos, GenerationArea::Chunk, &mut chunk)
+            });
+        }
+
+        timings.time("mesh", || mesh_chunk(&chunk, &light, config, LOD::MAX));
+        timings.time("collider", || calculate_colliders(&chunk, &pos, config));
+        timings.time("visibility graph", || {
+            VisibilityGraph::from_chunk(config, &chunk)
+        });
+        if let Some(storage) = &storage {
+            timings.time("export", || storage.save(config, &pos, &chunk))?;
+        }
+
+        report.add_chunk(config, &chunk);
+    }
+
+    Ok(report)
+}
+
+/// Load the blocks and structures from the assets folder, and generate the
+/// region with them.
+pub fn generate_region(options: &RegionOptions) -> anyhow::Result<RegionReport> {
+    let dict = pollster::block_on(load_block_dictionary(0.0625))?;
+    let structures = pollster::block_on(load_structures(&dict))?;
+    // meshed the way the game meshes chunks
+    let config = ChunkConfig {
+        meshing: MeshingMode::Greedy,
+        ..ChunkConfig::from_settings(options.settings.clone(), dict, structures, options.depth, 0)?
+    };
+    generate_region_with(&config, options)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::util::test_directory;
+
+    fn create_mock_config(seed: u32) -> ChunkConfig {
+        ChunkConfig::new(seed, 16, 3)
+    }
+
+    fn create_options(output: Option<PathBuf>) -> RegionOptions {
+        RegionOptions {
+            settings: WorldGenSettings::default(),
+            depth: 16,
+            from: (1, 0, 1),
+            to: (0, -1, 0),
+            output,
+        }
+    }
+
+    #[test]
+    fn region_test() -> anyhow::Result<()> {
+        let config = create_mock_config(10);
+        let options = create_options(None);
+        assert_eq!(options.chunks().len(), 8);
+        assert_eq!(options.chunks()[0], (0, -1, 0));
+
+        let report = generate_region_with(&config, &options)?;
+        assert_eq!(report.chunks, 8);
+        assert!(report.blocks > 0 && report.blocks < 8 * 16 * 16 * 16);
+
+        // every pass is timed on its own, in order
+        let stages: Vec<&str> = report.timings.iter().map(|(stage, _)| stage).collect();
+        let mut expected: Vec<&str> = config.passes.iter().map(|pass| pass.name()).collect();
+        expected.extend(["mesh", "collider", "visibility graph"]);
+        assert_eq!(stages, expected);
+        assert!(report.to_string().contains("visibility graph"));
+
+        // the same blocks every run, and other blocks for another seed
+        let again = generate_region_with(&config, &options)?;
+        assert_eq!(again.checksum, report.checksum);
+        let other = generate_region_with(&create_mock_config(11), &options)?;
+        assert_ne!(other.checksum, report.checksum);
+        Ok(())
+    }
+
+    #[test]
+    fn export_test() -> anyhow::Result<()> {
+        let config = create_mock_config(10);
+        let output = test_directory("export");
+        let options = create_options(Some(output.clone()));
+        let report = generate_region_with(&config, &options)?;
+        assert!(report.timings.iter().any(|(stage, _)| stage == "export"));
+
+        // the exported chunks load back the same as they were generated
+        let storage = RegionStorage::new(&output);
+        for position in options.chunks() {
+            let pos = ChunkPos::from(position);
+            let mut generated = crate::chunk::generation::generate(&config, &pos);
+            generated.clear_border();
+            assert_eq!(storage.load(&config, &pos)?, Some(generated));
+        }
+        std::fs::remove_dir_all(&output)?;
+        Ok(())
+    }
+}